@@ -0,0 +1,114 @@
+#[macro_use]
+extern crate criterion;
+extern crate chip8_emulator;
+
+use std::hint::black_box;
+
+use criterion::{Criterion, Throughput};
+
+use chip8_emulator::chip8::{Chip8Builder, Engine};
+use chip8_emulator::bus::EventBus;
+use chip8_emulator::command::{Command, CommandEmulator, Topic, AudioCommand, DisplayCommand, KeyCommand::KeyDownUp};
+use chip8_emulator::emulator::Chip8Emulator;
+use chip8_emulator::io::HeadlessIO;
+use chip8_emulator::opcode::{Opcode, OpcodeDisassembler, Operation::DRW, OpcodeType::XYN};
+use chip8_emulator::rng::SeededRandom;
+
+const ROUTED_COMMANDS: u64 = 64;
+const CPU_CYCLES: u64 = 1000;
+
+fn disassemble(c: &mut Criterion) {
+    let mut group = c.benchmark_group("disassemble");
+    group.throughput(Throughput::Elements(0x10000));
+
+    group.bench_function("all_instructions", |b| b.iter(|| {
+        (0..=0xFFFFu16).for_each(|instruction|
+            { black_box(OpcodeDisassembler::disassemble(black_box(instruction))); })
+    }));
+    group.finish();
+}
+
+fn draw(c: &mut Criterion) {
+    let sprite = [0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF,
+        0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81];
+
+    // 15 row sprite straddling the bottom right corner, so it wraps both ways
+    c.bench_function("drw/15_rows_wrapped", |b| {
+        let mut chip8 = Chip8Builder::new()
+            .i(0x300)
+            .memory(0x300, &sprite)
+            .register(1, 60)
+            .register(2, 25)
+            .build();
+        b.iter(|| chip8.execute_opcode(Opcode(DRW, XYN(1, 2, 15))))
+    });
+}
+
+// Register arithmetic in a tight loop, so each cycle is mostly fetch and decode
+fn interpreter(c: &mut Criterion) {
+    let program = [
+        0x70, 0x01, // ADD V0, 1
+        0x81, 0x04, // ADD V1, V0
+        0x82, 0x13, // XOR V2, V1
+        0x83, 0x2E, // SHL V3, V2
+        0x30, 0x00, // SE V0, 0
+        0x12, 0x00, // JP 0x200
+        0x00, 0xE0, // CLS
+    ];
+    let mut group = c.benchmark_group("interpreter");
+    group.throughput(Throughput::Elements(CPU_CYCLES));
+
+    for &(name, engine) in [("alu_loop", Engine::Interpreter), ("alu_loop_translated", Engine::Translated)].iter() {
+        group.bench_function(name, |b| {
+            let mut chip8 = Chip8Builder::new().engine(engine).memory(0x200, &program).build();
+            b.iter(|| {
+                (0..CPU_CYCLES).for_each(|_| chip8.emulate_cycle());
+                black_box(chip8.get_commands().consume_all_outbound())
+            })
+        });
+    }
+
+    // Whole blocks per dispatch, against the interpreter run the same way
+    for &(name, engine) in [("alu_loop_run", Engine::Interpreter), ("alu_loop_translated_run", Engine::Translated)].iter() {
+        group.bench_function(name, |b| {
+            let mut chip8 = Chip8Builder::new().engine(engine).memory(0x200, &program).build();
+            b.iter(|| {
+                chip8.run_cycles(CPU_CYCLES);
+                black_box(chip8.get_commands().consume_all_outbound())
+            })
+        });
+    }
+    group.finish();
+}
+
+fn routing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("routing");
+    group.throughput(Throughput::Elements(ROUTED_COMMANDS));
+
+    group.bench_function("bus_publish", |b| {
+        let mut bus = EventBus::new();
+        let (publisher, subscriber) = (bus.register("publisher"), bus.register("subscriber"));
+        [Topic::Key, Topic::Audio, Topic::Display].iter().for_each(|&topic| bus.subscribe(subscriber, topic));
+        b.iter(|| {
+            (0..ROUTED_COMMANDS).for_each(|i| bus.publish(publisher, match i % 3 {
+                0 => Command::Key(KeyDownUp((i % 16) as usize, true)),
+                1 => Command::Audio(AudioCommand::Pause),
+                _ => Command::Display(DisplayCommand::SendDraw)
+            }));
+            black_box(bus.take(subscriber))
+        })
+    });
+    group.finish();
+
+    // JP 0x200 keeps the CPU busy with almost nothing, so a frame is mostly routing
+    c.bench_function("routing/idle_frame", |b| {
+        let mut emulator = Chip8Emulator::new(
+            Box::new(HeadlessIO::new()),
+            Box::new(SeededRandom::new(0)));
+        emulator.load_game(&[0x12, 0x00]).unwrap();
+        b.iter(|| emulator.run_frame())
+    });
+}
+
+criterion_group!(benches, disassemble, draw, interpreter, routing);
+criterion_main!(benches);
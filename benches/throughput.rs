@@ -0,0 +1,58 @@
+#[macro_use]
+extern crate criterion;
+extern crate chip8_emulator;
+
+use std::fs;
+
+use criterion::{Criterion, Throughput};
+
+use chip8_emulator::chip8::Engine;
+use chip8_emulator::emulator::Chip8Emulator;
+use chip8_emulator::io::HeadlessIO;
+use chip8_emulator::rng::SeededRandom;
+
+const CYCLES: u64 = 10_000;
+const ROMS: [&str; 3] = ["PONG", "BRIX", "INVADERS"];
+
+fn headless_emulator(rom_name: &str, engine: Engine) -> Chip8Emulator {
+    let rom_bytes = fs::read(format!("{}/ROMs/{}", env!("CARGO_MANIFEST_DIR"), rom_name))
+        .expect("ROM should be readable");
+    let mut emulator = Chip8Emulator::new(
+        Box::new(HeadlessIO::new()),
+        Box::new(SeededRandom::new(0)));
+    emulator.set_engine(engine);
+    emulator.load_game(&rom_bytes).unwrap();
+    emulator
+}
+
+fn headless_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("headless");
+    group.throughput(Throughput::Elements(CYCLES));
+
+    for rom_name in ROMS.iter() {
+        group.bench_function(*rom_name, |b| {
+            let mut emulator = headless_emulator(rom_name, Engine::Interpreter);
+            b.iter(|| (0..CYCLES).for_each(|_| emulator.run_frame()))
+        });
+    }
+    group.finish();
+}
+
+// The same ROMs run in bulk, as the emulator does a video frame, on each engine
+fn engine_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engines");
+    group.throughput(Throughput::Elements(CYCLES));
+
+    for rom_name in ROMS.iter() {
+        for &(suffix, engine) in [("", Engine::Interpreter), ("_translated", Engine::Translated)].iter() {
+            group.bench_function(format!("{}{}", rom_name, suffix), |b| {
+                let mut emulator = headless_emulator(rom_name, engine);
+                b.iter(|| emulator.run_cycles(CYCLES))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, headless_throughput, engine_throughput);
+criterion_main!(benches);
@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::iter::successors;
+
+use byteorder::{ByteOrder, BigEndian};
+
+use constants::ROM_ADDR;
+use library::{Platform, Quirk, detect_platform, extension};
+use movie::rom_hash;
+use opcode::{Opcode, OpcodeDisassembler, Operation::*, OpcodeType::*};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction {
+    pub addr: usize,
+    pub word: u16,
+    pub opcode: Opcode
+}
+
+// Code reachable from the start of the ROM, and the subroutines each one calls
+pub struct ControlFlow {
+    pub code: BTreeMap<usize, Instruction>,
+    pub calls: BTreeMap<usize, BTreeSet<usize>>
+}
+
+/*
+Follows the program from its start through jumps, calls and skips. A return
+or a jump by V0 ends a path, as do instructions no platform defines. Platform
+extensions are stepped over, except SCHIP's exit. Sprite data is never reached
+this way, so it isn't mistaken for code.
+*/
+pub fn trace(rom_bytes: &[u8]) -> ControlFlow {
+    let end = ROM_ADDR + rom_bytes.len();
+    let mut flow = ControlFlow { code: BTreeMap::new(), calls: BTreeMap::new() };
+    let mut subroutines = vec![ROM_ADDR];
+
+    while let Some(subroutine) = subroutines.pop() {
+        if flow.calls.contains_key(&subroutine) { continue }
+        let mut callees = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![subroutine];
+
+        while let Some(addr) = pending.pop() {
+            if addr < ROM_ADDR || addr + 1 >= end || !visited.insert(addr) { continue }
+
+            let word = BigEndian::read_u16(&rom_bytes[addr - ROM_ADDR..addr - ROM_ADDR + 2]);
+            let opcode = OpcodeDisassembler::disassemble(word);
+            flow.code.insert(addr, Instruction { addr, word, opcode });
+
+            match opcode {
+                Opcode(RET, _) | Opcode(JP, V0_NNN(_)) => {},
+                Opcode(JP, NNN(nnn)) => pending.push(nnn as usize),
+                Opcode(CALL, NNN(nnn)) => {
+                    callees.insert(nnn as usize);
+                    subroutines.push(nnn as usize);
+                    pending.push(addr + 2)
+                },
+                Opcode(SE, _) | Opcode(SNE, _) | Opcode(SKP, _) | Opcode(SKNP, _) => pending.extend(&[addr + 2, addr + 4]),
+                Opcode(SYS, _) | Opcode(UNDEFINED, _) => match word {
+                    0x00FD => {},
+                    // Long I is followed by its address
+                    0xF000 => pending.push(addr + 4),
+                    _ if extension(word).is_some() => pending.push(addr + 2),
+                    _ => {}
+                },
+                _ => pending.push(addr + 2)
+            }
+        }
+        flow.calls.insert(subroutine, callees);
+    }
+    flow
+}
+
+/*
+Static report on a ROM for triaging it before it's added to the library. Only
+code reachable from the start is looked at. Register values are followed back
+through the straight-line code before an instruction, so keys and writes set
+up elsewhere are counted as unknown.
+*/
+pub struct Analysis {
+    pub size: usize,
+    pub rom_hash: String,
+    pub instructions: usize,
+    // Count of each instruction form, most used first
+    pub histogram: Vec<(String, usize)>,
+    pub platform: Platform,
+    // Platform of every aligned word, sprite data included
+    pub scanned_platform: Platform,
+    pub extensions: Vec<(usize, Platform, &'static str)>,
+    pub quirks: Vec<(Quirk, usize)>,
+    pub calls: BTreeMap<usize, BTreeSet<usize>>,
+    pub keys: BTreeSet<u16>,
+    pub unknown_key_tests: Vec<usize>,
+    pub key_waits: Vec<usize>,
+    pub undefined: Vec<usize>,
+    // Address of the write and of the instruction it overwrites
+    pub self_modifying: Vec<(usize, usize)>
+}
+
+impl Analysis {
+    pub fn of(rom_bytes: &[u8]) -> Self {
+        let flow = trace(rom_bytes);
+        let mut analysis = Analysis {
+            size: rom_bytes.len(),
+            rom_hash: rom_hash(rom_bytes),
+            instructions: flow.code.len(),
+            histogram: Vec::new(),
+            platform: Platform::Chip8,
+            scanned_platform: detect_platform(rom_bytes),
+            extensions: Vec::new(),
+            quirks: Vec::new(),
+            calls: flow.calls.clone(),
+            keys: BTreeSet::new(),
+            unknown_key_tests: Vec::new(),
+            key_waits: Vec::new(),
+            undefined: Vec::new(),
+            self_modifying: Vec::new()
+        };
+
+        let mut histogram = HashMap::new();
+        for instruction in flow.code.values() {
+            let addr = instruction.addr;
+            let form = match (instruction.opcode, extension(instruction.word)) {
+                (_, Some((platform, name))) => {
+                    analysis.extensions.push((addr, platform, name));
+                    analysis.platform = analysis.platform.max(platform);
+                    format!("{} ({})", name, platform)
+                },
+                (Opcode(UNDEFINED, _), None) => {
+                    analysis.undefined.push(addr);
+                    "undefined".to_string()
+                },
+                (opcode, _) => form(opcode)
+            };
+            *histogram.entry(form).or_insert(0) += 1;
+
+            match instruction.opcode {
+                Opcode(SHR, _) | Opcode(SHL, _) if instruction.word >> 8 & 0xF != instruction.word >> 4 & 0xF =>
+                    analysis.quirks.push((Quirk::ShiftVy, addr)),
+                Opcode(OR, _) | Opcode(AND, _) | Opcode(XOR, _) if reads_vf_after(&flow, addr) =>
+                    analysis.quirks.push((Quirk::VfReset, addr)),
+                Opcode(JP, V0_NNN(nnn)) if nnn >> 8 != 0 =>
+                    analysis.quirks.push((Quirk::JumpVx, addr)),
+                Opcode(LD, RI_X(_)) | Opcode(LD, X_RI(_)) if reads_i_after(&flow, addr) =>
+                    analysis.quirks.push((Quirk::LoadStoreI, addr)),
+                _ => {}
+            }
+
+            match instruction.opcode {
+                Opcode(SKP, X(x)) | Opcode(SKNP, X(x)) => match register_constant(&flow, addr, x) {
+                    Some(key) => { analysis.keys.insert(key & 0xF); },
+                    None => analysis.unknown_key_tests.push(addr)
+                },
+                Opcode(LD, X_K(_)) => analysis.key_waits.push(addr),
+                _ => {}
+            }
+
+            let written = match instruction.opcode {
+                Opcode(LD, RI_X(x)) => Some(x as usize + 1),
+                Opcode(LD, B_X(_)) => Some(3),
+                _ => None
+            };
+            if let (Some(len), Some(start)) = (written, i_constant(&flow, addr)) {
+                let overwritten = flow.code.keys()
+                    .find(|&&code_addr| code_addr + 1 >= start && code_addr < start + len);
+                if let Some(&code_addr) = overwritten {
+                    analysis.self_modifying.push((addr, code_addr))
+                }
+            }
+        }
+
+        analysis.histogram = histogram.into_iter().collect();
+        analysis.histogram.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        analysis
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Size: {} bytes, SHA-1 {}", self.size, self.rom_hash)?;
+        writeln!(f, "Reachable code: {} instructions", self.instructions)?;
+
+        write!(f, "Platform: {}", self.platform)?;
+        if self.scanned_platform != self.platform {
+            write!(f, " ({} instructions outside the reachable code)", self.scanned_platform)?
+        }
+        writeln!(f)?;
+        writeln!(f, "Extensions: {}", list(self.extensions.iter()
+            .map(|(addr, platform, name)| format!("{} ({}) at {:03X}", name, platform, addr))))?;
+        writeln!(f, "Undefined instructions: {}", list(self.undefined.iter().map(|addr| format!("{:03X}", addr))))?;
+
+        writeln!(f, "Quirks: {}", list(self.quirks.iter()
+            .map(|(quirk, addr)| format!("{} at {:03X}", quirk.name(), addr))))?;
+
+        let mut keys = self.keys.iter().map(|key| format!("{:X}", key)).collect::<Vec<String>>();
+        keys.extend(self.unknown_key_tests.iter().map(|addr| format!("unknown at {:03X}", addr)));
+        keys.extend(self.key_waits.iter().map(|addr| format!("waits for any at {:03X}", addr)));
+        writeln!(f, "Keys: {}", list(keys.into_iter()))?;
+
+        writeln!(f, "Self-modifying code: {}", list(self.self_modifying.iter()
+            .map(|(addr, code_addr)| format!("{:03X} writes over {:03X}", addr, code_addr))))?;
+
+        writeln!(f, "Call graph:")?;
+        for (subroutine, callees) in &self.calls {
+            writeln!(f, "  {:03X} -> {}", subroutine, list(callees.iter().map(|addr| format!("{:03X}", addr))))?
+        }
+
+        writeln!(f, "Instructions:")?;
+        for (form, count) in &self.histogram {
+            writeln!(f, "  {:>5}  {}", count, form)?
+        }
+        Ok(())
+    }
+}
+
+// e.g. "LD Vx, nn" for 6XNN
+pub fn form(opcode: Opcode) -> String {
+    let operands = match opcode.1 {
+        NONE => "",
+        NNN(_) => "nnn",
+        XNN(..) => "Vx, nn",
+        XY(..) => "Vx, Vy",
+        XYN(..) => "Vx, Vy, n",
+        X(_) => "Vx",
+        I_X(_) => "I, Vx",
+        I_NNN(_) => "I, nnn",
+        V0_NNN(_) => "V0, nnn",
+        X_K(_) => "Vx, K",
+        DT_X(_) => "DT, Vx",
+        X_DT(_) => "Vx, DT",
+        F_X(_) => "F, Vx",
+        B_X(_) => "B, Vx",
+        ST_X(_) => "ST, Vx",
+        RI_X(_) => "[I], Vx",
+        X_RI(_) => "Vx, [I]"
+    };
+    format!("{:?} {}", opcode.0, operands).trim().to_string()
+}
+
+// Instructions running straight into addr, nearest first. Control can't be
+// followed back past a jump, a return or a call.
+fn straight_line_before(flow: &ControlFlow, addr: usize) -> impl Iterator<Item = &Instruction> {
+    successors(addr.checked_sub(2), |addr| addr.checked_sub(2))
+        .map_while(move |addr| flow.code.get(&addr))
+        .take_while(|instruction| !ends_straight_line(instruction.opcode))
+}
+
+// Instructions run after addr until control leaves the straight line
+fn straight_line_after(flow: &ControlFlow, addr: usize) -> impl Iterator<Item = &Instruction> {
+    successors(Some(addr + 2), |addr| Some(addr + 2))
+        .map_while(move |addr| flow.code.get(&addr))
+        .take_while(|instruction| !ends_straight_line(instruction.opcode))
+}
+
+fn ends_straight_line(opcode: Opcode) -> bool {
+    matches!(opcode, Opcode(JP, _) | Opcode(RET, _) | Opcode(CALL, _) | Opcode(UNDEFINED, _))
+}
+
+// The value LD Vx, nn last gave the register before addr
+fn register_constant(flow: &ControlFlow, addr: usize, register: u16) -> Option<u16> {
+    straight_line_before(flow, addr)
+        .find(|instruction| writes_register(instruction.opcode, register))
+        .and_then(|instruction| match instruction.opcode {
+            Opcode(LD, XNN(x, nn)) if x == register => Some(nn),
+            _ => None
+        })
+}
+
+// The value LD I, nnn last gave I before addr
+fn i_constant(flow: &ControlFlow, addr: usize) -> Option<usize> {
+    straight_line_before(flow, addr)
+        .find(|instruction| writes_i(instruction.opcode))
+        .and_then(|instruction| match instruction.opcode {
+            Opcode(LD, I_NNN(nnn)) => Some(nnn as usize),
+            _ => None
+        })
+}
+
+// Whether I is read after LD [I] or LD Vx, [I] before anything sets it again
+fn reads_i_after(flow: &ControlFlow, addr: usize) -> bool {
+    straight_line_after(flow, addr)
+        .find(|instruction| reads_i(instruction.opcode) || writes_i(instruction.opcode))
+        .is_some_and(|instruction| reads_i(instruction.opcode))
+}
+
+// Whether VF is read after a logic op before anything sets it again
+fn reads_vf_after(flow: &ControlFlow, addr: usize) -> bool {
+    straight_line_after(flow, addr)
+        .find(|instruction| reads_register(instruction.opcode, 0xF) || writes_register(instruction.opcode, 0xF))
+        .is_some_and(|instruction| reads_register(instruction.opcode, 0xF))
+}
+
+fn writes_register(opcode: Opcode, register: u16) -> bool {
+    match opcode {
+        Opcode(LD, XNN(x, _)) | Opcode(ADD, XNN(x, _)) | Opcode(RND, XNN(x, _))
+        | Opcode(LD, X_DT(x)) | Opcode(LD, X_K(x)) => x == register,
+        Opcode(LD, XY(x, _)) | Opcode(OR, XY(x, _)) | Opcode(AND, XY(x, _)) | Opcode(XOR, XY(x, _)) => x == register,
+        // These also set VF
+        Opcode(ADD, XY(x, _)) | Opcode(SUB, XY(x, _)) | Opcode(SUBN, XY(x, _))
+        | Opcode(SHR, XY(x, _)) | Opcode(SHL, XY(x, _)) => x == register || register == 0xF,
+        Opcode(DRW, _) => register == 0xF,
+        Opcode(LD, X_RI(x)) => register <= x,
+        _ => false
+    }
+}
+
+fn reads_register(opcode: Opcode, register: u16) -> bool {
+    match opcode {
+        // Only written
+        Opcode(LD, XNN(..)) | Opcode(RND, XNN(..)) | Opcode(LD, X_DT(_)) | Opcode(LD, X_K(_)) | Opcode(LD, X_RI(_)) => false,
+        Opcode(LD, XY(_, y)) => y == register,
+        Opcode(_, XNN(x, _)) | Opcode(_, X(x)) | Opcode(_, I_X(x)) | Opcode(_, DT_X(x)) | Opcode(_, F_X(x))
+        | Opcode(_, B_X(x)) | Opcode(_, ST_X(x)) => x == register,
+        Opcode(_, XY(x, y)) | Opcode(_, XYN(x, y, _)) => x == register || y == register,
+        Opcode(_, RI_X(x)) => register <= x,
+        Opcode(_, V0_NNN(_)) => register == 0,
+        _ => false
+    }
+}
+
+fn writes_i(opcode: Opcode) -> bool {
+    matches!(opcode, Opcode(LD, I_NNN(_)) | Opcode(LD, F_X(_)) | Opcode(ADD, I_X(_)))
+}
+
+fn reads_i(opcode: Opcode) -> bool {
+    matches!(opcode, Opcode(DRW, _) | Opcode(LD, B_X(_)) | Opcode(LD, RI_X(_)) | Opcode(LD, X_RI(_)) | Opcode(ADD, I_X(_)))
+}
+
+fn list<I: Iterator<Item = String>>(items: I) -> String {
+    let items = items.collect::<Vec<String>>();
+    if items.is_empty() { "none".to_string() } else { items.join(", ") }
+}
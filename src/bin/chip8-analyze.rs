@@ -0,0 +1,57 @@
+/*
+Prints a static analysis report for each ROM given, for triaging new ROMs
+before they're added to the ROM database.
+*/
+
+extern crate chip8_emulator;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use chip8_emulator::analysis::Analysis;
+use chip8_emulator::library::Library;
+
+const USAGE: &str = "Usage: chip8-analyze [--rom-db <file>] <rom>...";
+
+fn main() {
+    let mut library = Library::bundled();
+    let mut rom_paths = Vec::new();
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rom-db" => {
+                let path = args.next().unwrap_or_else(|| exit_with(USAGE));
+                library.merge(Library::load(&path).unwrap_or_else(|e|
+                    exit_with(&format!("Failed to load ROM database '{}': {}", path, e))))
+            },
+            _ if arg.starts_with("--") => exit_with(&format!("Unknown argument '{}'.\n{}", arg, USAGE)),
+            _ => rom_paths.push(arg)
+        }
+    }
+    if rom_paths.is_empty() { exit_with(USAGE) }
+
+    let mut is_failed = false;
+    for path in &rom_paths {
+        println!("== {} ==", path);
+        match fs::read(path) {
+            Ok(ref rom_bytes) if rom_bytes.is_empty() => { println!("The ROM is empty"); is_failed = true },
+            Ok(rom_bytes) => {
+                if let Some(info) = library.lookup(&rom_bytes) {
+                    println!("Known ROM: {} [{}]", info.credits(), info.platform)
+                }
+                print!("{}", Analysis::of(&rom_bytes))
+            },
+            Err(e) => { println!("Failed to read ROM: {}", e); is_failed = true }
+        }
+        println!()
+    }
+
+    if is_failed { process::exit(1) }
+}
+
+fn exit_with(message: &str) -> ! {
+    println!("{}", message);
+    process::exit(1)
+}
@@ -0,0 +1,79 @@
+/*
+Debug adapter for editors that speak the Debug Adapter Protocol, over stdin
+and stdout. The client launches a ROM, which plays in a window as usual.
+Stdout carries the protocol, so problems are reported to the client.
+*/
+
+extern crate chip8_emulator;
+extern crate serde_json;
+
+use std::io::{self, BufReader};
+use std::sync::mpsc::channel;
+use std::thread;
+
+use serde_json::json;
+
+use chip8_emulator::dap::{DapSession, LaunchArgs, wait_for_launch, forward_requests, send_messages,
+    response, error_response, event};
+use chip8_emulator::emulator::Chip8Emulator;
+use chip8_emulator::io::{IO, RemoteIO};
+use chip8_emulator::library::Platform;
+use chip8_emulator::pacing::{CYCLES_PER_SECOND, FAST_FORWARD_MULTIPLIER};
+use chip8_emulator::rng::from_seed;
+use chip8_emulator::rom_files::RomSource;
+use chip8_emulator::rom_loader::{load_rom, CheckedRom};
+use chip8_emulator::router::ChannelRouter;
+use chip8_emulator::source_map::SourceMap;
+
+fn main() {
+    let (request_sender, requests) = channel();
+    let (messages, message_receiver) = channel();
+    thread::spawn(move || forward_requests(BufReader::new(io::stdin()), request_sender));
+    let writer_thread = thread::spawn(move || send_messages(message_receiver, io::stdout()));
+
+    if let Some((launch_request, launch)) = wait_for_launch(&requests, &messages) {
+        match load(&launch) {
+            Ok((rom, source_map)) => {
+                let _ = messages.send(response(&launch_request, json!({})));
+                for warning in &rom.warnings {
+                    let _ = messages.send(event("output", json!({ "category": "console", "output": format!("Warning: {}\n", warning) })));
+                }
+                // Breakpoints are set after this, the session then waits for configurationDone
+                let _ = messages.send(event("initialized", json!({})));
+
+                let mut io = IO::new();
+                let (emulator_link, mut front_end_link) = ChannelRouter::pair();
+                let session = DapSession::new(requests, messages.clone(), source_map, launch.stop_on_entry);
+                let emulator_thread = thread::spawn(move || {
+                    let mut emulator = Chip8Emulator::new(Box::new(RemoteIO::new(emulator_link)), from_seed(None));
+                    emulator.set_pacing(CYCLES_PER_SECOND, FAST_FORWARD_MULTIPLIER);
+                    emulator.attach_debugger(Box::new(session));
+                    emulator.load_game(&rom.bytes).expect("The ROM was checked");
+                    emulator.play(&rom.bytes)
+                });
+
+                io.run_front_end(&mut front_end_link);
+                drop(front_end_link);
+                emulator_thread.join().expect("Emulator thread panicked")
+            },
+            Err(message) => {
+                let _ = messages.send(error_response(&launch_request, &message));
+                let _ = messages.send(event("terminated", json!({})));
+            }
+        }
+    }
+
+    drop(messages);
+    let _ = writer_thread.join();
+}
+
+fn load(launch: &LaunchArgs) -> Result<(CheckedRom, Option<SourceMap>), String> {
+    let rom = load_rom(&RomSource::File(launch.program.clone().into()), Platform::Chip8)
+        .map_err(|e| format!("Failed to load ROM '{}': {}", launch.program, e))?;
+    let source_map = match launch.source_map {
+        Some(ref path) => Some(SourceMap::load(path)
+            .map_err(|e| format!("Failed to load source map '{}': {}", path, e))?),
+        None => None
+    };
+    Ok((rom, source_map))
+}
@@ -0,0 +1,97 @@
+use std::mem;
+use std::vec::Drain;
+
+use command::{Command, Topic};
+
+pub type ComponentId = usize;
+
+// One subscriber list per Topic variant
+const TOPICS: usize = 5;
+
+pub type Tracer = Box<dyn FnMut(&'static str, &Command)>;
+
+/*
+Publish/subscribe delivery of commands between components.
+
+Components subscribe to topics and receive every command published on them,
+except the ones they published themselves. Each subscriber receives commands
+in the order they were published.
+*/
+pub struct EventBus {
+    names: Vec<&'static str>,
+    subscribers: [Vec<ComponentId>; TOPICS],
+    mailboxes: Vec<Vec<Command>>,
+    tracer: Option<Tracer>
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus {
+            names: Vec::new(),
+            subscribers: Default::default(),
+            mailboxes: Vec::new(),
+            tracer: None
+        }
+    }
+
+    // Ids are handed out in registration order, starting at 0
+    pub fn register(&mut self, name: &'static str) -> ComponentId {
+        self.names.push(name);
+        self.mailboxes.push(Vec::new());
+        self.names.len() - 1
+    }
+
+    // Subscribing again to a topic changes nothing, each command is still delivered once
+    pub fn subscribe(&mut self, id: ComponentId, topic: Topic) {
+        let subscribers = &mut self.subscribers[topic as usize];
+        if !subscribers.contains(&id) { subscribers.push(id) }
+    }
+
+    pub fn unsubscribe(&mut self, id: ComponentId, topic: Topic) {
+        self.subscribers[topic as usize].retain(|&subscriber| subscriber != id)
+    }
+
+    pub fn name(&self, id: ComponentId) -> &'static str {
+        self.names[id]
+    }
+
+    // Called with the publisher's name and every command published from now on
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer)
+    }
+
+    pub fn publish(&mut self, publisher: ComponentId, command: Command) {
+        if let Some(ref mut tracer) = self.tracer {
+            tracer(self.names[publisher], &command)
+        }
+
+        let mut subscribers = self.subscribers[command.topic() as usize].iter()
+            .cloned()
+            .filter(|&id| id != publisher)
+            .peekable();
+
+        // Only extra subscribers pay for a copy of the command
+        while let Some(id) = subscribers.next() {
+            if subscribers.peek().is_none() {
+                self.mailboxes[id].push(command);
+                return
+            }
+            self.mailboxes[id].push(command.clone())
+        }
+    }
+
+    pub fn take(&mut self, id: ComponentId) -> Vec<Command> {
+        mem::take(&mut self.mailboxes[id])
+    }
+
+    // Like take, but the mailbox keeps its capacity for the next frame
+    pub fn drain(&mut self, id: ComponentId) -> Drain<'_, Command> {
+        self.mailboxes[id].drain(..)
+    }
+}
@@ -0,0 +1,172 @@
+/*
+Cheat file format (plain text, one block per ROM, like the ROM database):
+[<sha1>]                 - SHA-1 of the ROM bytes, starts the ROM's block
+cheat <name>             - starts a cheat code
+freeze <target> <value>  - the cheat keeps the target at the value, in hex.
+                           Targets are memory addresses, e.g. 2f0, or
+                           registers, v0-vf. A cheat can freeze several.
+Blank lines and lines starting with '#' are ignored, so a cheat is turned
+off by commenting its lines out.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+
+use chip8::Chip8;
+use constants::RAM_BYTES;
+use movie::rom_hash;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatTarget {
+    Memory(u16),
+    Register(usize)
+}
+
+impl fmt::Display for CheatTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheatTarget::Memory(addr) => write!(f, "{:03x}", addr),
+            CheatTarget::Register(x) => write!(f, "v{:x}", x)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    pub freezes: Vec<(CheatTarget, u8)>
+}
+
+impl Cheat {
+    // Puts back registers the program changed. Memory is frozen in the CPU
+    // instead, which drops the program's writes, see Chip8::freeze.
+    pub fn apply_registers(&self, chip8: &mut Chip8) {
+        for &(target, value) in &self.freezes {
+            if let CheatTarget::Register(x) = target {
+                chip8.set_register(x, value)
+            }
+        }
+    }
+}
+
+// Cheat codes by SHA-1 of the ROM bytes
+pub struct CheatFile {
+    roms: HashMap<String, Vec<Cheat>>
+}
+
+impl CheatFile {
+    pub fn load(path: &str) -> io::Result<Self> {
+        CheatFile::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut roms: HashMap<String, Vec<Cheat>> = HashMap::new();
+        let mut hash: Option<String> = None;
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            if line.starts_with('[') && line.ends_with(']') {
+                let rom = line[1..line.len() - 1].to_lowercase();
+                roms.entry(rom.clone()).or_default();
+                hash = Some(rom);
+                continue
+            }
+
+            let cheats = hash.as_ref().and_then(|hash| roms.get_mut(hash))
+                .ok_or_else(|| invalid_cheats(&format!("entry outside of a ROM block: {}", line)))?;
+            let (field, value) = match line.find(char::is_whitespace) {
+                Some(i) => (&line[..i], line[i..].trim()),
+                None => (line, "")
+            };
+            let bad_value = || invalid_cheats(&format!("bad value for '{}': {}", field, value));
+
+            match field {
+                "cheat" if !value.is_empty() => cheats.push(Cheat { name: value.to_string(), freezes: Vec::new() }),
+                "freeze" => {
+                    let words = value.split_whitespace().collect::<Vec<&str>>();
+                    let freeze = match words[..] {
+                        [target, value] => parse_target(target).zip(u8::from_str_radix(value, 16).ok()),
+                        _ => None
+                    };
+                    cheats.last_mut()
+                        .ok_or_else(|| invalid_cheats(&format!("freeze outside of a cheat: {}", line)))?
+                        .freezes.push(freeze.ok_or_else(bad_value)?)
+                },
+                _ => return Err(invalid_cheats(&format!("unknown entry: {}", line)))
+            }
+        }
+
+        Ok(CheatFile { roms })
+    }
+
+    pub fn lookup(&self, rom_bytes: &[u8]) -> &[Cheat] {
+        self.roms.get(&rom_hash(rom_bytes)).map_or(&[], Vec::as_slice)
+    }
+}
+
+fn parse_target(text: &str) -> Option<CheatTarget> {
+    match text.to_lowercase().strip_prefix('v') {
+        Some(x) if x.len() == 1 => usize::from_str_radix(x, 16).ok().map(CheatTarget::Register),
+        Some(_) => None,
+        None => usize::from_str_radix(text, 16).ok()
+            .filter(|&addr| addr < RAM_BYTES)
+            .map(|addr| CheatTarget::Memory(addr as u16))
+    }
+}
+
+fn invalid_cheats(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid cheat file: {}", message))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchFilter {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased
+}
+
+/*
+Narrows down where a game keeps a value, e.g. lives, by comparing RAM with a
+snapshot. Every address starts as a candidate, each filter keeps the ones
+that match and takes a new snapshot to compare the next filter against.
+*/
+pub struct RamSearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>
+}
+
+impl RamSearch {
+    pub fn new(ram: &[u8]) -> Self {
+        RamSearch {
+            snapshot: ram.to_vec(),
+            candidates: (0..ram.len() as u16).collect()
+        }
+    }
+
+    pub fn filter(&mut self, ram: &[u8], filter: SearchFilter) {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&addr| {
+            let (before, now) = (snapshot[addr as usize], ram[addr as usize]);
+            match filter {
+                SearchFilter::Equal(value) => now == value,
+                SearchFilter::Changed => now != before,
+                SearchFilter::Unchanged => now == before,
+                SearchFilter::Increased => now > before,
+                SearchFilter::Decreased => now < before
+            }
+        });
+        self.snapshot = ram.to_vec()
+    }
+
+    // In address order
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    pub fn is_candidate(&self, addr: u16) -> bool {
+        self.candidates.binary_search(&addr).is_ok()
+    }
+}
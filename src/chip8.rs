@@ -1,12 +1,73 @@
-use byteorder::{ByteOrder, BigEndian};
+use std::collections::HashMap;
+use std::rc::Rc;
 
-use rng::rng_byte;
-use constants::{W, H, N, ROM_ADDR, RAM_BYTES};
-use opcode::{Opcode, Operation::*, OpcodeType::{self,*}, OpcodeDisassembler};
+use rng::{RandomSource, RngState, SeededRandom};
+use constants::{W, H, N, ROM_ADDR, RAM_BYTES, FONT_ADDR};
+use opcode::{Opcode, Operation::*, OpcodeType::{self,*}, OpcodeCache};
 use command::{CommandEmulator, Command, 
     DisplayCommand::*, AudioCommand::*, KeyCommand::KeyDownUp, 
-    MemoryCommand::SendRAM};
+    MemoryCommand::{SendRAM, SendByte, SendPointers}};
 use router::Router;
+use translator::{Block, BlockCache};
+use library::Quirk;
+use profiler::Profile;
+use save_state::SaveState;
+
+// Addresses wrap around the 4kB address space instead of running off the end
+const ADDR_MASK: u16 = (RAM_BYTES - 1) as u16;
+const STACK_MASK: u8 = 0xF;
+
+// How instructions are run. Both give identical results, translated code
+// skips decoding and dispatch for blocks it has run before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    Interpreter,
+    Translated
+}
+
+// Behaviours of other interpreters, see library::Quirk. All are off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Quirks {
+    // SHR and SHL shift Vy into Vx
+    pub shift_vy: bool,
+    // LD [I], Vx and LD Vx, [I] leave I after the last byte moved
+    pub load_store_i: bool,
+    // OR, AND and XOR clear VF
+    pub vf_reset: bool,
+    // Sprites are cut off at the edges of the screen instead of wrapping
+    pub clip: bool,
+    // BXNN jumps to XNN plus Vx instead of V0
+    pub jump_vx: bool
+}
+
+impl Quirks {
+    pub fn new(quirks: &[Quirk]) -> Self {
+        Quirks {
+            shift_vy: quirks.contains(&Quirk::ShiftVy),
+            load_store_i: quirks.contains(&Quirk::LoadStoreI),
+            vf_reset: quirks.contains(&Quirk::VfReset),
+            clip: quirks.contains(&Quirk::Clip),
+            jump_vx: quirks.contains(&Quirk::JumpVx)
+        }
+    }
+
+    // The quirks turned on, in the order the ROM database lists them
+    pub fn enabled(&self) -> Vec<Quirk> {
+        [(Quirk::ShiftVy, self.shift_vy), (Quirk::LoadStoreI, self.load_store_i),
+            (Quirk::VfReset, self.vf_reset), (Quirk::Clip, self.clip), (Quirk::JumpVx, self.jump_vx)]
+            .iter()
+            .filter(|(_, is_on)| *is_on)
+            .map(|(quirk, _)| *quirk)
+            .collect()
+    }
+}
+
+// A translated instruction. Plain steps only read and write registers and pc,
+// so the timer ticks after a run of them can be sent together.
+struct Step {
+    run: Box<dyn Fn(&mut Chip8)>,
+    is_plain: bool
+}
 
 #[allow(non_snake_case)]
 pub struct Chip8 {
@@ -20,11 +81,25 @@ pub struct Chip8 {
     V: [u8; 0x10],
     delay_timer: u8,
     sound_timer: u8,
+    // Whether Play or Pause was sent last, audio commands are only sent on a change
+    is_sound_on: bool,
 
+    rng: Box<dyn RandomSource>,
     commands: Router<Command>,
     key_buf: [bool; 0x10],
     pixel_buf: [bool; N],
-    memory_buf: [u8; RAM_BYTES]
+    memory_buf: [u8; RAM_BYTES],
+    opcode_cache: OpcodeCache,
+    quirks: Quirks,
+    // Bytes kept at a value, e.g. by cheats. Writes to them are dropped
+    // before they land, so nothing else ever sees them.
+    frozen: HashMap<u16, u8>,
+
+    engine: Engine,
+    block_cache: BlockCache<Step>,
+    block_position: Option<(Rc<Block<Step>>, usize)>,
+
+    profile: Option<Profile>
 }
 
 impl CommandEmulator for Chip8 {
@@ -34,10 +109,7 @@ impl CommandEmulator for Chip8 {
 
     fn process_inbound_command(&mut self, command: &Command) { 
         match command {
-            Command::Display(c) => match c {
-                SendPixels(p) => self.pixel_buf.copy_from_slice(p),
-                _ => {}
-            },
+            Command::Display(SendPixels(p)) => self.pixel_buf.copy_from_slice(&p[..]),
             Command::Key(c) => match *c {
                 KeyDownUp(key_i, key_is_down) => {
                     self.key_buf[key_i] = key_is_down;
@@ -48,7 +120,21 @@ impl CommandEmulator for Chip8 {
                 }
             },
             Command::Memory(c) => match c {
-                SendRAM(bytes) => self.memory_buf.copy_from_slice(bytes)
+                SendRAM(bytes) => {
+                    self.memory_buf.copy_from_slice(&bytes[..]);
+                    self.clear_decoded();
+                    self.restore_frozen()
+                },
+                // A frozen byte is sent back, so the sender's copy keeps it too
+                SendByte(addr, value) => match self.frozen.get(&(ram_index(*addr) as u16)) {
+                    Some(&frozen) => self.commands.send_outbound(Command::Memory(SendByte(*addr, frozen))),
+                    None => {
+                        self.memory_buf[ram_index(*addr)] = *value;
+                        self.invalidate_decoded(ram_index(*addr));
+                        self.block_position = None
+                    }
+                },
+                SendPointers(..) => {}
             }
             _ => {}
         }
@@ -56,21 +142,24 @@ impl CommandEmulator for Chip8 {
 
     fn emulate_cycle(&mut self) {
         if !self.key_wait {
-            let instruction: u16 = self.next_instruction();
+            if self.profile.is_some() { self.profile_instruction() }
 
-            let opcode: Opcode = OpcodeDisassembler::disassemble(instruction);
+            match self.engine {
+                Engine::Interpreter => {
+                    let opcode: Opcode = self.next_opcode();
 
-            self.execute_opcode(opcode);
-
-            self.update_timers();
-            self.update_display();
-            self.update_memory();
+                    self.execute_opcode(opcode);
+                    self.update_timers();
+                    self.update_display();
+                },
+                Engine::Translated => { self.run_translated(1); }
+            }
         }
     }
 }
 
 impl Chip8 {
-    pub fn new() -> Self {
+    pub fn new(rng: Box<dyn RandomSource>) -> Self {
         Chip8 {
             draw_flag: false,
             key_wait: false,
@@ -82,20 +171,212 @@ impl Chip8 {
             V: [0; 0x10],
             delay_timer: 0,
             sound_timer: 0,
+            is_sound_on: false,
 
+            rng,
             commands: Router::<Command>::new(),
             key_buf: [false; 0x10],
             pixel_buf: [false; N],
             memory_buf: [0; RAM_BYTES],
+            opcode_cache: OpcodeCache::new(RAM_BYTES),
+            quirks: Quirks::default(),
+            frozen: HashMap::new(),
+
+            engine: Engine::Interpreter,
+            block_cache: BlockCache::new(RAM_BYTES),
+            block_position: None,
+
+            profile: None
+        }
+    }
+
+    pub fn get_engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+        self.block_position = None
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Translated code is dropped, it was translated for the old quirks
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.clear_decoded()
+    }
+
+    // The same as that many calls to emulate_cycle, but translated code runs a
+    // whole block per dispatch instead of going back through it every step
+    pub fn run_cycles(&mut self, cycles: u64) {
+        if self.engine == Engine::Interpreter || self.profile.is_some() {
+            return (0..cycles).for_each(|_| self.emulate_cycle())
+        }
+
+        let mut remaining = cycles;
+        while remaining > 0 && !self.key_wait {
+            remaining -= self.run_translated(remaining)
+        }
+    }
+
+    // Counts the instructions run from now on, at each address and by operation
+    pub fn start_profiling(&mut self) {
+        self.profile = Some(Profile::new())
+    }
+
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    pub fn get_pixels(&self) -> &[bool] {
+        &self.pixel_buf
+    }
+
+    pub fn get_registers(&self) -> &[u8] {
+        &self.V
+    }
+
+    pub fn get_i(&self) -> u16 {
+        self.I
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn get_sp(&self) -> u8 {
+        self.sp
+    }
+
+    // Return addresses of the active subroutine calls, outermost first
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack[1..=self.sp as usize]
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn get_memory(&self) -> &[u8] {
+        &self.memory_buf
+    }
+
+    // Setters for debuggers, taking effect from the next instruction
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.V[x & 0xF] = value
+    }
+
+    pub fn set_i(&mut self, value: u16) {
+        self.I = value
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc & ADDR_MASK;
+        self.block_position = None
+    }
+
+    pub fn set_sp(&mut self, sp: u8) {
+        self.sp = sp & STACK_MASK
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value
+    }
+
+    // Written like the program's own stores, so memory and decoded code stay in step
+    pub fn write_byte(&mut self, addr: u16, value: u8) {
+        self.write_memory(addr, value);
+        self.block_position = None
+    }
+
+    // Keeps a byte at a value, stored once like the program's own writes, or lets it go
+    pub fn freeze(&mut self, addr: u16, value: Option<u8>) {
+        let addr = addr & ADDR_MASK;
+        self.frozen.remove(&addr);
+        if let Some(value) = value {
+            if self.memory_buf[addr as usize] != value {
+                self.store(addr, value);
+                self.block_position = None
+            }
+            self.frozen.insert(addr, value);
+        }
+    }
+
+    pub fn is_frozen(&self, addr: u16) -> bool {
+        self.frozen.contains_key(&(addr & ADDR_MASK))
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait
+    }
+
+    pub fn rng_state(&self) -> Option<RngState> {
+        self.rng.state()
+    }
+
+    pub fn restore_rng(&mut self, state: &RngState) {
+        self.rng = state.restore()
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            pc: self.pc,
+            i: self.I,
+            sp: self.sp,
+            stack: self.stack,
+            registers: self.V,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            key_wait: if self.key_wait { Some(self.reg_wait) } else { None },
+            rng: self.rng.state(),
+            memory: self.memory_buf.to_vec(),
+            pixels: self.pixel_buf.to_vec()
         }
     }
 
-    fn execute_opcode(&mut self, opcode: Opcode) {
+    // Memory and the display are sent on, like after loading a ROM and drawing
+    pub fn restore_state(&mut self, state: &SaveState) {
+        self.pc = state.pc & ADDR_MASK;
+        self.I = state.i;
+        self.sp = state.sp & STACK_MASK;
+        self.stack = state.stack;
+        self.V = state.registers;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.key_wait = state.key_wait.is_some();
+        self.reg_wait = state.key_wait.unwrap_or(0) & 0xF;
+        if let Some(ref rng) = state.rng { self.restore_rng(rng) }
+
+        self.memory_buf.copy_from_slice(&state.memory[..RAM_BYTES]);
+        self.clear_decoded();
+        self.commands.send_outbound(Command::Memory(SendRAM(Box::new(self.memory_buf))));
+        self.restore_frozen();
+        self.pixel_buf.copy_from_slice(&state.pixels[..N]);
+        self.draw_flag = true;
+        self.update_display()
+    }
+
+    pub fn execute_opcode(&mut self, opcode: Opcode) {
         match opcode {
+            Opcode(SYS, _) => {}, // RCA 1802 machine code is ignored
             Opcode(CLS, NONE) => self.clear_display(),
             Opcode(RET, NONE) => self.subroutine_return(),
             Opcode(JP, NNN(nnn)) => self.jump(nnn),
-            Opcode(JP, V0_NNN(nnn)) => self.jump(self.V[0] as u16 + nnn),
+            Opcode(JP, V0_NNN(nnn)) => {
+                let x = if self.quirks.jump_vx { nnn >> 8 } else { 0 };
+                self.jump(self.V[x as usize] as u16 + nnn)
+            },
             Opcode(CALL, NNN(nnn)) => self.subroutine_call(nnn),
             Opcode(SE, op_type) => self.skip_equal(op_type),
             Opcode(SNE, op_type) => self.skip_not_equal(op_type),
@@ -110,17 +391,22 @@ impl Chip8 {
             Opcode(LD, DT_X(x)) => self.delay_timer = self.V[x as usize],
             Opcode(LD, ST_X(x)) => self.sound_timer = self.V[x as usize],
             Opcode(LD, F_X(x)) => {
-                self.I = (0x50 + (0x5 * self.V[x as usize])) as u16
+                self.I = FONT_ADDR as u16 + 0x5 * (self.V[x as usize] & 0xF) as u16
             },
             Opcode(LD, B_X(x)) => { 
-                self.memory_buf[self.I as usize] = self.V[x as usize] / 100;
-                self.memory_buf[(self.I+1) as usize] = (self.V[x as usize] / 10) % 10;
-                self.memory_buf[(self.I+2) as usize] = self.V[x as usize] % 10
+                let vx = self.V[x as usize];
+                self.write_memory(self.I, vx / 100);
+                self.write_memory(self.I+1, (vx / 10) % 10);
+                self.write_memory(self.I+2, vx % 10)
+            },
+            Opcode(LD, RI_X(x)) => {
+                (0..x+1).for_each(|i| self.write_memory(self.I + i, self.V[i as usize]));
+                if self.quirks.load_store_i { self.I = (self.I + x + 1) & ADDR_MASK }
+            },
+            Opcode(LD, X_RI(x)) => {
+                (0..x+1).for_each(|i| self.V[i as usize] = self.memory_buf[ram_index(self.I + i)]);
+                if self.quirks.load_store_i { self.I = (self.I + x + 1) & ADDR_MASK }
             },
-            Opcode(LD, RI_X(x)) => (0..x+1).for_each(|i| 
-                self.memory_buf[(self.I + i) as usize] = self.V[i as usize]),
-            Opcode(LD, X_RI(x)) => (0..x+1).for_each(|i| 
-                self.V[i as usize] = self.memory_buf[(self.I + i) as usize]),
             Opcode(ADD, XNN(x, nn)) => {
                 //self.V[0xF] = ((((self.V[x as usize] as u16) + nn) & 0xFF) >> 8) as u8; 
                 self.V[x as usize] = (((self.V[x as usize] as u16) + nn) & 0xFF) as u8
@@ -131,83 +417,182 @@ impl Chip8 {
                 self.I &= 0xFFF
             },
             Opcode(ADD, XY(x, y)) => {
-                self.V[0xF] = (((self.V[x as usize] as u16) + (self.V[y as usize] as u16)) >> 8) as u8; 
-                self.V[x as usize] = (((self.V[x as usize] as u16)+(self.V[y as usize] as u16)) & 0xFF) as u8
+                let sum = (self.V[x as usize] as u16) + (self.V[y as usize] as u16);
+                self.set_with_flag(x, (sum & 0xFF) as u8, (sum >> 8) as u8)
+            },
+            Opcode(OR, XY(x, y)) => {
+                self.V[x as usize] |= self.V[y as usize];
+                if self.quirks.vf_reset { self.V[0xF] = 0 }
+            },
+            Opcode(AND, XY(x, y)) => {
+                self.V[x as usize] &= self.V[y as usize];
+                if self.quirks.vf_reset { self.V[0xF] = 0 }
+            },
+            Opcode(XOR, XY(x, y)) => {
+                self.V[x as usize] ^= self.V[y as usize];
+                if self.quirks.vf_reset { self.V[0xF] = 0 }
             },
-            Opcode(OR, XY(x, y)) => self.V[x as usize] |= self.V[y as usize],
-            Opcode(AND, XY(x, y)) => self.V[x as usize] &= self.V[y as usize],
-            Opcode(XOR, XY(x, y)) => self.V[x as usize] ^= self.V[y as usize],
             Opcode(SUB, XY(x, y)) => {
-                self.V[0xF] = (self.V[x as usize] > self.V[y as usize]) as u8; 
-                self.V[x as usize] = ((self.V[x as usize] as i16) - (self.V[y as usize] as i16)) as u8
+                let (vx, vy) = (self.V[x as usize], self.V[y as usize]);
+                self.set_with_flag(x, vx.wrapping_sub(vy), (vx >= vy) as u8)
             },
             Opcode(SUBN, XY(x, y)) => {
-                self.V[0xF] = (self.V[y as usize] > self.V[x as usize]) as u8; 
-                self.V[x as usize] = ((self.V[y as usize] as i16) - (self.V[x as usize] as i16)) as u8
+                let (vx, vy) = (self.V[x as usize], self.V[y as usize]);
+                self.set_with_flag(x, vy.wrapping_sub(vx), (vy >= vx) as u8)
             },
-            Opcode(SHR, X(x)) => {
-                self.V[0xF] = self.V[x as usize] & 0x1;
-                self.V[x as usize] >>= 1
+            Opcode(SHR, XY(x, y)) => {
+                let vx = self.V[if self.quirks.shift_vy { y } else { x } as usize];
+                self.set_with_flag(x, vx >> 1, vx & 0x1)
             },
-            Opcode(SHL, X(x)) => {
-                self.V[0xF] = self.V[x as usize] >> 7;
-                self.V[x as usize] <<= 1
+            Opcode(SHL, XY(x, y)) => {
+                let vx = self.V[if self.quirks.shift_vy { y } else { x } as usize];
+                self.set_with_flag(x, vx << 1, vx >> 7)
             },
-            Opcode(RND, XNN(x, nn)) => self.V[x as usize] = rng_byte() & nn as u8,
+            Opcode(RND, XNN(x, nn)) => self.V[x as usize] = self.rng.rng_byte() & nn as u8,
             Opcode(DRW, XYN(x, y, n)) => {
+                // Coordinates are read before VF is used for collisions. The
+                // sprite starts on screen, clipped sprites then stop at the edges.
+                let (vx, vy) = (self.V[x as usize] as usize % W, self.V[y as usize] as usize % H);
                 self.V[0xF] = 0;
-                let mut py: usize = self.V[y as usize] as usize;
-                for i in 0..n {
-                    while py >= H {py -= H};
-                    let mut px: usize = self.V[x as usize] as usize;
+                for i in 0..n as usize {
+                    if self.quirks.clip && vy + i >= H { break }
+                    let row = self.memory_buf[ram_index(self.I + i as u16)];
                     for ii in 0..8 {
-                        while px >= W {px -= W};
-                        self.update_pixel(px, py, 
-                            (((self.memory_buf[(self.I + i) as usize] >> (7 - ii)) & 1) == 1) as bool);
-                        px += 1
-                    };
-                    py += 1
-                };
+                        if self.quirks.clip && vx + ii >= W { break }
+                        self.update_pixel((vx + ii) % W, (vy + i) % H, (row >> (7 - ii)) & 1 == 1)
+                    }
+                }
             },
             Opcode(SKP, X(x)) => self.skip(self.key_buf[(self.V[x as usize] & 0xF) as usize]),
             Opcode(SKNP, X(x)) => self.skip(!self.key_buf[(self.V[x as usize] & 0xF) as usize]),
-            Opcode(UNDEFINED, ..) => unimplemented!("Undefined opcode behaviour encountered in program."),
-            _ => unimplemented!("This opcode is not implemented in this chip8 emulator. Opcode: {:?}", opcode)
+            Opcode(UNDEFINED, ..) => {}, // Skipped, like data the program jumped into
+            _ => {} // Operands the decoder never pairs with the instruction, skipped the same way
+        }
+    }
+
+    // Translated blocks don't keep opcodes, so the one at pc is decoded here
+    fn profile_instruction(&mut self) {
+        let addr = ram_index(self.pc);
+        let Opcode(operation, _) = self.opcode_cache.get_or_decode(&self.memory_buf, addr);
+        if let Some(ref mut profile) = self.profile {
+            profile.record(addr, operation)
         }
     }
 
-    fn next_instruction(&mut self) -> u16 {
-        let pc = self.pc as usize;
-        let instruction: u16 = BigEndian::read_u16(&self.memory_buf[pc..(pc + 2)]);
-        self.pc += 2;
+    fn next_opcode(&mut self) -> Opcode {
+        let opcode = self.opcode_cache.get_or_decode(&self.memory_buf, ram_index(self.pc));
+        self.pc = (self.pc + 2) & ADDR_MASK;
 
-        instruction
+        opcode
+    }
+
+    // Runs up to max_steps of the translated block at pc, each a cycle with its
+    // timer tick, and returns how many ran. Blocks only change control flow in
+    // their last step, so the following step is next in line.
+    fn run_translated(&mut self, max_steps: u64) -> u64 {
+        let (block, first) = match self.block_position.take() {
+            Some(position) => position,
+            None => {
+                let quirks = self.quirks;
+                let block = self.block_cache.get_or_translate(&self.memory_buf, ram_index(self.pc),
+                    |opcode| translate(opcode, quirks));
+                (block, 0)
+            }
+        };
+        let last = block.steps.len().min(first + max_steps as usize);
+        let steps = &block.steps[first..last];
+
+        if steps.iter().all(|step| step.is_plain) {
+            for step in steps {
+                self.pc = (self.pc + 2) & ADDR_MASK;
+                (step.run)(self)
+            }
+            self.update_timers_by(steps.len())
+        } else {
+            for step in steps {
+                self.pc = (self.pc + 2) & ADDR_MASK;
+                (step.run)(self);
+                self.update_timers();
+                self.update_display()
+            }
+        }
+
+        if last < block.steps.len() {
+            self.block_position = Some((block, last))
+        }
+        (last - first) as u64
+    }
+
+    fn invalidate_decoded(&mut self, addr: usize) {
+        self.opcode_cache.invalidate(addr);
+        self.block_cache.invalidate(addr)
+    }
+
+    fn clear_decoded(&mut self) {
+        self.opcode_cache.clear();
+        self.block_cache.clear();
+        self.block_position = None
     }
 
     fn update_timers(&mut self) {
         if self.delay_timer > 0 { self.delay_timer -= 1 };
 
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
-            self.commands.send_outbound(Command::Audio(Play))
-        } else {
-            self.commands.send_outbound(Command::Audio(Pause))
+        let is_sound_on = self.sound_timer > 0;
+        if is_sound_on { self.sound_timer -= 1 }
+        self.set_sound(is_sound_on)
+    }
+
+    // The same as that many update_timers in a row
+    fn update_timers_by(&mut self, ticks: usize) {
+        let played = ticks.min(self.sound_timer as usize);
+        self.delay_timer = self.delay_timer.saturating_sub(ticks.min(0xFF) as u8);
+        self.sound_timer -= played as u8;
+
+        if played > 0 { self.set_sound(true) }
+        if ticks > played { self.set_sound(false) }
+    }
+
+    fn set_sound(&mut self, is_on: bool) {
+        if is_on != self.is_sound_on {
+            self.is_sound_on = is_on;
+            self.commands.send_outbound(Command::Audio(if is_on { Play } else { Pause }))
         }
     }
 
+    // Pixels are only sent when a draw or clear changed them
     fn update_display(&mut self) {
-        self.commands.send_outbound(
-            Command::Display(SendPixels(self.pixel_buf.clone())));
-
         if self.draw_flag {
+            self.commands.send_outbound(
+                Command::Display(SendPixels(Box::new(self.pixel_buf))));
             self.commands.send_outbound(Command::Display(SendDraw));
             self.draw_flag = false
         }
     }
 
-    fn update_memory(&mut self) {
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        if !self.frozen.contains_key(&(addr & ADDR_MASK)) { self.store(addr, value) }
+    }
+
+    fn store(&mut self, addr: u16, value: u8) {
+        self.memory_buf[ram_index(addr)] = value;
+        self.invalidate_decoded(ram_index(addr));
         self.commands.send_outbound(
-            Command::Memory(SendRAM(self.memory_buf.clone())))
+            Command::Memory(SendByte(addr & ADDR_MASK, value)))
+    }
+
+    // Memory loaded over frozen bytes gets their values back
+    fn restore_frozen(&mut self) {
+        let thawed = self.frozen.iter()
+            .filter(|&(&addr, &value)| self.memory_buf[addr as usize] != value)
+            .map(|(&addr, &value)| (addr, value))
+            .collect::<Vec<(u16, u8)>>();
+        thawed.into_iter().for_each(|(addr, value)| self.store(addr, value))
+    }
+
+    // VF is written after Vx, so the flag wins when x is 0xF
+    fn set_with_flag(&mut self, x: u16, value: u8, flag: u8) {
+        self.V[x as usize] = value;
+        self.V[0xF] = flag
     }
 
     fn update_pixel(&mut self, x: usize, y: usize, val: bool) {
@@ -225,27 +610,29 @@ impl Chip8 {
     }
 
     fn clear_display(&mut self) {
+        self.pixel_buf = [false; N];
         self.commands.send_outbound(Command::Display(SendClearDisplay));
         self.draw_flag = true
     }
 
+    // The stack pointer wraps on overflow and underflow rather than panicking
     fn subroutine_return(&mut self) {
         self.pc = self.stack[self.sp as usize]; 
-        self.sp -= 1 
+        self.sp = self.sp.wrapping_sub(1) & STACK_MASK
     }
 
     fn jump(&mut self, location: u16) {
-        self.pc = location
+        self.pc = location & ADDR_MASK
     }
 
     fn subroutine_call(&mut self, location: u16) {
-        self.sp += 1; 
+        self.sp = (self.sp + 1) & STACK_MASK; 
         self.stack[self.sp as usize] = self.pc;
         self.jump(location)
     }
 
     fn skip(&mut self, condition: bool) {
-        if condition { self.pc += 2 }
+        if condition { self.pc = (self.pc + 2) & ADDR_MASK }
     }
 
     fn skip_equal(&mut self, op_type: OpcodeType) {
@@ -260,3 +647,167 @@ impl Chip8 {
         }
     }
 }
+
+fn ram_index(addr: u16) -> usize {
+    (addr & ADDR_MASK) as usize
+}
+
+// Common register instructions get specialised steps, the rest run through execute_opcode
+fn translate(opcode: Opcode, quirks: Quirks) -> Step {
+    let run: Box<dyn Fn(&mut Chip8)> = match opcode {
+        Opcode(LD, XNN(x, nn)) => {
+            let (x, nn) = (x as usize, nn as u8);
+            Box::new(move |chip8| chip8.V[x] = nn)
+        },
+        Opcode(LD, XY(x, y)) => {
+            let (x, y) = (x as usize, y as usize);
+            Box::new(move |chip8| chip8.V[x] = chip8.V[y])
+        },
+        Opcode(LD, I_NNN(nnn)) => Box::new(move |chip8| chip8.I = nnn),
+        Opcode(ADD, XNN(x, nn)) => {
+            let (x, nn) = (x as usize, nn as u8);
+            Box::new(move |chip8| chip8.V[x] = chip8.V[x].wrapping_add(nn))
+        },
+        Opcode(OR, XY(x, y)) if !quirks.vf_reset => {
+            let (x, y) = (x as usize, y as usize);
+            Box::new(move |chip8| chip8.V[x] |= chip8.V[y])
+        },
+        Opcode(AND, XY(x, y)) if !quirks.vf_reset => {
+            let (x, y) = (x as usize, y as usize);
+            Box::new(move |chip8| chip8.V[x] &= chip8.V[y])
+        },
+        Opcode(XOR, XY(x, y)) if !quirks.vf_reset => {
+            let (x, y) = (x as usize, y as usize);
+            Box::new(move |chip8| chip8.V[x] ^= chip8.V[y])
+        },
+        Opcode(ADD, XY(x, y)) => {
+            let (x, y) = (x as usize, y as usize);
+            Box::new(move |chip8| {
+                let (sum, carry) = chip8.V[x].overflowing_add(chip8.V[y]);
+                chip8.V[x] = sum;
+                chip8.V[0xF] = carry as u8
+            })
+        },
+        Opcode(SHR, XY(x, y)) => {
+            let (x, source) = (x as usize, if quirks.shift_vy { y } else { x } as usize);
+            Box::new(move |chip8| {
+                let vx = chip8.V[source];
+                chip8.V[x] = vx >> 1;
+                chip8.V[0xF] = vx & 0x1
+            })
+        },
+        Opcode(SHL, XY(x, y)) => {
+            let (x, source) = (x as usize, if quirks.shift_vy { y } else { x } as usize);
+            Box::new(move |chip8| {
+                let vx = chip8.V[source];
+                chip8.V[x] = vx << 1;
+                chip8.V[0xF] = vx >> 7
+            })
+        },
+        Opcode(SE, XNN(x, nn)) => {
+            let (x, nn) = (x as usize, nn as u8);
+            Box::new(move |chip8| chip8.skip(chip8.V[x] == nn))
+        },
+        Opcode(SNE, XNN(x, nn)) => {
+            let (x, nn) = (x as usize, nn as u8);
+            Box::new(move |chip8| chip8.skip(chip8.V[x] != nn))
+        },
+        Opcode(JP, NNN(nnn)) => Box::new(move |chip8| chip8.jump(nnn)),
+        _ => return Step { run: Box::new(move |chip8| chip8.execute_opcode(opcode)), is_plain: false }
+    };
+    Step { run, is_plain: true }
+}
+
+// Sets up a Chip8 in a given state, e.g. for testing single instructions
+pub struct Chip8Builder {
+    chip8: Chip8
+}
+
+impl Default for Chip8Builder {
+    fn default() -> Self {
+        Chip8Builder::new()
+    }
+}
+
+impl Chip8Builder {
+    pub fn new() -> Self {
+        Chip8Builder {
+            chip8: Chip8::new(Box::new(SeededRandom::new(0)))
+        }
+    }
+
+    pub fn rng(mut self, rng: Box<dyn RandomSource>) -> Self {
+        self.chip8.rng = rng;
+        self
+    }
+
+    pub fn pc(mut self, pc: u16) -> Self {
+        self.chip8.pc = pc;
+        self
+    }
+
+    pub fn i(mut self, i: u16) -> Self {
+        self.chip8.I = i;
+        self
+    }
+
+    pub fn register(mut self, x: usize, value: u8) -> Self {
+        self.chip8.V[x] = value;
+        self
+    }
+
+    // Return addresses as if CALL had been executed from each, outermost first.
+    // The stack is read from slot 1 up to sp, so 15 fit.
+    pub fn stack(mut self, return_addresses: &[u16]) -> Self {
+        assert!(return_addresses.len() < self.chip8.stack.len(),
+            "The stack holds at most {} return addresses", self.chip8.stack.len() - 1);
+        self.chip8.sp = return_addresses.len() as u8;
+        self.chip8.stack[1..=return_addresses.len()].copy_from_slice(return_addresses);
+        self
+    }
+
+    pub fn delay_timer(mut self, value: u8) -> Self {
+        self.chip8.delay_timer = value;
+        self
+    }
+
+    pub fn sound_timer(mut self, value: u8) -> Self {
+        self.chip8.sound_timer = value;
+        self
+    }
+
+    pub fn memory(mut self, addr: usize, bytes: &[u8]) -> Self {
+        self.chip8.memory_buf[addr..addr + bytes.len()].copy_from_slice(bytes);
+        self.chip8.clear_decoded();
+        self
+    }
+
+    pub fn pixel(mut self, x: usize, y: usize, is_lit: bool) -> Self {
+        self.chip8.pixel_buf[y * W + x] = is_lit;
+        self
+    }
+
+    pub fn pixels(mut self, pixels: &[bool]) -> Self {
+        self.chip8.pixel_buf.copy_from_slice(pixels);
+        self
+    }
+
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.chip8.set_engine(engine);
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.chip8.set_quirks(quirks);
+        self
+    }
+
+    pub fn key(mut self, key_i: usize, is_down: bool) -> Self {
+        self.chip8.key_buf[key_i] = is_down;
+        self
+    }
+
+    pub fn build(self) -> Chip8 {
+        self.chip8
+    }
+}
@@ -1,5 +1,6 @@
 use constants::*;
 use router::Router;
+use osd::Status;
 
 pub trait CommandEmulator {
     fn get_commands(&mut self) -> &mut Router<Command>;
@@ -16,6 +17,7 @@ pub trait CommandEmulator {
     fn emulate_cycle(&mut self);
 }
 
+#[derive(Debug, Clone)]
 pub enum Command {
     Memory(MemoryCommand),
     Audio(AudioCommand),
@@ -24,25 +26,65 @@ pub enum Command {
     GameState(GameCommand),
 }
 
+// Command categories, used to subscribe to commands on the event bus
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topic {
+    Memory,
+    Audio,
+    Display,
+    Key,
+    GameState
+}
+
+impl Command {
+    pub fn topic(&self) -> Topic {
+        match self {
+            Command::Memory(_) => Topic::Memory,
+            Command::Audio(_) => Topic::Audio,
+            Command::Display(_) => Topic::Display,
+            Command::Key(_) => Topic::Key,
+            Command::GameState(_) => Topic::GameState
+        }
+    }
+}
+
+// Bulk payloads are boxed to keep every Command small to move around
+#[derive(Debug, Clone)]
 pub enum MemoryCommand {
-    SendRAM([u8; RAM_BYTES])
+    SendRAM(Box<[u8; RAM_BYTES]>),
+    SendByte(u16, u8),
+    // I and PC, for the memory window
+    SendPointers(u16, u16)
 }
 
+#[derive(Debug, Clone)]
 pub enum AudioCommand {
     Play,
     Pause
 }
 
+#[derive(Debug, Clone)]
 pub enum DisplayCommand {
-    SendPixels([bool; N]),
+    SendPixels(Box<[bool; N]>),
     SendDraw,
-    SendClearDisplay
+    SendClearDisplay,
+    SendStatus(Status),
+    SendMessage(String)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum KeyCommand {
     KeyDownUp(usize, bool)
 }
 
+#[derive(Debug, Clone)]
 pub enum GameCommand {
-    Exit
+    Exit,
+    FastForward(bool),
+    TogglePause,
+    FrameAdvance,
+    // The memory window opened or closed
+    ShowMemory(bool),
+    // Keeps a byte at a value every frame, or lets it go
+    Freeze(u16, Option<u8>)
 }
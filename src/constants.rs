@@ -22,6 +22,19 @@ pub const KEYS: usize = 0x10;
 pub static BLACK: Color = Color { r: 0, g: 0, b: 0, a: 0xff };
 pub const WHITE: Color = Color { r: 255, g: 255, b: 255, a: 0xff };
 
+// On-screen display text and the translucent box behind it
+pub const OSD_TEXT: Color = Color { r: 255, g: 220, b: 0, a: 0xff };
+pub const OSD_BACKGROUND: Color = Color { r: 0, g: 0, b: 0, a: 0xa0 };
+pub const OSD_SCALE: u32 = 3;
+
+// Memory window text size and the marks behind bytes
+pub const VIEWER_SCALE: u32 = 2;
+pub const VIEWER_I: Color = Color { r: 30, g: 90, b: 200, a: 0xff };
+pub const VIEWER_PC: Color = Color { r: 20, g: 140, b: 60, a: 0xff };
+pub const VIEWER_FLASH: Color = Color { r: 230, g: 40, b: 40, a: 0xff };
+pub const VIEWER_FROZEN: Color = Color { r: 0, g: 150, b: 170, a: 0xff };
+pub const VIEWER_CANDIDATE: Color = Color { r: 120, g: 50, b: 150, a: 0xff };
+
 /*
 1234
 QWER
@@ -0,0 +1,527 @@
+/*
+Debug Adapter Protocol server, so editors such as VS Code can debug ROMs.
+Messages are JSON after a "Content-Length: <bytes>\r\n\r\n" header. One
+thread reads them from the client and another writes them back; the
+session runs on the emulator thread and talks to both over channels.
+
+Launch arguments:
+program      - path of the ROM
+sourceMap    - optional source map written by the assembler, see source_map.rs
+stopOnEntry  - optional, halts before the first instruction runs
+
+Breakpoints can be set on lines of assembler source in the source map, or on
+instruction addresses. There's one thread, whose stack trace has a frame for
+each subroutine call on the Chip-8 stack. Variables are the registers and
+timers; I and PC reference memory for the memory and disassembly views.
+Memory references are addresses in hex, e.g. "0x200".
+*/
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write, Error, ErrorKind};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+
+use serde_json::{self, Value, json};
+
+use constants::RAM_BYTES;
+use chip8::Chip8;
+use emulator::Chip8Emulator;
+use debugger::{DebugSession, Debugger, StopReason, word_at};
+use opcode::OpcodeDisassembler;
+use source_map::{SourceLine, SourceMap};
+
+const THREAD_ID: u64 = 1;
+
+// Variable references of the scopes
+const REGISTERS: u64 = 1;
+const TIMERS: u64 = 2;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchArgs {
+    pub program: String,
+    pub source_map: Option<String>,
+    pub stop_on_entry: bool
+}
+
+// The next message, None when the client closed the stream
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 { return Ok(None) }
+        let header = header.trim();
+        if header.is_empty() {
+            if content_length.is_some() { break } else { continue }
+        }
+        if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = Some(length.trim().parse::<usize>()
+                .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Invalid header: {}", header)))?)
+        }
+    }
+
+    let mut content = vec![0; content_length.unwrap_or(0)];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content).map(Some).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    writer.flush()
+}
+
+// Forwards the client's messages until it closes the stream or nothing receives them
+pub fn forward_requests<R: BufRead>(mut reader: R, requests: Sender<Value>) -> io::Result<()> {
+    while let Some(request) = read_message(&mut reader)? {
+        if requests.send(request).is_err() { break }
+    }
+    Ok(())
+}
+
+// Numbers messages in the order they're sent, until every sender is dropped
+pub fn send_messages<W: Write>(messages: Receiver<Value>, mut writer: W) -> io::Result<()> {
+    for (seq, mut message) in messages.iter().enumerate() {
+        message["seq"] = json!(seq + 1);
+        write_message(&mut writer, &message)?
+    }
+    Ok(())
+}
+
+pub fn response(request: &Value, body: Value) -> Value {
+    json!({
+        "type": "response",
+        "request_seq": request["seq"],
+        "success": true,
+        "command": request["command"],
+        "body": body
+    })
+}
+
+pub fn error_response(request: &Value, message: &str) -> Value {
+    json!({
+        "type": "response",
+        "request_seq": request["seq"],
+        "success": false,
+        "command": request["command"],
+        "message": message
+    })
+}
+
+pub fn event(name: &str, body: Value) -> Value {
+    json!({ "type": "event", "event": name, "body": body })
+}
+
+pub fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsSetVariable": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsTerminateRequest": true
+    })
+}
+
+/*
+Answers the client until it asks to launch a ROM, returning the request so
+it can be answered once the ROM has loaded. None when the client disconnects
+first.
+*/
+pub fn wait_for_launch(requests: &Receiver<Value>, messages: &Sender<Value>) -> Option<(Value, LaunchArgs)> {
+    for request in requests.iter() {
+        let args = &request["arguments"];
+        let reply = match request["command"].as_str().unwrap_or("") {
+            "initialize" => response(&request, capabilities()),
+            "launch" => match args["program"].as_str() {
+                Some(program) => {
+                    let launch = LaunchArgs {
+                        program: program.to_string(),
+                        source_map: args["sourceMap"].as_str().map(str::to_string),
+                        stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false)
+                    };
+                    return Some((request.clone(), launch))
+                },
+                None => error_response(&request, "Launch needs the path of the ROM as 'program'")
+            },
+            "disconnect" => {
+                let _ = messages.send(response(&request, json!({})));
+                return None
+            },
+            command => error_response(&request, &format!("Unsupported request '{}' before launch", command))
+        };
+        if messages.send(reply).is_err() { return None }
+    }
+    None
+}
+
+pub struct DapSession {
+    requests: Receiver<Value>,
+    messages: Sender<Value>,
+    debugger: Debugger,
+    source_map: Option<SourceMap>,
+    // Set by source file, each request replaces the file's breakpoints
+    source_breakpoints: HashMap<PathBuf, Vec<u16>>,
+    instruction_breakpoints: Vec<u16>,
+    stop_on_entry: bool,
+    is_detached: bool
+}
+
+impl DapSession {
+    // The program stays halted until the client has set its breakpoints
+    pub fn new(requests: Receiver<Value>, messages: Sender<Value>, source_map: Option<SourceMap>,
+               stop_on_entry: bool) -> Self {
+        DapSession {
+            requests,
+            messages,
+            debugger: Debugger::new(),
+            source_map,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry,
+            is_detached: false
+        }
+    }
+
+    fn send(&self, message: Value) {
+        let _ = self.messages.send(message);
+    }
+
+    // A client that's gone ends the game, nothing else can resume it
+    fn receive(&mut self, emulator: &mut Chip8Emulator) {
+        loop {
+            match self.requests.try_recv() {
+                Ok(request) => {
+                    let reply = self.handle(&request, emulator);
+                    self.send(reply)
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.is_detached = true;
+                    emulator.exit_game();
+                    break
+                }
+            }
+        }
+    }
+
+    fn report_stop(&mut self) {
+        let reason = match self.debugger.take_stop() {
+            Some(StopReason::Entry) => "entry",
+            Some(StopReason::Breakpoint) => "breakpoint",
+            Some(StopReason::Step) => "step",
+            Some(StopReason::Pause) => "pause",
+            None => return
+        };
+        self.send(event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true })))
+    }
+
+    // Stops are reported after the response to the request that caused them
+    fn handle(&mut self, request: &Value, emulator: &mut Chip8Emulator) -> Value {
+        let args = &request["arguments"];
+        let result = match request["command"].as_str().unwrap_or("") {
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "configurationDone" => {
+                if self.stop_on_entry { self.debugger.halt(StopReason::Entry) } else { self.debugger.resume() }
+                Ok(json!({}))
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "Chip-8" }] })),
+            "stackTrace" => Ok(self.stack_trace(emulator.get_chip8(), args)),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false }
+            ] })),
+            "variables" => Ok(json!({ "variables": variables(emulator.get_chip8(), args["variablesReference"].as_u64()) })),
+            "setVariable" => set_variable(emulator.get_chip8_mut(), args),
+            "readMemory" => read_memory(emulator.get_chip8(), args),
+            "writeMemory" => write_memory(emulator.get_chip8_mut(), args),
+            "disassemble" => self.disassemble(emulator.get_chip8(), args),
+            "continue" => {
+                self.debugger.resume();
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "next" => {
+                self.debugger.step_over(emulator.get_chip8());
+                Ok(json!({}))
+            },
+            "stepIn" => {
+                self.debugger.step();
+                Ok(json!({}))
+            },
+            "stepOut" => {
+                self.debugger.step_out(emulator.get_chip8());
+                Ok(json!({}))
+            },
+            "pause" => {
+                self.debugger.pause();
+                Ok(json!({}))
+            },
+            "disconnect" | "terminate" => {
+                emulator.exit_game();
+                Ok(json!({}))
+            },
+            command => Err(format!("Unsupported request '{}'", command))
+        };
+
+        match result {
+            Ok(body) => response(request, body),
+            Err(message) => error_response(request, &message)
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = PathBuf::from(args["source"]["path"].as_str().ok_or("Breakpoints need a source path")?);
+        let lines: Vec<u64> = args["breakpoints"].as_array()
+            .map(|breakpoints| breakpoints.iter().filter_map(|breakpoint| breakpoint["line"].as_u64()).collect())
+            .unwrap_or_default();
+
+        let mut addrs = Vec::new();
+        let breakpoints = lines.iter().map(|&line| {
+            let source_line = self.source_map.as_ref().and_then(|map| map.instruction_at(&path, line as u32));
+            match source_line {
+                Some(source_line) => {
+                    addrs.push(source_line.addr);
+                    json!({ "verified": true, "line": source_line.line, "instructionReference": memory_reference(source_line.addr) })
+                },
+                None => json!({ "verified": false, "line": line, "message": if self.source_map.is_some() {
+                    "No instruction on or after this line in the source map"
+                } else {
+                    "No source map was given to launch"
+                } })
+            }
+        }).collect::<Vec<Value>>();
+
+        self.source_breakpoints.insert(path, addrs);
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let addrs = requested.iter()
+            .map(|breakpoint| address(&breakpoint["instructionReference"], &breakpoint["offset"]))
+            .collect::<Vec<Option<u16>>>();
+
+        self.instruction_breakpoints = addrs.iter().filter_map(|&addr| addr).collect();
+        self.update_breakpoints();
+        let breakpoints = addrs.iter().map(|addr| match addr {
+            Some(addr) => json!({ "verified": true, "instructionReference": memory_reference(*addr) }),
+            None => json!({ "verified": false, "message": "Not an address in memory" })
+        }).collect::<Vec<Value>>();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn update_breakpoints(&mut self) {
+        let addrs = self.source_breakpoints.values().flatten().chain(&self.instruction_breakpoints).cloned().collect::<Vec<u16>>();
+        self.debugger.replace_breakpoints(addrs)
+    }
+
+    fn location(&self, addr: u16) -> Option<&SourceLine> {
+        self.source_map.as_ref().and_then(|map| map.location(addr))
+    }
+
+    /*
+    The innermost frame is at PC, the others at the calls they made. Each is in
+    the subroutine the next frame out called, the outermost in the program.
+    */
+    fn stack_trace(&self, chip8: &Chip8, args: &Value) -> Value {
+        let stack = chip8.get_stack();
+        let addrs = iter::once(chip8.get_pc())
+            .chain(stack.iter().rev().map(|&return_addr| return_addr.wrapping_sub(2)));
+        let names = stack.iter().rev()
+            .map(|&return_addr| format!("sub_{:03X}", word_at(chip8, return_addr.wrapping_sub(2)) & 0xFFF))
+            .chain(iter::once("main".to_string()));
+
+        let start = args["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = args["levels"].as_u64().filter(|&levels| levels > 0).unwrap_or(u64::MAX) as usize;
+        let frames = addrs.zip(names).enumerate().skip(start).take(levels).map(|(id, (addr, name))| {
+            let mut frame = json!({ "id": id, "name": name, "line": 0, "column": 0,
+                "instructionPointerReference": memory_reference(addr) });
+            if let Some(source_line) = self.location(addr) {
+                frame["source"] = source(&source_line.path);
+                frame["line"] = json!(source_line.line);
+                frame["column"] = json!(1)
+            }
+            frame
+        }).collect::<Vec<Value>>();
+        json!({ "stackFrames": frames, "totalFrames": stack.len() + 1 })
+    }
+
+    // Addresses past the ends of memory are listed as invalid, as the client asks for a fixed count
+    fn disassemble(&self, chip8: &Chip8, args: &Value) -> Result<Value, String> {
+        let start = i64::from(address(&args["memoryReference"], &args["offset"]).ok_or("Not an address in memory")?)
+            + 2 * args["instructionOffset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_i64().unwrap_or(0);
+
+        let instructions = (0..count).map(|n| start + 2 * n).map(|addr| {
+            if addr < 0 || addr + 1 >= RAM_BYTES as i64 {
+                return json!({ "address": format!("{}", addr), "instruction": "", "presentationHint": "invalid" })
+            }
+            let word = word_at(chip8, addr as u16);
+            let mut instruction = json!({
+                "address": memory_reference(addr as u16),
+                "instructionBytes": format!("{:02X} {:02X}", word >> 8, word & 0xFF),
+                "instruction": OpcodeDisassembler::disassemble(word).to_string()
+            });
+            if let Some(source_line) = self.location(addr as u16) {
+                instruction["location"] = source(&source_line.path);
+                instruction["line"] = json!(source_line.line)
+            }
+            instruction
+        }).collect::<Vec<Value>>();
+        Ok(json!({ "instructions": instructions }))
+    }
+}
+
+impl DebugSession for DapSession {
+    fn before_cycle(&mut self, emulator: &mut Chip8Emulator) -> bool {
+        self.receive(emulator);
+        let is_running = self.debugger.before_cycle(emulator);
+        self.report_stop();
+        is_running
+    }
+
+    fn after_cycle(&mut self, emulator: &mut Chip8Emulator) {
+        self.debugger.after_cycle(emulator);
+        self.report_stop()
+    }
+
+    fn game_exited(&mut self) {
+        self.send(event("terminated", json!({})));
+        self.send(event("exited", json!({ "exitCode": 0 })))
+    }
+
+    fn is_detached(&self) -> bool {
+        self.is_detached
+    }
+}
+
+fn source(path: &Path) -> Value {
+    json!({
+        "name": path.file_name().map(|name| name.to_string_lossy().into_owned()),
+        "path": path.to_string_lossy()
+    })
+}
+
+pub fn memory_reference(addr: u16) -> String {
+    format!("0x{:03X}", addr)
+}
+
+// A memory reference plus an optional offset, if it's in memory
+fn address(reference: &Value, offset: &Value) -> Option<u16> {
+    let addr = i64::from(parse_number(reference.as_str()?)?) + offset.as_i64().unwrap_or(0);
+    if addr >= 0 && addr < RAM_BYTES as i64 { Some(addr as u16) } else { None }
+}
+
+// Hex with a 0x prefix, otherwise decimal
+fn parse_number(text: &str) -> Option<u32> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok()
+    }
+}
+
+fn byte_value(value: u8) -> String {
+    format!("0x{:02X} ({})", value, value)
+}
+
+fn variable(name: &str, value: String, memory: Option<u16>) -> Value {
+    let mut variable = json!({ "name": name, "value": value, "variablesReference": 0 });
+    if let Some(addr) = memory { variable["memoryReference"] = json!(memory_reference(addr)) }
+    variable
+}
+
+fn variables(chip8: &Chip8, reference: Option<u64>) -> Vec<Value> {
+    match reference {
+        Some(REGISTERS) => chip8.get_registers().iter().enumerate()
+            .map(|(x, &value)| variable(&format!("V{:X}", x), byte_value(value), None))
+            .chain(vec![
+                variable("I", memory_reference(chip8.get_i()), Some(chip8.get_i())),
+                variable("PC", memory_reference(chip8.get_pc()), Some(chip8.get_pc())),
+                variable("SP", byte_value(chip8.get_sp()), None)
+            ])
+            .collect(),
+        Some(TIMERS) => vec![
+            variable("DT", byte_value(chip8.get_delay_timer()), None),
+            variable("ST", byte_value(chip8.get_sound_timer()), None)
+        ],
+        _ => Vec::new()
+    }
+}
+
+fn set_variable(chip8: &mut Chip8, args: &Value) -> Result<Value, String> {
+    let name = args["name"].as_str().unwrap_or("");
+    let value = args["value"].as_str().and_then(parse_number).ok_or("Values are numbers, e.g. 0x1F or 31")?;
+    let byte = || if value <= 0xFF { Ok(value as u8) } else { Err(format!("{} holds a byte", name)) };
+    let addr = || if (value as usize) < RAM_BYTES { Ok(value as u16) } else { Err(format!("{} holds an address", name)) };
+
+    let shown = match name {
+        "I" => { chip8.set_i(addr()?); memory_reference(chip8.get_i()) },
+        "PC" => { chip8.set_pc(addr()?); memory_reference(chip8.get_pc()) },
+        "SP" => { chip8.set_sp(byte()?); byte_value(chip8.get_sp()) },
+        "DT" => { chip8.set_delay_timer(byte()?); byte_value(chip8.get_delay_timer()) },
+        "ST" => { chip8.set_sound_timer(byte()?); byte_value(chip8.get_sound_timer()) },
+        _ => match name.strip_prefix('V').and_then(|x| usize::from_str_radix(x, 16).ok()).filter(|&x| x < 16) {
+            Some(x) => { chip8.set_register(x, byte()?); byte_value(chip8.get_registers()[x]) },
+            None => return Err(format!("No variable '{}'", name))
+        }
+    };
+    Ok(json!({ "value": shown }))
+}
+
+// Bytes past the end of memory are unreadable
+fn read_memory(chip8: &Chip8, args: &Value) -> Result<Value, String> {
+    let addr = address(&args["memoryReference"], &args["offset"]).ok_or("Not an address in memory")? as usize;
+    let count = args["count"].as_u64().unwrap_or(0) as usize;
+    let end = addr.saturating_add(count).min(RAM_BYTES);
+    Ok(json!({
+        "address": memory_reference(addr as u16),
+        "data": base64_encode(&chip8.get_memory()[addr..end]),
+        "unreadableBytes": count - (end - addr)
+    }))
+}
+
+fn write_memory(chip8: &mut Chip8, args: &Value) -> Result<Value, String> {
+    let addr = address(&args["memoryReference"], &args["offset"]).ok_or("Not an address in memory")? as usize;
+    let bytes = args["data"].as_str().and_then(base64_decode).ok_or("Data should be base64")?;
+    if addr + bytes.len() > RAM_BYTES { return Err("The data doesn't fit in memory".to_string()) }
+
+    bytes.iter().enumerate().for_each(|(i, &value)| chip8.write_byte((addr + i) as u16, value));
+    Ok(json!({ "bytesWritten": bytes.len() }))
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &b)| group | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(group >> (18 - 6 * i) & 0x3F) as usize] as char)
+            } else {
+                text.push('=')
+            }
+        }
+    }
+    text
+}
+
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut bytes = Vec::new();
+    let (mut group, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        group = group << 6 | BASE64.iter().position(|&b| b == c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((group >> bits) as u8);
+            group &= (1 << bits) - 1
+        }
+    }
+    Some(bytes)
+}
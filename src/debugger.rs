@@ -0,0 +1,175 @@
+/*
+Pause, step and breakpoint core shared by the debugger front ends, e.g. the
+GDB stub and the DAP server. A front end is a DebugSession the emulator asks
+before and after each instruction; it keeps a Debugger to decide what runs
+and to learn when and why the program stopped, which it reports to its client.
+Breakpoints don't change memory, they stop before the instruction at their
+address runs.
+*/
+
+use std::collections::BTreeSet;
+
+use byteorder::{ByteOrder, BigEndian};
+
+use constants::RAM_BYTES;
+use chip8::Chip8;
+use emulator::Chip8Emulator;
+use opcode::{Opcode, OpcodeDisassembler, Operation::CALL};
+
+pub trait DebugSession {
+    // Handles the client's requests and decides whether the next instruction runs
+    fn before_cycle(&mut self, emulator: &mut Chip8Emulator) -> bool;
+    fn after_cycle(&mut self, emulator: &mut Chip8Emulator);
+    fn game_exited(&mut self);
+    // Detached sessions are dropped and the game runs on freely
+    fn is_detached(&self) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    Entry,
+    Breakpoint,
+    Step,
+    Pause
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RunState {
+    Halted,
+    Running,
+    // Runs one instruction, then halts
+    Stepping,
+    // Runs until a call returns to the instruction after it
+    SteppingOver { pc: u16, sp: u8 },
+    // Runs until the stack is shallower than this
+    SteppingOut { sp: u8 }
+}
+
+pub struct Debugger {
+    state: RunState,
+    breakpoints: BTreeSet<u16>,
+    // Resuming from a breakpoint runs its instruction before checking again
+    is_resuming: bool,
+    // PC and frame before the cycle, to tell whether an instruction ran
+    cycle_start: Option<(u16, u64)>,
+    stop: Option<StopReason>
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    // The program starts halted, so breakpoints can be set before it runs
+    pub fn new() -> Self {
+        Debugger {
+            state: RunState::Halted,
+            breakpoints: BTreeSet::new(),
+            is_resuming: false,
+            cycle_start: None,
+            stop: None
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.state == RunState::Halted
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn replace_breakpoints<I: IntoIterator<Item = u16>>(&mut self, addrs: I) {
+        self.breakpoints = addrs.into_iter().collect()
+    }
+
+    pub fn resume(&mut self) {
+        self.start(RunState::Running)
+    }
+
+    pub fn step(&mut self) {
+        self.start(RunState::Stepping)
+    }
+
+    // Runs a whole subroutine when the next instruction calls one
+    pub fn step_over(&mut self, chip8: &Chip8) {
+        let pc = chip8.get_pc();
+        let state = match OpcodeDisassembler::disassemble(word_at(chip8, pc)) {
+            Opcode(CALL, _) => RunState::SteppingOver { pc: pc.wrapping_add(2), sp: chip8.get_sp() },
+            _ => RunState::Stepping
+        };
+        self.start(state)
+    }
+
+    // Runs until the current subroutine returns, or freely from the top level
+    pub fn step_out(&mut self, chip8: &Chip8) {
+        self.start(RunState::SteppingOut { sp: chip8.get_sp() })
+    }
+
+    pub fn pause(&mut self) {
+        if !self.is_halted() { self.halt(StopReason::Pause) }
+    }
+
+    pub fn halt(&mut self, reason: StopReason) {
+        self.state = RunState::Halted;
+        self.stop = Some(reason)
+    }
+
+    // False while halted, including when a breakpoint halts it now
+    pub fn before_cycle(&mut self, emulator: &Chip8Emulator) -> bool {
+        let chip8 = emulator.get_chip8();
+        if self.is_halted() { return false }
+        if !self.is_resuming && self.breakpoints.contains(&chip8.get_pc()) {
+            self.halt(StopReason::Breakpoint);
+            return false
+        }
+        self.is_resuming = false;
+        self.cycle_start = Some((chip8.get_pc(), emulator.get_frame()));
+        true
+    }
+
+    // Steps only finish once an instruction ran, not while the emulator is
+    // paused or the program waits for a key
+    pub fn after_cycle(&mut self, emulator: &Chip8Emulator) {
+        let chip8 = emulator.get_chip8();
+        let has_run = self.cycle_start.take().is_some_and(|(pc, frame)|
+            chip8.get_pc() != pc || (emulator.get_frame() != frame && !chip8.is_waiting_for_key()));
+        if !has_run { return }
+
+        let is_step_done = match self.state {
+            RunState::Stepping => true,
+            RunState::SteppingOver { pc, sp } => chip8.get_pc() == pc && chip8.get_sp() == sp,
+            RunState::SteppingOut { sp } => chip8.get_sp() < sp,
+            RunState::Halted | RunState::Running => false
+        };
+        if is_step_done { self.halt(StopReason::Step) }
+    }
+
+    // Why the program stopped, once for each stop
+    pub fn take_stop(&mut self) -> Option<StopReason> {
+        self.stop.take()
+    }
+
+    fn start(&mut self, state: RunState) {
+        self.state = state;
+        self.is_resuming = true;
+        self.stop = None
+    }
+}
+
+// The instruction word at an address, wrapping around memory
+pub fn word_at(chip8: &Chip8, addr: u16) -> u16 {
+    let memory = chip8.get_memory();
+    let addr = addr as usize % RAM_BYTES;
+    BigEndian::read_u16(&[memory[addr], memory[(addr + 1) % RAM_BYTES]])
+}
@@ -1,14 +1,34 @@
-use sdl2::Sdl;
-use sdl2::render::WindowCanvas;
+use std::time::Instant;
+use sdl2::{Sdl, VideoSubsystem};
+use sdl2::render::{WindowCanvas, BlendMode};
 use sdl2::rect::Rect;
+use sdl2::pixels::Color;
 
-use constants::{BLACK, WHITE};
+use constants::{BLACK, WHITE, OSD_TEXT, OSD_BACKGROUND, OSD_SCALE,
+    VIEWER_SCALE, VIEWER_I, VIEWER_PC, VIEWER_FLASH, VIEWER_FROZEN, VIEWER_CANDIDATE};
+use osd::{Osd, GLYPH_W, GLYPH_H, text_pixels, text_width};
+use memory_viewer::{MemoryViewer, Highlight, BYTES_PER_ROW, VIEWER_ROWS, SPRITE_ROWS, ascii};
+
+// Memory window layout in characters: the address, hex bytes, ASCII, then the sprite at I
+const CHAR_W: i32 = (GLYPH_W as i32 + 1) * VIEWER_SCALE as i32;
+const ROW_H: i32 = (GLYPH_H as i32 + 2) * VIEWER_SCALE as i32;
+const HEX_COLUMN: i32 = 5;
+const ASCII_COLUMN: i32 = HEX_COLUMN + 3 * BYTES_PER_ROW as i32 + 1;
+const SPRITE_COLUMN: i32 = ASCII_COLUMN + BYTES_PER_ROW as i32 + 2;
+const SPRITE_PIXEL: u32 = 12;
+const VIEWER_HELP: [&str; 2] = [
+    "ARROWS MOVE  0-F EDIT  G GO TO  I/P TO I/PC  Z FREEZE  F2 CLOSE",
+    "S SEARCH  = EQUAL  X CHANGED  U UNCHANGED  M MORE  L LESS  J NEXT"
+];
 
 pub trait Display<T> {
     fn draw_pixels(&mut self);
     fn reset_screen(&mut self);
     fn update_pixels(&mut self, &[T]);
     fn get_pixels(&self) -> &[T];
+    fn get_osd(&mut self) -> &mut Osd;
+    fn set_colours(&mut self, foreground: Color, background: Color);
+    fn draw_menu(&mut self, title: &str, rows: &[(String, bool)], preview: Option<(&[T], (Color, Color))>);
 }
 
 pub struct WindowDisplay<const W: usize, 
@@ -17,7 +37,10 @@ pub struct WindowDisplay<const W: usize,
     const PIXEL_SIZE: u32> 
 {
     canvas: WindowCanvas,
-    pub pixels: [bool; N]
+    pub pixels: [bool; N],
+    foreground: Color,
+    background: Color,
+    osd: Osd
 }
 
 impl <const W: usize, const H: usize, const N: usize, const PIXEL_SIZE: u32> 
@@ -43,20 +66,55 @@ WindowDisplay<W, H, N, PIXEL_SIZE>
         canvas.set_draw_color(BLACK);
         canvas.clear();
         canvas.present();
+        canvas.set_blend_mode(BlendMode::Blend);
         
         WindowDisplay {
             canvas: canvas, 
-            pixels: [false; N]
+            pixels: [false; N],
+            foreground: WHITE,
+            background: BLACK,
+            osd: Osd::new()
         }
     }
+
+    fn draw_osd(&mut self, now: Instant) {
+        let scale = OSD_SCALE as i32;
+        for (row, (text, alpha)) in self.osd.lines(now).iter().enumerate() {
+            let (left, top) = (scale, scale + row as i32 * (GLYPH_H as i32 + 2) * scale);
+
+            // The box fades along with the text
+            let background_alpha = (OSD_BACKGROUND.a as u32 * *alpha as u32 / 0xFF) as u8;
+            self.canvas.set_draw_color(Color { a: background_alpha, ..OSD_BACKGROUND });
+            self.canvas.fill_rect(Rect::new(
+                left - scale,
+                top - scale,
+                (text_width(text) as u32 + 2) * OSD_SCALE,
+                (GLYPH_H as u32 + 2) * OSD_SCALE)
+            ).unwrap();
+
+            self.draw_text(text, left, top, Color { a: *alpha, ..OSD_TEXT })
+        }
+    }
+
+    fn draw_text(&mut self, text: &str, left: i32, top: i32, color: Color) {
+        draw_text(&mut self.canvas, text, left, top, OSD_SCALE, color)
+    }
+}
+
+fn draw_text(canvas: &mut WindowCanvas, text: &str, left: i32, top: i32, scale: u32, color: Color) {
+    let rects = text_pixels(text).into_iter()
+        .map(|(x, y)| Rect::new(left + x as i32 * scale as i32, top + y as i32 * scale as i32, scale, scale))
+        .collect::<Vec<Rect>>();
+    canvas.set_draw_color(color);
+    canvas.fill_rects(&rects).unwrap()
 }
 
 impl <const W: usize, const H: usize, const N: usize, const PIXEL_SIZE: u32> 
 Display<bool> for WindowDisplay<W, H, N, PIXEL_SIZE> {
     fn draw_pixels(&mut self) {
-        self.canvas.set_draw_color(BLACK);
+        self.canvas.set_draw_color(self.background);
         self.canvas.clear();
-        self.canvas.set_draw_color(WHITE);
+        self.canvas.set_draw_color(self.foreground);
         let width: i32 = W as i32;
         
         for (i,v) in self.pixels.iter().enumerate() {
@@ -70,7 +128,10 @@ Display<bool> for WindowDisplay<W, H, N, PIXEL_SIZE> {
                 ).unwrap()
             }
         }
-    
+
+        let now = Instant::now();
+        self.osd.frame_presented(now);
+        self.draw_osd(now);
         self.canvas.present()
     }
 
@@ -86,4 +147,163 @@ Display<bool> for WindowDisplay<W, H, N, PIXEL_SIZE> {
     fn get_pixels(&self) -> &[bool] {
         &self.pixels
     }
+
+    fn get_osd(&mut self) -> &mut Osd {
+        &mut self.osd
+    }
+
+    fn set_colours(&mut self, foreground: Color, background: Color) {
+        self.foreground = foreground;
+        self.background = background
+    }
+
+    // Title and list on the left, selected rows highlighted, preview screen on the right
+    fn draw_menu(&mut self, title: &str, rows: &[(String, bool)], preview: Option<(&[bool], (Color, Color))>) {
+        let scale = OSD_SCALE as i32;
+        let row_height = (GLYPH_H as i32 + 2) * scale;
+        let (window_w, window_h) = (PIXEL_SIZE * W as u32, PIXEL_SIZE * H as u32);
+
+        self.canvas.set_draw_color(BLACK);
+        self.canvas.clear();
+        self.draw_text(title, 2 * scale, 2 * scale, OSD_TEXT);
+
+        for (i, (name, is_selected)) in rows.iter().enumerate() {
+            let top = 2 * scale + (i as i32 + 2) * row_height;
+            let color = if *is_selected {
+                self.canvas.set_draw_color(OSD_TEXT);
+                self.canvas.fill_rect(Rect::new(scale, top - scale, window_w / 2 - 2 * OSD_SCALE, row_height as u32))
+                    .unwrap();
+                BLACK
+            } else {
+                WHITE
+            };
+            self.draw_text(name, 2 * scale, top, color)
+        }
+
+        let preview_pixel = (window_w / 2 - 4 * OSD_SCALE) / W as u32;
+        let (left, top) = ((window_w / 2) as i32 + 2 * scale, ((window_h - preview_pixel * H as u32) / 2) as i32);
+        self.canvas.set_draw_color(OSD_TEXT);
+        self.canvas.draw_rect(Rect::new(left - 1, top - 1,
+            preview_pixel * W as u32 + 2, preview_pixel * H as u32 + 2)).unwrap();
+
+        match preview {
+            Some((pixels, (foreground, background))) => {
+                self.canvas.set_draw_color(background);
+                self.canvas.fill_rect(Rect::new(left, top, preview_pixel * W as u32, preview_pixel * H as u32))
+                    .unwrap();
+                self.canvas.set_draw_color(foreground);
+                for (i, _) in pixels.iter().enumerate().filter(|(_, v)| **v) {
+                    self.canvas.fill_rect(Rect::new(
+                        left + (i % W) as i32 * preview_pixel as i32,
+                        top + (i / W) as i32 * preview_pixel as i32,
+                        preview_pixel,
+                        preview_pixel)
+                    ).unwrap()
+                }
+            },
+            None => self.draw_text("NO PREVIEW", left + 2 * scale, top + 2 * scale, WHITE)
+        }
+
+        self.draw_osd(Instant::now());
+        self.canvas.present()
+    }
+}
+
+// Separate window showing RAM as the memory viewer sees it
+pub struct MemoryWindow {
+    canvas: WindowCanvas
+}
+
+impl MemoryWindow {
+    pub fn new(video_subsystem: &VideoSubsystem) -> Self {
+        let margin = 2 * VIEWER_SCALE;
+        let window = video_subsystem
+            .window("Chip8 Memory",
+                (SPRITE_COLUMN * CHAR_W) as u32 + 8 * SPRITE_PIXEL + 2 * margin,
+                ((VIEWER_ROWS as i32 + 5) * ROW_H) as u32 + 2 * margin)
+            .build().unwrap();
+
+        let mut canvas = window.into_canvas().build().unwrap();
+        canvas.set_blend_mode(BlendMode::Blend);
+        MemoryWindow { canvas }
+    }
+
+    // Events carry this to say which window they're for
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    pub fn draw(&mut self, viewer: &mut MemoryViewer) {
+        let scale = VIEWER_SCALE as i32;
+        let (left, top) = (2 * scale, 2 * scale);
+        let text_at = |column: i32, row: i32| (left + column * CHAR_W, top + row * ROW_H);
+
+        self.canvas.set_draw_color(BLACK);
+        self.canvas.clear();
+        let (x, y) = text_at(0, 0);
+        draw_text(&mut self.canvas, &viewer.status_line(), x, y, VIEWER_SCALE, OSD_TEXT);
+        let header = (0..BYTES_PER_ROW).map(|column| format!("{:2X} ", column)).collect::<String>();
+        let (x, y) = text_at(HEX_COLUMN, 1);
+        draw_text(&mut self.canvas, &header, x, y, VIEWER_SCALE, WHITE);
+
+        for (row, (addr, cells)) in viewer.rows(Instant::now()).iter().enumerate() {
+            let row = row as i32 + 2;
+            let (x, y) = text_at(0, row);
+            draw_text(&mut self.canvas, &format!("{:03X}:", addr), x, y, VIEWER_SCALE, WHITE);
+
+            for (column, cell) in cells.iter().enumerate() {
+                let background = match cell.highlight {
+                    Highlight::None => None,
+                    Highlight::Candidate => Some(VIEWER_CANDIDATE),
+                    Highlight::Frozen => Some(VIEWER_FROZEN),
+                    Highlight::I => Some(VIEWER_I),
+                    Highlight::Pc => Some(VIEWER_PC),
+                    Highlight::Cursor => Some(OSD_TEXT)
+                };
+                let color = if cell.highlight == Highlight::Cursor { BLACK } else { WHITE };
+                let cells = [(HEX_COLUMN + 3 * column as i32, format!("{:02X}", cell.value)),
+                    (ASCII_COLUMN + column as i32, ascii(cell.value).to_string())];
+
+                for (column, text) in cells.iter() {
+                    let (x, y) = text_at(*column, row);
+                    let mark = Rect::new(x - scale, y - scale,
+                        (text_width(text) as u32 + 2) * VIEWER_SCALE, ROW_H as u32);
+                    if let Some(background) = background {
+                        self.canvas.set_draw_color(background);
+                        self.canvas.fill_rect(mark).unwrap()
+                    }
+                    if cell.flash > 0 {
+                        self.canvas.set_draw_color(Color { a: cell.flash, ..VIEWER_FLASH });
+                        self.canvas.fill_rect(mark).unwrap()
+                    }
+                    draw_text(&mut self.canvas, text, x, y, VIEWER_SCALE, color)
+                }
+            }
+        }
+
+        // Drawn like DRW would, lit pixels on a dark box as tall as the tallest sprite
+        let (x, y) = text_at(SPRITE_COLUMN, 1);
+        draw_text(&mut self.canvas, "SPRITE AT I", x, y, VIEWER_SCALE, WHITE);
+        let (x, y) = text_at(SPRITE_COLUMN, 2);
+        self.canvas.set_draw_color(OSD_TEXT);
+        self.canvas.draw_rect(Rect::new(x - 1, y - 1, 8 * SPRITE_PIXEL + 2, SPRITE_ROWS as u32 * SPRITE_PIXEL + 2))
+            .unwrap();
+        let pixels = viewer.sprite().iter().enumerate()
+            .flat_map(|(row, pixels)| pixels.iter().enumerate()
+                .filter(|(_, is_lit)| **is_lit)
+                .map(move |(column, _)| Rect::new(
+                    x + column as i32 * SPRITE_PIXEL as i32,
+                    y + row as i32 * SPRITE_PIXEL as i32,
+                    SPRITE_PIXEL,
+                    SPRITE_PIXEL)))
+            .collect::<Vec<Rect>>();
+        self.canvas.set_draw_color(WHITE);
+        self.canvas.fill_rects(&pixels).unwrap();
+
+        for (row, help) in VIEWER_HELP.iter().enumerate() {
+            let (x, y) = text_at(0, VIEWER_ROWS as i32 + 3 + row as i32);
+            draw_text(&mut self.canvas, help, x, y, VIEWER_SCALE, OSD_TEXT)
+        }
+        self.canvas.present()
+    }
 }
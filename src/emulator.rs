@@ -2,118 +2,555 @@
 use byteorder::{ByteOrder, BigEndian};
 
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
+use std::mem;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
 use memory::Memory;
-use chip8::Chip8;
-use io::IO;
-use constants::{ROM_ADDR};
+use chip8::{Chip8, Engine, Quirks};
+use constants::{ROM_ADDR, RAM_BYTES};
 use opcode::OpcodeDisassembler;
-use command::{Command::{self}, CommandEmulator, GameCommand::*};
-use router::Router;
+use command::{Command::{self}, CommandEmulator, GameCommand::*, KeyCommand::{self, KeyDownUp}, Topic,
+    MemoryCommand::{SendRAM, SendByte, SendPointers},
+    DisplayCommand::{SendStatus, SendMessage}};
+use bus::{EventBus, ComponentId, Tracer};
+use movie::{Movie, MoviePlayer, rom_hash};
+use rng::RandomSource;
+use pacing::{Pacer, RateMeter, CYCLES_PER_SECOND, FAST_FORWARD_MULTIPLIER};
+use osd::Status;
+use rom_loader::RomError;
+use debugger::DebugSession;
+use script::{Script, ScriptError};
+use cheats::{Cheat, CheatTarget};
+use library::Quirk;
+
+// Bus ids of the emulator and its built in components, in registration order
+const EMULATOR: ComponentId = 0;
+const MEMORY: ComponentId = 1;
+const IO: ComponentId = 2;
+const CHIP8: ComponentId = 3;
+
+// How often input is polled while paused
+const PAUSED_POLL: Duration = Duration::from_millis(10);
+
+// Frame advance and scripts' frame hooks go by 60Hz video frames worth of cycles
+const VIDEO_FPS: u32 = 60;
 
 pub struct Chip8Emulator {
-    io: IO,
+    io: Box<dyn CommandEmulator>,
     memory: Memory,
     chip8: Chip8,
+    components: Vec<(ComponentId, Box<dyn CommandEmulator>)>,
     running_flag: bool,
-    router_bridge: Router<Command>
+    bus: EventBus,
+    frame: u64,
+    pacer: Pacer,
+    cycle_meter: RateMeter,
+    fast_forward_multiplier: u32,
+    is_paused: bool,
+    advance_cycles: u32,
+    movie_recording: Option<(String, Movie)>,
+    movie_player: Option<MoviePlayer>,
+    profile_path: Option<String>,
+    debugger: Option<Box<dyn DebugSession>>,
+    script: Option<Script>,
+    // Writes since the script's write hooks last ran
+    script_writes: Vec<(u16, u8)>,
+    script_error: Option<ScriptError>,
+    // The front end's memory window is open
+    is_memory_shown: bool,
+    cheats: Vec<Cheat>,
+    // SHA-1 of the loaded ROM, which cheat files are keyed by
+    rom_hash: String
 }
 
 impl Chip8Emulator {
-    pub fn new() -> Self {
+    pub fn new(io: Box<dyn CommandEmulator>, rng: Box<dyn RandomSource>) -> Self {
+        let mut bus = EventBus::new();
+        ["emulator", "memory", "io", "chip8"].iter().for_each(|name| { bus.register(name); });
+
+        // Keys go through the emulator so movies can record or replace them
+        bus.subscribe(EMULATOR, Topic::GameState);
+        bus.subscribe(EMULATOR, Topic::Key);
+        bus.subscribe(MEMORY, Topic::Memory);
+        bus.subscribe(IO, Topic::Display);
+        bus.subscribe(IO, Topic::Audio);
+        bus.subscribe(CHIP8, Topic::Memory);
+        bus.subscribe(CHIP8, Topic::Display);
+
         Chip8Emulator {
-            io: IO::new(),
+            io,
             memory: Memory::new(),
-            chip8: Chip8::new(),
+            chip8: Chip8::new(rng),
+            components: Vec::new(),
             running_flag: true,
-            router_bridge: Router::new(),
+            bus,
+            frame: 0,
+            pacer: Pacer::new(CYCLES_PER_SECOND),
+            cycle_meter: RateMeter::new(Instant::now()),
+            fast_forward_multiplier: FAST_FORWARD_MULTIPLIER,
+            is_paused: false,
+            advance_cycles: 0,
+            movie_recording: None,
+            movie_player: None,
+            profile_path: None,
+            debugger: None,
+            script: None,
+            script_writes: Vec::new(),
+            script_error: None,
+            is_memory_shown: false,
+            cheats: Vec::new(),
+            rom_hash: String::new()
         }
     }
 
-    pub fn start_game(&mut self, rom_path: &str) {
-        let rom_bytes = self.get_rom_bytes(rom_path);
-        self.memory.load_font_sprites();
-        self.memory.load_rom(&rom_bytes);
-        self.disassemble_code(&rom_bytes);
+    // Adds a component that runs after the CPU every frame. It receives the
+    // commands published on its topics and can publish its own, e.g. keys.
+    pub fn add_component(&mut self, name: &'static str, component: Box<dyn CommandEmulator>,
+        topics: &[Topic]) -> ComponentId
+    {
+        let id = self.bus.register(name);
+        topics.iter().for_each(|&topic| self.bus.subscribe(id, topic));
+        self.components.push((id, component));
+        id
+    }
 
-        while self.running_flag {            
-            {
-                let Chip8Emulator {memory, router_bridge, ..} = self;
-                Chip8Emulator::simulate_component(
-                    Box::new(memory), 
-                    Box::new(router_bridge), 
-                    false);
+    pub fn set_pacing(&mut self, cycles_per_second: u32, fast_forward_multiplier: u32) {
+        self.pacer = Pacer::new(cycles_per_second);
+        self.fast_forward_multiplier = fast_forward_multiplier
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.bus.set_tracer(tracer)
+    }
+
+    // The RNG given to the emulator must be seeded with rng_seed for the movie to replay
+    pub fn record_movie(&mut self, movie_path: &str, rng_seed: u64) {
+        self.movie_recording = Some((movie_path.to_string(), Movie::new("", rng_seed)));
+        self.show_message("Recording movie")
+    }
+
+    pub fn replay_movie(&mut self, movie: Movie) {
+        self.movie_player = Some(MoviePlayer::new(movie));
+        self.show_message("Replaying movie")
+    }
+
+    // The report is written to profile_path when the game exits
+    pub fn profile_game(&mut self, profile_path: &str) {
+        self.profile_path = Some(profile_path.to_string());
+        self.chip8.start_profiling()
+    }
+
+    // A debugger, e.g. GDB, controls when instructions run until it detaches
+    pub fn attach_debugger(&mut self, debugger: Box<dyn DebugSession>) {
+        self.debugger = Some(debugger)
+    }
+
+    // The script starts with the game, its hooks then run as it plays
+    pub fn attach_script(&mut self, script: Script) {
+        self.bus.subscribe(EMULATOR, Topic::Memory);
+        self.script = Some(script)
+    }
+
+    // Why the script ended the game, if it failed
+    pub fn script_error(&self) -> Option<&ScriptError> {
+        self.script_error.as_ref()
+    }
+
+    // Cheats hold their targets from the first instruction on
+    pub fn enable_cheats(&mut self, cheats: &[Cheat]) {
+        for cheat in cheats {
+            eprintln!("Cheat on: {}", cheat.name);
+            self.show_message(&format!("Cheat on: {}", cheat.name));
+            for &(target, value) in &cheat.freezes {
+                if let CheatTarget::Memory(addr) = target { self.chip8.freeze(addr, Some(value)) }
             }
-            self.route_to_components();
+            self.cheats.push(cheat.clone())
+        }
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.chip8.set_engine(engine)
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.chip8.set_quirks(quirks)
+    }
+
+    pub fn start_game(&mut self, rom_path: &str) -> Result<(), RomError> {
+        let rom_bytes = self.get_rom_bytes(rom_path)?;
+        self.run_game(&rom_bytes)
+    }
 
+    // Plays ROM bytes read elsewhere, e.g. from an archive, until the game exits
+    pub fn run_game(&mut self, rom_bytes: &[u8]) -> Result<(), RomError> {
+        self.load_game(rom_bytes)?;
+        self.disassemble_code(rom_bytes);
+        self.play(rom_bytes);
+        Ok(())
+    }
+
+    // Plays the game loaded from these ROM bytes until it exits
+    pub fn play(&mut self, rom_bytes: &[u8]) {
+        self.run_script(Script::start);
+        while self.running_flag {
+            if self.debugger.is_some() { self.run_debugged_frame() } else { self.run_paced_frame() }
+        }
+
+        if let Some(ref mut debugger) = self.debugger { debugger.game_exited() }
+        self.save_movie_recording();
+        self.save_profile(rom_bytes);
+    }
+
+    pub fn load_game(&mut self, rom_bytes: &[u8]) -> Result<(), RomError> {
+        self.memory.load_font_sprites();
+        self.memory.load_rom(rom_bytes)?;
+        let hash = rom_hash(rom_bytes);
+        self.check_movie_rom(&hash);
+        self.check_movie_quirks();
+        self.rom_hash = hash;
+        Ok(())
+    }
+
+    // Commands published by a component are delivered before the next one runs
+    pub fn run_frame(&mut self) {
+        Chip8Emulator::simulate_component(&mut self.memory, MEMORY, &mut self.bus);
+        self.deliver_commands();
+
+        Chip8Emulator::simulate_component(&mut *self.io, IO, &mut self.bus);
+        self.deliver_commands();
+        self.replay_movie_keys();
+
+        if self.script.is_some() && !self.chip8.is_waiting_for_key() { self.run_script(Script::before_instruction) }
+        Chip8Emulator::simulate_component(&mut self.chip8, CHIP8, &mut self.bus);
+        self.deliver_commands();
+        // Undoes the instruction's changes to frozen registers before the next one runs
+        for cheat in &self.cheats { cheat.apply_registers(&mut self.chip8) }
+        if !self.script_writes.is_empty() {
+            let writes = mem::take(&mut self.script_writes);
+            self.run_script(|script, emulator| script.after_writes(emulator, &writes))
+        }
+
+        for i in 0..self.components.len() {
             {
-                let Chip8Emulator {io, router_bridge, ..} = self;
-                Chip8Emulator::simulate_component(
-                    Box::new(io), 
-                    Box::new(router_bridge), 
-                    false);
+                let Chip8Emulator {components, bus, ..} = self;
+                let (id, ref mut component) = components[i];
+                Chip8Emulator::simulate_component(&mut **component, id, bus);
             }
-            self.route_to_components();
+            self.deliver_commands();
+        }
+        self.frame += 1;
+        if self.frame.is_multiple_of(self.cycles_per_video_frame()) { self.run_script(Script::after_frame) }
+        if self.is_memory_shown { self.send_pointers() }
+    }
 
-            {
-                let Chip8Emulator {chip8, router_bridge, ..} = self;
-                Chip8Emulator::simulate_component(
-                    Box::new(chip8), 
-                    Box::new(router_bridge), 
-                    true);
+    // A failing script ends the game
+    fn run_script<F: FnOnce(&mut Script, &mut Chip8Emulator) -> Result<(), ScriptError>>(&mut self, run: F) {
+        if let Some(mut script) = self.script.take() {
+            match run(&mut script, self) {
+                Ok(()) => self.script = Some(script),
+                Err(e) => {
+                    eprintln!("Script error: {}", e);
+                    self.script_error = Some(e);
+                    self.exit_game()
+                }
             }
-            self.route_to_components();
         }
     }
 
-    fn simulate_component<'a>(
-        component: Box<&'a mut dyn CommandEmulator>, 
-        router_bridge: Box<&'a mut Router<Command>>, 
-        is_chip8_routing: bool) 
-    {
+    // Runs this many frames, a cycle each. Unless something has to run between
+    // instructions, the CPU runs them in one go, see Chip8::run_cycles.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        if !self.is_batchable() { return (0..cycles).for_each(|_| self.run_frame()) }
+
+        Chip8Emulator::simulate_component(&mut self.memory, MEMORY, &mut self.bus);
+        self.deliver_commands();
+        Chip8Emulator::simulate_component(&mut *self.io, IO, &mut self.bus);
+        self.deliver_commands();
+
+        self.chip8.process_inbound_commands();
+        self.chip8.run_cycles(cycles);
+        let Chip8Emulator {chip8, bus, ..} = self;
+        chip8.get_commands().consume_all_outbound().into_iter().for_each(|c| bus.publish(CHIP8, c));
+        self.deliver_commands();
+
+        self.frame += cycles;
+        if self.is_memory_shown { self.send_pointers() }
+    }
+
+    // Scripts, cheats, movie playback and added components act
+    // between instructions, so frames are run one at a time while they're on
+    fn is_batchable(&self) -> bool {
+        self.script.is_none() && self.debugger.is_none() && self.movie_player.is_none()
+            && self.cheats.is_empty() && self.components.is_empty()
+    }
+
+    // Runs a video frame's cycles in real time, or only polls input while paused
+    pub fn run_paced_frame(&mut self) {
+        let cycles = if self.is_batchable() { self.cycles_per_video_frame() } else { 1 };
+        self.run_paced_cycles(cycles)
+    }
+
+    fn run_paced_cycles(&mut self, cycles: u64) {
+        if self.is_paused && self.advance_cycles == 0 {
+            Chip8Emulator::simulate_component(&mut *self.io, IO, &mut self.bus);
+            self.deliver_commands();
+            sleep(PAUSED_POLL);
+            return
+        }
+
+        let cycles = if self.is_paused { cycles.min(self.advance_cycles as u64) } else { cycles };
+        self.advance_cycles -= cycles.min(self.advance_cycles as u64) as u32;
+        self.run_cycles(cycles);
+        if let Some(cycles_per_second) = self.cycle_meter.tick_by(cycles as u32, Instant::now()) {
+            self.publish_status(cycles_per_second)
+        }
+        self.pacer.wait_cycles(cycles)
+    }
+
+    // Like a paused frame while the debugger has the program halted
+    fn run_debugged_frame(&mut self) {
+        let mut debugger = self.debugger.take().expect("a debugger should be attached");
+        // A cycle at a time, the debugger checks each instruction
+        if debugger.before_cycle(self) {
+            self.run_paced_cycles(1);
+            debugger.after_cycle(self)
+        } else {
+            Chip8Emulator::simulate_component(&mut *self.io, IO, &mut self.bus);
+            self.deliver_commands();
+            sleep(PAUSED_POLL)
+        }
+
+        if !debugger.is_detached() {
+            self.debugger = Some(debugger)
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running_flag
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    pub fn get_frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Video frames run so far, at the pacing's speed
+    pub fn get_video_frame(&self) -> u64 {
+        self.frame / self.cycles_per_video_frame()
+    }
+
+    fn cycles_per_video_frame(&self) -> u64 {
+        (self.pacer.cycles_per_second() / VIDEO_FPS).max(1) as u64
+    }
+
+    pub fn get_pixels(&self) -> &[bool] {
+        self.chip8.get_pixels()
+    }
+
+    // The CPU's state and its copy of memory, for debuggers
+    pub fn get_chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn get_chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    fn simulate_component(component: &mut dyn CommandEmulator, id: ComponentId, bus: &mut EventBus) {
         component.process_inbound_commands();
         component.emulate_cycle();
 
-        if is_chip8_routing {
-            (*component).get_commands().forward_outbound(*router_bridge)
-        } else {        
-            (*component).get_commands().forward_inbound(*router_bridge)
-        }
+        component.get_commands()
+            .consume_all_outbound()
+            .into_iter()
+            .for_each(|c| bus.publish(id, c))
     }
 
-    fn route_to_components(&mut self) {
-        self.router_bridge.consume_all_inbound().into_iter().for_each(|c| {
+    fn deliver_commands(&mut self) {
+        self.bus.take(EMULATOR).into_iter().for_each(|c| {
             match c {
                 Command::GameState(Exit) => self.exit_game(),
-                _ => self.chip8.get_commands().send_inbound(c)
+                Command::GameState(FastForward(is_held)) => {
+                    let multiplier = if is_held { self.fast_forward_multiplier } else { 1 };
+                    if is_held { self.show_message(&format!("Fast forward {}x", multiplier)) }
+                    self.pacer.set_multiplier(multiplier, Instant::now())
+                },
+                Command::GameState(TogglePause) => {
+                    self.is_paused = !self.is_paused;
+                    self.advance_cycles = 0;
+                    self.pacer.restart(Instant::now());
+                    self.cycle_meter.restart(Instant::now());
+                    // Shown until the next measurement after resuming
+                    let cycles_per_second = if self.is_paused { 0 } else { self.pacer.cycles_per_second() };
+                    self.publish_status(cycles_per_second)
+                },
+                Command::GameState(FrameAdvance) if self.is_paused => {
+                    self.advance_cycles += self.cycles_per_video_frame() as u32
+                },
+                Command::GameState(ShowMemory(is_shown)) => self.show_memory(is_shown),
+                Command::GameState(Freeze(addr, value)) => self.freeze(addr, value),
+                Command::Key(key) => self.route_key(key),
+                Command::Memory(SendByte(addr, value)) if self.script.as_ref().is_some_and(Script::is_watching_writes) =>
+                    self.script_writes.push((addr, value)),
+                _ => {}
             }
         });
 
-        self.router_bridge.consume_all_outbound().into_iter().for_each(|c| {
-            match c {
-                Command::GameState(Exit) => self.exit_game(),
-                Command::Display(_)
-                | Command::Audio(_)
-                | Command::Key(_) => self.io.get_commands().send_inbound(c),
-                Command::Memory(_) => self.memory.get_commands().send_inbound(c)
+        let Chip8Emulator {io, memory, chip8, components, bus, ..} = self;
+        Chip8Emulator::deliver_to(memory, MEMORY, bus);
+        Chip8Emulator::deliver_to(&mut **io, IO, bus);
+        Chip8Emulator::deliver_to(chip8, CHIP8, bus);
+        components.iter_mut().for_each(|(id, component)|
+            Chip8Emulator::deliver_to(&mut **component, *id, bus))
+    }
+
+    // Status and messages are published on the display topic for the on-screen display
+    fn publish_status(&mut self, cycles_per_second: u32) {
+        let status = Status { cycles_per_second, is_paused: self.is_paused };
+        self.bus.publish(EMULATOR, Command::Display(SendStatus(status)))
+    }
+
+    fn show_message(&mut self, text: &str) {
+        self.bus.publish(EMULATOR, Command::Display(SendMessage(text.to_string())))
+    }
+
+    /*
+    While the memory window is open the IO is sent the CPU's RAM once, then the
+    bytes written as they're published, and I and PC after every frame.
+    */
+    fn show_memory(&mut self, is_shown: bool) {
+        if is_shown == self.is_memory_shown { return }
+        self.is_memory_shown = is_shown;
+        if is_shown {
+            self.bus.subscribe(IO, Topic::Memory);
+            let mut ram = Box::new([0; RAM_BYTES]);
+            ram.copy_from_slice(self.chip8.get_memory());
+            self.io.get_commands().send_inbound(Command::Memory(SendRAM(ram)));
+            self.send_pointers()
+        } else {
+            self.bus.unsubscribe(IO, Topic::Memory)
+        }
+    }
+
+    // Freezes from the memory window are cheats of their own, printed ready for a cheat file
+    fn freeze(&mut self, addr: u16, value: Option<u8>) {
+        let target = CheatTarget::Memory(addr);
+        self.cheats.retain(|cheat| !(cheat.freezes.len() == 1 && cheat.freezes[0].0 == target));
+        match value {
+            Some(value) => {
+                let name = format!("Freeze {:03X}", addr);
+                eprintln!("{} at {:02X}, in a cheat file:\n[{}]\ncheat {}\nfreeze {} {:02x}",
+                    name, value, self.rom_hash, name, target, value);
+                self.show_message(&format!("{} at {:02X}", name, value));
+                self.cheats.push(Cheat { name, freezes: vec![(target, value)] })
+            },
+            None => self.show_message(&format!("Unfroze {:03X}", addr))
+        }
+
+        // A cheat from the cheat file can still hold the byte, the newest wins
+        let held = self.cheats.iter().rev()
+            .flat_map(|cheat| cheat.freezes.iter().rev())
+            .find(|(frozen, _)| *frozen == target)
+            .map(|&(_, value)| value);
+        self.chip8.freeze(addr, held)
+    }
+
+    fn send_pointers(&mut self) {
+        let pointers = SendPointers(self.chip8.get_i(), self.chip8.get_pc());
+        self.io.get_commands().send_inbound(Command::Memory(pointers))
+    }
+
+    fn deliver_to(component: &mut dyn CommandEmulator, id: ComponentId, bus: &mut EventBus) {
+        bus.drain(id).for_each(|c| component.get_commands().send_inbound(c))
+    }
+
+    pub fn press_key(&mut self, key_i: usize, is_down: bool) {
+        self.route_key(KeyDownUp(key_i, is_down))
+    }
+
+    fn route_key(&mut self, key: KeyCommand) {
+        // Live input is ignored while a movie drives the keypad
+        if self.movie_player.is_some() { return }
+
+        if let Some((_, ref mut movie)) = self.movie_recording {
+            movie.record(self.frame, key)
+        }
+        self.chip8.get_commands().send_inbound(Command::Key(key))
+    }
+
+    fn replay_movie_keys(&mut self) {
+        if let Some(ref mut player) = self.movie_player {
+            for key in player.events_at(self.frame) {
+                self.chip8.get_commands().send_inbound(Command::Key(key))
             }
-        });
+        }
+    }
+
+    fn check_movie_rom(&mut self, hash: &str) {
+        if let Some((_, ref mut movie)) = self.movie_recording {
+            movie.rom_hash = hash.to_string()
+        }
+
+        let movie_hash = self.movie_player.as_ref().map(|player| player.movie().rom_hash.clone());
+        if let Some(movie_hash) = movie_hash.filter(|movie_hash| movie_hash != hash) {
+            eprintln!("Warning: movie was recorded on a different ROM ({}), \
+                playback may desync.", movie_hash);
+            self.show_message("Movie was recorded on a different ROM")
+        }
+    }
+
+    // Quirks are set before the game loads, so they're the ones it runs with
+    fn check_movie_quirks(&mut self) {
+        let quirks = self.chip8.get_quirks();
+        if let Some((_, ref mut movie)) = self.movie_recording {
+            movie.quirks = quirks.enabled()
+        }
+
+        let movie_quirks = self.movie_player.as_ref().map(|player| player.movie().quirks.clone());
+        if let Some(movie_quirks) = movie_quirks.filter(|movie_quirks| Quirks::new(movie_quirks) != quirks) {
+            let names = movie_quirks.iter().map(Quirk::name).collect::<Vec<&str>>();
+            eprintln!("Warning: movie was recorded with different quirks ({}), \
+                playback may desync.", if names.is_empty() { "none".to_string() } else { names.join(", ") });
+            self.show_message("Movie was recorded with different quirks")
+        }
+    }
+
+    fn save_movie_recording(&mut self) {
+        if let Some((path, movie)) = self.movie_recording.take() {
+            match movie.save(&path) {
+                Ok(()) => eprintln!("Movie saved to '{}'.", path),
+                Err(e) => eprintln!("Failed to save movie '{}': {}", path, e)
+            }
+        }
+    }
+
+    fn save_profile(&mut self, rom_bytes: &[u8]) {
+        if let (Some(path), Some(profile)) = (self.profile_path.take(), self.chip8.take_profile()) {
+            match profile.save(&path, rom_bytes) {
+                Ok(()) => eprintln!("Profile saved to '{}'.", path),
+                Err(e) => eprintln!("Failed to save profile '{}': {}", path, e)
+            }
+        }
     }
 
-    fn exit_game(&mut self) {
+    pub fn exit_game(&mut self) {
         self.running_flag = false
     }
 
-    pub fn get_rom_bytes(&mut self, rom_path: &str) -> Vec<u8> {
+    pub fn get_rom_bytes(&mut self, rom_path: &str) -> io::Result<Vec<u8>> {
         let mut rom_buf: Vec<u8> = Vec::new();
-        let mut file = File::open(&rom_path).unwrap();
-        file.read_to_end(&mut rom_buf).unwrap();
+        let mut file = File::open(rom_path)?;
+        file.read_to_end(&mut rom_buf)?;
         
-        rom_buf
+        Ok(rom_buf)
     }
 
-    pub fn disassemble_code(&mut self, rom_bytes: &Vec<u8>) {
+    pub fn disassemble_code(&mut self, rom_bytes: &[u8]) {
         println!("Disassembling code: \n");
 
         (0..rom_bytes.len()/2)
@@ -0,0 +1,327 @@
+/*
+GDB remote serial protocol stub, serving one client over TCP on loopback.
+
+Registers, numbered for p/P and in this order for g/G, in big endian hex:
+0-15  V0-VF  1 byte
+16    I      2 bytes
+17    PC     2 bytes
+18    SP     1 byte
+19    DT     1 byte
+20    ST     1 byte
+
+Supported packets: ? g G p P m M c s Z0 z0 D k, qSupported and qAttached.
+Others get the empty reply, which tells GDB they aren't supported.
+*/
+
+use std::io::{self, Read, Write, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+
+use constants::RAM_BYTES;
+use chip8::Chip8;
+use emulator::Chip8Emulator;
+use debugger::{DebugSession, Debugger, StopReason};
+
+const INTERRUPT: u8 = 0x03;
+const REGISTERS: usize = 21;
+const PACKET_SIZE: usize = 0x1000;
+
+// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+pub struct GdbStub {
+    stream: TcpStream,
+    debugger: Debugger,
+    is_detached: bool,
+    received: Vec<u8>,
+    last_sent: Vec<u8>
+}
+
+impl GdbStub {
+    // Waits for GDB to connect, e.g. with 'target remote :<port>'
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for GDB on 127.0.0.1:{}", listener.local_addr()?.port());
+        let (stream, _) = listener.accept()?;
+        GdbStub::new(stream)
+    }
+
+    // The program starts halted, so breakpoints can be set before it runs
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            stream,
+            debugger: Debugger::new(),
+            is_detached: false,
+            received: Vec::new(),
+            last_sent: Vec::new()
+        })
+    }
+
+    // Tells GDB about a stop, once
+    fn report_stop(&mut self) {
+        let signal = match self.debugger.take_stop() {
+            Some(StopReason::Pause) => SIGINT,
+            Some(_) => SIGTRAP,
+            None => return
+        };
+        let _ = self.send(&format!("S{:02x}", signal));
+    }
+
+    fn receive(&mut self, emulator: &mut Chip8Emulator) -> io::Result<()> {
+        let mut buf = [0; PACKET_SIZE];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "GDB disconnected")),
+                Ok(n) => self.received.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        }
+
+        while let Some(&byte) = self.received.first() {
+            match byte {
+                b'$' => match decode_packet(&self.received) {
+                    Some((packet, len)) => {
+                        self.received.drain(..len);
+                        match packet {
+                            Some(packet) => {
+                                self.stream.write_all(b"+")?;
+                                let reply = self.handle(&packet, emulator);
+                                if let Some(reply) = reply { self.send(&reply)? }
+                            },
+                            None => self.stream.write_all(b"-")?
+                        }
+                    },
+                    None => break
+                },
+                b'-' => {
+                    self.received.remove(0);
+                    let last_sent = self.last_sent.clone();
+                    self.stream.write_all(&last_sent)?
+                },
+                INTERRUPT => {
+                    self.received.remove(0);
+                    self.debugger.pause();
+                    self.report_stop()
+                },
+                // Acknowledgements and noise between packets
+                _ => { self.received.remove(0); }
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        self.last_sent = encode_packet(data);
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(&self.last_sent);
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+
+    // The reply to a packet, None for packets answered later by a stop reply
+    fn handle(&mut self, packet: &str, emulator: &mut Chip8Emulator) -> Option<String> {
+        let (command, args) = (packet.get(..1).unwrap_or(""), packet.get(1..).unwrap_or(""));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => read_registers(emulator.get_chip8()),
+            "G" => ok_or_error(write_registers(emulator.get_chip8_mut(), args)),
+            "p" => value_or_error(parse_hex(args).and_then(|n| read_register(emulator.get_chip8(), n as usize))),
+            "P" => ok_or_error(write_register_arg(emulator.get_chip8_mut(), args)),
+            "m" => value_or_error(read_memory(emulator.get_chip8(), args)),
+            "M" => ok_or_error(write_memory(emulator.get_chip8_mut(), args)),
+            "c" | "s" => {
+                if let Some(addr) = parse_hex(args) { emulator.get_chip8_mut().set_pc(addr as u16) }
+                if command == "c" { self.debugger.resume() } else { self.debugger.step() }
+                return None
+            },
+            "Z" | "z" => match parse_breakpoint(args) {
+                Some(addr) => {
+                    if command == "Z" { self.debugger.add_breakpoint(addr) } else { self.debugger.remove_breakpoint(addr) }
+                    "OK".to_string()
+                },
+                None => String::new()
+            },
+            "D" => {
+                println!("GDB detached.");
+                self.is_detached = true;
+                "OK".to_string()
+            },
+            "k" => {
+                self.is_detached = true;
+                emulator.exit_game();
+                return None
+            },
+            "H" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => format!("PacketSize={:x}", PACKET_SIZE),
+            "q" if args == "Attached" => "1".to_string(),
+            _ => String::new()
+        };
+        Some(reply)
+    }
+}
+
+impl DebugSession for GdbStub {
+    /*
+    Handles what GDB sent and decides whether the next instruction runs. False
+    while halted, e.g. at a breakpoint, so the emulator only polls input.
+    */
+    fn before_cycle(&mut self, emulator: &mut Chip8Emulator) -> bool {
+        if let Err(e) = self.receive(emulator) {
+            println!("GDB connection lost: {}", e);
+            self.is_detached = true
+        }
+        if self.is_detached { return true }
+
+        let is_running = self.debugger.before_cycle(emulator);
+        self.report_stop();
+        is_running
+    }
+
+    fn after_cycle(&mut self, emulator: &mut Chip8Emulator) {
+        self.debugger.after_cycle(emulator);
+        self.report_stop()
+    }
+
+    fn game_exited(&mut self) {
+        if !self.is_detached {
+            let _ = self.send("W00");
+        }
+    }
+
+    fn is_detached(&self) -> bool {
+        self.is_detached
+    }
+}
+
+// "$<data>#<checksum>", the checksum being the sum of the data bytes
+pub fn encode_packet(data: &str) -> Vec<u8> {
+    format!("${}#{:02x}", data, checksum(data.as_bytes())).into_bytes()
+}
+
+// The packet at the start of the bytes and its length, None until it's all
+// there. The packet is None when its checksum is wrong.
+pub fn decode_packet(bytes: &[u8]) -> Option<(Option<String>, usize)> {
+    let end = bytes.iter().position(|&b| b == b'#')?;
+    if bytes.len() < end + 3 { return None }
+
+    let data = &bytes[1..end];
+    let is_valid = String::from_utf8_lossy(&bytes[end + 1..end + 3]).to_lowercase()
+        == format!("{:02x}", checksum(data));
+    let packet = if is_valid { Some(String::from_utf8_lossy(data).into_owned()) } else { None };
+    Some((packet, end + 3))
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+fn read_registers(chip8: &Chip8) -> String {
+    (0..REGISTERS).filter_map(|n| read_register(chip8, n)).collect()
+}
+
+fn read_register(chip8: &Chip8, n: usize) -> Option<String> {
+    Some(match n {
+        0..=15 => format!("{:02x}", chip8.get_registers()[n]),
+        16 => format!("{:04x}", chip8.get_i()),
+        17 => format!("{:04x}", chip8.get_pc()),
+        18 => format!("{:02x}", chip8.get_sp()),
+        19 => format!("{:02x}", chip8.get_delay_timer()),
+        20 => format!("{:02x}", chip8.get_sound_timer()),
+        _ => return None
+    })
+}
+
+fn register_digits(n: usize) -> usize {
+    if n == 16 || n == 17 { 4 } else { 2 }
+}
+
+fn write_register(chip8: &mut Chip8, n: usize, value: u16) -> Option<()> {
+    match n {
+        0..=15 => chip8.set_register(n, value as u8),
+        16 => chip8.set_i(value),
+        17 => chip8.set_pc(value),
+        18 => chip8.set_sp(value as u8),
+        19 => chip8.set_delay_timer(value as u8),
+        20 => chip8.set_sound_timer(value as u8),
+        _ => return None
+    }
+    Some(())
+}
+
+fn write_registers(chip8: &mut Chip8, hex: &str) -> Option<()> {
+    if !is_hex(hex) { return None }
+    let mut rest = hex;
+    for n in 0..REGISTERS {
+        let digits = register_digits(n);
+        if rest.len() < digits { return None }
+        let (value, next) = rest.split_at(digits);
+        write_register(chip8, n, u16::from_str_radix(value, 16).ok()?)?;
+        rest = next
+    }
+    Some(())
+}
+
+// "<n>=<value>"
+fn write_register_arg(chip8: &mut Chip8, args: &str) -> Option<()> {
+    let mut parts = args.splitn(2, '=');
+    let n = parse_hex(parts.next()?)? as usize;
+    let value = parts.next()?;
+    if value.len() != register_digits(n) { return None }
+    write_register(chip8, n, u16::from_str_radix(value, 16).ok()?)
+}
+
+// "<addr>,<length>", not empty: an empty reply tells GDB the packet isn't supported
+fn read_memory(chip8: &Chip8, args: &str) -> Option<String> {
+    let (addr, len) = parse_range(args).filter(|&(_, len)| len > 0)?;
+    Some(chip8.get_memory()[addr..addr + len].iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// "<addr>,<length>:<bytes>"
+fn write_memory(chip8: &mut Chip8, args: &str) -> Option<()> {
+    let mut parts = args.splitn(2, ':');
+    let (addr, len) = parse_range(parts.next()?)?;
+    let bytes = parts.next()?;
+    if bytes.len() != 2 * len || !is_hex(bytes) { return None }
+
+    let values = (0..len)
+        .map(|i| u8::from_str_radix(&bytes[2 * i..2 * i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    values.into_iter().enumerate().for_each(|(i, value)| chip8.write_byte((addr + i) as u16, value));
+    Some(())
+}
+
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let addr = parse_hex(parts.next()?)? as usize;
+    let len = parse_hex(parts.next()?)? as usize;
+    if addr + len > RAM_BYTES { return None }
+    Some((addr, len))
+}
+
+// "0,<addr>,<kind>", only software breakpoints
+fn parse_breakpoint(args: &str) -> Option<u16> {
+    let mut parts = args.split(',');
+    if parts.next()? != "0" { return None }
+    parse_hex(parts.next()?).filter(|&addr| (addr as usize) < RAM_BYTES).map(|addr| addr as u16)
+}
+
+// Packets are decoded lossily, so payloads are checked before they're sliced
+fn is_hex(text: &str) -> bool {
+    text.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn parse_hex(hex: &str) -> Option<u32> {
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    value_or_error(result.map(|_| "OK".to_string()))
+}
+
+fn value_or_error(result: Option<String>) -> String {
+    result.unwrap_or_else(|| "E01".to_string())
+}
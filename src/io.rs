@@ -1,27 +1,36 @@
-use std::time::Duration;
-use std::thread::sleep;
+use std::time::{Duration, Instant};
 use sdl2::audio::{AudioDevice, AudioStatus};
-use sdl2::EventPump;
-use sdl2::event::Event;
+use sdl2::{EventPump, VideoSubsystem};
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
+use sdl2::controller::{GameController, Button};
+use sdl2::GameControllerSubsystem;
+use sdl2::pixels::Color;
 
-use std::convert::TryInto;
-
-use display::{Display, WindowDisplay};
+use display::{Display, WindowDisplay, MemoryWindow};
 use audio::{setup_square_audio, SquareWave};
-use constants::{W, H, N, PIXEL_SIZE, KEY_VALUES};
+use constants::{W, H, N, PIXEL_SIZE, KEY_VALUES, BLACK, WHITE};
 use command::{CommandEmulator, Command, 
-    DisplayCommand::{*, self}, AudioCommand, KeyCommand::*, GameCommand::Exit};
-use router::Router;
+    DisplayCommand, AudioCommand, KeyCommand::*, GameCommand::*, MemoryCommand::SendByte};
+use router::{Router, ChannelRouter};
+use rom_browser::{RomBrowser, BrowserInput};
+use rom_files::RomSource;
+use memory_viewer::{MemoryViewer, ViewerInput, ViewerEdit};
+use cheats::SearchFilter;
 
-const SCREEN_FPS: u32 = 10;
-const FRAME_CYCLE: u32 = 120;
-const NANO_UNIT: u32 = i64::pow(10,9) as u32;
+const FRONT_END_WAIT: Duration = Duration::from_millis(8);
+const BROWSER_TITLE: &str = "SELECT A ROM - ENTER TO PLAY, ESC TO QUIT";
 
 pub struct IO {
     display: Box<dyn Display<bool>>,
+    video_subsystem: VideoSubsystem,
+    memory_viewer: MemoryViewer,
+    memory_window: Option<MemoryWindow>,
     event_pump: EventPump, 
     audio_device: AudioDevice<SquareWave>,
+    controller_subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
+    is_quit: bool,
     commands: Router<Command>
 }
 
@@ -31,19 +40,193 @@ impl IO {
         let display = WindowDisplay::<W,H,N,PIXEL_SIZE>::new(&sdl_context);
         IO {
             display: Box::new(display),
+            video_subsystem: sdl_context.video().unwrap(),
+            memory_viewer: MemoryViewer::new(),
+            memory_window: None,
             event_pump: sdl_context.event_pump().unwrap(),
             audio_device: setup_square_audio(&sdl_context),
+            controller_subsystem: sdl_context.game_controller().unwrap(),
+            controllers: Vec::new(),
+            is_quit: false,
             commands: Router::<Command>::new()
         }
     }
 
+    // The window was closed, as opposed to a game being left with Esc
+    pub fn is_quit(&self) -> bool {
+        self.is_quit
+    }
+
+    /*
+    Shows the ROM browser until a ROM is picked with Enter (or A on a controller),
+    returning where to read it from. Returns None when the browser is left with Esc or the
+    window is closed.
+    */
+    pub fn run_browser(&mut self, browser: &mut RomBrowser) -> Option<RomSource> {
+        let mut is_dirty = true;
+        loop {
+            let events = self.event_pump.wait_event_timeout(FRONT_END_WAIT.as_millis() as u32)
+                .into_iter()
+                .chain(self.event_pump.poll_iter())
+                .collect::<Vec<Event>>();
+
+            for event in events {
+                let input = match event {
+                    Event::Quit {..} => { self.is_quit = true; return None },
+                    Event::KeyDown { keycode: Some(Keycode::Escape), repeat: false, .. }
+                    | Event::ControllerButtonDown { button: Button::B, .. }
+                    | Event::ControllerButtonDown { button: Button::Back, .. } => return None,
+                    Event::KeyDown { keycode: Some(Keycode::Return), .. }
+                    | Event::ControllerButtonDown { button: Button::A, .. }
+                    | Event::ControllerButtonDown { button: Button::Start, .. } => {
+                        if let Some(rom) = browser.selected_rom() {
+                            return Some(rom.clone())
+                        }
+                        None
+                    },
+                    Event::KeyDown { keycode: Some(Keycode::Up), .. }
+                    | Event::ControllerButtonDown { button: Button::DPadUp, .. } => Some(BrowserInput::Up),
+                    Event::KeyDown { keycode: Some(Keycode::Down), .. }
+                    | Event::ControllerButtonDown { button: Button::DPadDown, .. } => Some(BrowserInput::Down),
+                    Event::KeyDown { keycode: Some(Keycode::PageUp), .. }
+                    | Event::ControllerButtonDown { button: Button::LeftShoulder, .. } => Some(BrowserInput::PageUp),
+                    Event::KeyDown { keycode: Some(Keycode::PageDown), .. }
+                    | Event::ControllerButtonDown { button: Button::RightShoulder, .. } => Some(BrowserInput::PageDown),
+                    Event::KeyDown { keycode: Some(Keycode::Home), .. } => Some(BrowserInput::First),
+                    Event::KeyDown { keycode: Some(Keycode::End), .. } => Some(BrowserInput::Last),
+                    Event::ControllerDeviceAdded { which, .. } => { self.open_controller(which); None },
+                    _ => None
+                };
+
+                if let Some(input) = input {
+                    browser.move_selection(input);
+                    is_dirty = true
+                }
+            }
+
+            if is_dirty || self.display.get_osd().needs_redraw() {
+                let rows = browser.visible_rows();
+                self.display.draw_menu(BROWSER_TITLE, &rows, browser.preview());
+                is_dirty = false
+            }
+        }
+    }
+
+    // Stops a game's sound, input and colours carrying over once it has exited
+    pub fn reset_game(&mut self) {
+        self.commands.consume_all_outbound();
+        self.process_inbound_command(&Command::Audio(AudioCommand::Pause));
+        self.display.reset_screen();
+        self.display.set_colours(WHITE, BLACK);
+        self.memory_window = None;
+        self.memory_viewer = MemoryViewer::new()
+    }
+
+    pub fn set_colours(&mut self, foreground: Color, background: Color) {
+        self.display.set_colours(foreground, background)
+    }
+
+    pub fn show_message(&mut self, text: &str) {
+        self.display.get_osd().show_message(text, Instant::now())
+    }
+
+    fn open_controller(&mut self, which: i32) {
+        match self.controller_subsystem.open(which as u32) {
+            Ok(controller) => self.controllers.push(controller),
+            // Stdout can carry a protocol, e.g. for the debug adapter
+            Err(e) => eprintln!("Failed to open controller {}: {}", which, e)
+        }
+    }
+
+    // The emulator only sends memory while the window is open
+    fn toggle_memory_window(&mut self) {
+        self.memory_window = match self.memory_window {
+            Some(_) => None,
+            None => Some(MemoryWindow::new(&self.video_subsystem))
+        };
+        self.commands.send_outbound(Command::GameState(ShowMemory(self.memory_window.is_some())))
+    }
+
+    fn process_memory_window_event(&mut self, event: Event) {
+        let input = match event {
+            Event::Window { win_event: WindowEvent::Close, .. }
+            | Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => {
+                self.toggle_memory_window();
+                None
+            },
+            Event::KeyDown { keycode: Some(key), .. } => match key {
+                Keycode::Up => Some(ViewerInput::Up),
+                Keycode::Down => Some(ViewerInput::Down),
+                Keycode::Left => Some(ViewerInput::Left),
+                Keycode::Right => Some(ViewerInput::Right),
+                Keycode::PageUp => Some(ViewerInput::PageUp),
+                Keycode::PageDown => Some(ViewerInput::PageDown),
+                Keycode::Backspace => Some(ViewerInput::Erase),
+                Keycode::G => Some(ViewerInput::GoTo),
+                Keycode::I => Some(ViewerInput::GoToI),
+                Keycode::P => Some(ViewerInput::GoToPc),
+                Keycode::Return | Keycode::KpEnter => Some(ViewerInput::Confirm),
+                Keycode::Escape => Some(ViewerInput::Cancel),
+                Keycode::S => Some(ViewerInput::StartSearch),
+                Keycode::Equals => Some(ViewerInput::SearchEqual),
+                Keycode::X => Some(ViewerInput::Search(SearchFilter::Changed)),
+                Keycode::U => Some(ViewerInput::Search(SearchFilter::Unchanged)),
+                Keycode::M => Some(ViewerInput::Search(SearchFilter::Increased)),
+                Keycode::L => Some(ViewerInput::Search(SearchFilter::Decreased)),
+                Keycode::J => Some(ViewerInput::NextCandidate),
+                Keycode::Z => Some(ViewerInput::Freeze),
+                // Digit and letter keycodes are their lowercase characters
+                _ => char::from_u32(key as u32).and_then(|c| c.to_digit(16)).map(|digit| ViewerInput::HexDigit(digit as u8))
+            },
+            _ => None
+        };
+
+        if let Some(input) = input {
+            match self.memory_viewer.input(input, Instant::now()) {
+                Some(ViewerEdit::Write(addr, value)) => self.commands.send_outbound(Command::Memory(SendByte(addr, value))),
+                Some(ViewerEdit::Freeze(addr, value)) => self.commands.send_outbound(Command::GameState(Freeze(addr, value))),
+                None => {}
+            }
+        }
+    }
+
     pub fn poll_event_pump(&mut self) {
-        for event in self.event_pump.poll_iter() {
+        let events = self.event_pump.poll_iter().collect::<Vec<Event>>();
+        let memory_window_id = self.memory_window.as_ref().map(MemoryWindow::window_id);
+        for event in events {
             match event {
-                Event::Quit {..} 
-                | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                Event::Window { window_id, .. }
+                | Event::KeyDown { window_id, .. }
+                | Event::KeyUp { window_id, .. } if Some(window_id) == memory_window_id => {
+                    self.process_memory_window_event(event)
+                },
+                // With the memory window open, closing the game window doesn't quit by itself
+                Event::Quit {..}
+                | Event::Window { win_event: WindowEvent::Close, .. } => {
+                    self.is_quit = true;
                     self.commands.send_outbound(Command::GameState(Exit))
                 },
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. }
+                | Event::ControllerButtonDown { button: Button::Back, .. } => {
+                    self.commands.send_outbound(Command::GameState(Exit))
+                },
+                Event::ControllerDeviceAdded { which, .. } => self.open_controller(which),
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                    self.commands.send_outbound(Command::GameState(FastForward(true)))
+                },
+                Event::KeyUp { keycode: Some(Keycode::Tab), .. } => {
+                    self.commands.send_outbound(Command::GameState(FastForward(false)))
+                },
+                Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                    self.commands.send_outbound(Command::GameState(TogglePause))
+                },
+                Event::KeyDown { keycode: Some(Keycode::N), .. } => {
+                    self.commands.send_outbound(Command::GameState(FrameAdvance))
+                },
+                Event::KeyDown { keycode: Some(Keycode::F1), repeat: false, .. } => {
+                    self.display.get_osd().toggle_status()
+                },
+                Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => self.toggle_memory_window(),
                 Event::KeyDown { keycode: Some(key), repeat: false, .. } => {
                     if let Some(key_i) = IO::get_key_index(key) {
                         self.commands.send_outbound(
@@ -61,8 +244,37 @@ impl IO {
         };
     }
 
-    fn sleep_frame() {
-        sleep(Duration::new(0, NANO_UNIT / SCREEN_FPS / FRAME_CYCLE));
+    /*
+    Front end loop for an emulator running on another thread, linked by the
+    other end of `link`. Input is sent over as it happens, display and audio
+    commands are played back as they arrive. Returns when the window is closed
+    or the emulator thread has finished.
+    */
+    pub fn run_front_end(&mut self, link: &mut ChannelRouter<Command>) {
+        while link.is_connected() {
+            self.poll_event_pump();
+
+            let mut is_exiting = false;
+            for command in self.commands.consume_all_outbound() {
+                if let Command::GameState(Exit) = command { is_exiting = true }
+                link.send_outbound(command)
+            }
+            if is_exiting { return }
+
+            // Only the latest framebuffer is worth presenting when several draws queued up
+            let mut is_drawn = false;
+            for command in link.wait_all_inbound(FRONT_END_WAIT) {
+                match command {
+                    Command::Display(DisplayCommand::SendDraw) => is_drawn = true,
+                    _ => self.process_inbound_command(&command)
+                }
+            }
+            // The on-screen display keeps redrawing while messages fade, even when paused
+            if is_drawn || self.display.get_osd().needs_redraw() { self.display.draw_pixels() }
+            if let Some(ref mut memory_window) = self.memory_window {
+                if self.memory_viewer.needs_redraw() { memory_window.draw(&mut self.memory_viewer) }
+            }
+        }
     }
 
     fn get_key_index(key: Keycode) -> Option<usize> {
@@ -85,7 +297,9 @@ impl CommandEmulator for IO {
             Command::Display(c) => match c {
                 DisplayCommand::SendClearDisplay => self.display.reset_screen(),
                 DisplayCommand::SendDraw => self.display.draw_pixels(),
-                DisplayCommand::SendPixels(p) => self.display.update_pixels(p)
+                DisplayCommand::SendPixels(p) => self.display.update_pixels(&p[..]),
+                DisplayCommand::SendStatus(status) => self.display.get_osd().set_status(*status),
+                DisplayCommand::SendMessage(text) => self.display.get_osd().show_message(text, Instant::now())
             },
             Command::Audio(c) => match c {
                 AudioCommand::Play => {
@@ -98,17 +312,94 @@ impl CommandEmulator for IO {
                         self.audio_device.pause();
                     }
                 }
-            }
+            },
+            Command::Memory(c) => self.memory_viewer.process(c, Instant::now()),
             _ => {}
         }
     }
 
     fn emulate_cycle(&mut self) {
-        self.commands.send_outbound(Command::Display(
-            SendPixels(self.display.get_pixels().try_into().unwrap())));
-    
-        self.poll_event_pump();
+        self.poll_event_pump()
+    }
+}
+
+// Stands in for IO on the emulator thread, passing commands to and from the front end
+pub struct RemoteIO {
+    link: ChannelRouter<Command>,
+    commands: Router<Command>
+}
+
+impl RemoteIO {
+    pub fn new(link: ChannelRouter<Command>) -> Self {
+        RemoteIO {
+            link,
+            commands: Router::<Command>::new()
+        }
+    }
+}
+
+impl CommandEmulator for RemoteIO {
+    fn get_commands(&mut self) -> &mut Router<Command> {
+        &mut self.commands
+    }
+
+    fn process_inbound_command(&mut self, command: &Command) {
+        self.link.send_outbound(command.clone())
+    }
+
+    // Commands are moved across, so framebuffer snapshots aren't copied again
+    fn process_inbound_commands(&mut self) {
+        let RemoteIO {link, commands} = self;
+        commands.consume_all_inbound()
+            .into_iter()
+            .for_each(|c| link.send_outbound(c))
+    }
+
+    fn emulate_cycle(&mut self) {
+        let RemoteIO {link, commands} = self;
+        link.consume_all_inbound()
+            .into_iter()
+            .for_each(|c| commands.send_outbound(c));
 
-        IO::sleep_frame();
+        if !self.link.is_connected() {
+            self.commands.send_outbound(Command::GameState(Exit))
+        }
     }
 }
+
+// IO without a window, audio device or keyboard, for running ROMs headlessly
+pub struct HeadlessIO {
+    pixels: [bool; N],
+    commands: Router<Command>
+}
+
+impl Default for HeadlessIO {
+    fn default() -> Self {
+        HeadlessIO::new()
+    }
+}
+
+impl HeadlessIO {
+    pub fn new() -> Self {
+        HeadlessIO {
+            pixels: [false; N],
+            commands: Router::<Command>::new()
+        }
+    }
+}
+
+impl CommandEmulator for HeadlessIO {
+    fn get_commands(&mut self) -> &mut Router<Command> {
+        &mut self.commands
+    }
+
+    fn process_inbound_command(&mut self, command: &Command) {
+        match command {
+            Command::Display(DisplayCommand::SendClearDisplay) => self.pixels = [false; N],
+            Command::Display(DisplayCommand::SendPixels(p)) => self.pixels.copy_from_slice(&p[..]),
+            _ => {}
+        }
+    }
+
+    fn emulate_cycle(&mut self) {}
+}
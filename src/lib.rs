@@ -0,0 +1,55 @@
+/*
+Chip8 Emulator in Rust v2
+
+Patrick Neilson 2021
+*/
+
+extern crate sdl2;
+extern crate rand;
+extern crate byteorder;
+extern crate sha1_smol;
+extern crate zip;
+extern crate crossterm;
+extern crate serde_json;
+extern crate rhai;
+
+pub mod memory;
+pub mod rom_menu;
+pub mod rom_search;
+pub mod menu_history;
+pub mod rom_browser;
+pub mod library;
+pub mod rom_files;
+pub mod rom_loader;
+pub mod audio;
+pub mod display;
+pub mod osd;
+pub mod memory_viewer;
+pub mod chip8;
+pub mod io;
+pub mod constants;
+pub mod rng;
+pub mod opcode;
+pub mod analysis;
+pub mod profiler;
+pub mod save_state;
+pub mod script;
+pub mod cheats;
+pub mod debugger;
+pub mod gdb;
+pub mod dap;
+pub mod source_map;
+pub mod translator;
+pub mod emulator;
+pub mod command;
+pub mod router;
+pub mod bus;
+pub mod movie;
+pub mod options;
+pub mod pacing;
+
+#[cfg(test)]
+extern crate proptest;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,263 @@
+/*
+ROM metadata database format (plain text, one block per ROM):
+[<sha1>]                  - SHA-1 of the ROM bytes, starts the ROM's block
+title <text>
+author <text>             - optional, like every line below
+year <u16>
+platform <chip8|schip|xochip> - chip8 when left out
+quirks <quirk>...         - shift_vy, load_store_i, vf_reset, clip, jump_vx
+speed <cycles per second> - recommended speed
+colours <rrggbb> <rrggbb> - foreground and background colour
+key <hex key> <text>      - what a key does, one line per key
+Blank lines and lines starting with '#' are ignored.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+
+use byteorder::{ByteOrder, BigEndian};
+use sdl2::pixels::Color;
+
+use constants::KEYS;
+use movie::rom_hash;
+
+const BUNDLED_DATABASE: &str = include_str!("rom_database.txt");
+
+// Ordered by the instructions each adds to the one before
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+impl Platform {
+    // XO-CHIP programs can use 64kB, the others 4kB
+    pub fn memory_bytes(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SCHIP",
+            Platform::XoChip => "XO-CHIP"
+        })
+    }
+}
+
+// Behaviours of other interpreters a ROM can depend on. Without them shifts
+// work on Vx in place, I is left alone by LD [I] and LD Vx, [I], logic ops
+// keep VF, sprites wrap and BNNN adds V0. The emulator turns on the ones a
+// known ROM needs, see chip8::Quirks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quirk {
+    ShiftVy,
+    LoadStoreI,
+    VfReset,
+    Clip,
+    JumpVx
+}
+
+const QUIRK_NAMES: [(Quirk, &str); 5] = [
+    (Quirk::ShiftVy, "shift_vy"),
+    (Quirk::LoadStoreI, "load_store_i"),
+    (Quirk::VfReset, "vf_reset"),
+    (Quirk::Clip, "clip"),
+    (Quirk::JumpVx, "jump_vx")
+];
+
+impl Quirk {
+    pub fn parse(name: &str) -> Option<Self> {
+        QUIRK_NAMES.iter().find(|(_, quirk_name)| *quirk_name == name).map(|(quirk, _)| *quirk)
+    }
+
+    pub fn name(&self) -> &'static str {
+        QUIRK_NAMES.iter().find(|(quirk, _)| quirk == self).unwrap().1
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub year: Option<u16>,
+    pub platform: Platform,
+    pub quirks: Vec<Quirk>,
+    pub speed: Option<u32>,
+    pub colours: Option<(Color, Color)>,
+    pub keys: Vec<(usize, String)>
+}
+
+impl RomInfo {
+    fn new(title: &str) -> Self {
+        RomInfo {
+            title: title.to_string(),
+            author: None,
+            year: None,
+            platform: Platform::Chip8,
+            quirks: Vec::new(),
+            speed: None,
+            colours: None,
+            keys: Vec::new()
+        }
+    }
+
+    // e.g. "Pong by Paul Vervalin (1990)"
+    pub fn credits(&self) -> String {
+        let author = self.author.as_ref().map_or(String::new(), |author| format!(" by {}", author));
+        let year = self.year.map_or(String::new(), |year| format!(" ({})", year));
+        format!("{}{}{}", self.title, author, year)
+    }
+
+    // Reasons the ROM may not play right on this emulator
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.platform != Platform::Chip8 {
+            warnings.push(format!("{} ROM, only CHIP-8 is supported", self.platform))
+        }
+        warnings
+    }
+}
+
+// ROM metadata by SHA-1 of the ROM bytes
+pub struct Library {
+    roms: HashMap<String, RomInfo>
+}
+
+impl Library {
+    pub fn bundled() -> Self {
+        Library::parse(BUNDLED_DATABASE).expect("Bundled ROM database should be valid")
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        Library::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut roms = HashMap::new();
+        let mut current: Option<(String, RomInfo)> = None;
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            if line.starts_with('[') && line.ends_with(']') {
+                let hash = line[1..line.len() - 1].to_lowercase();
+                roms.extend(current.replace((hash, RomInfo::new(""))));
+                continue
+            }
+
+            let (_, ref mut info) = *current.as_mut()
+                .ok_or_else(|| invalid_database(&format!("entry outside of a ROM block: {}", line)))?;
+            let (field, value) = match line.find(char::is_whitespace) {
+                Some(i) => (&line[..i], line[i..].trim()),
+                None => (line, "")
+            };
+            let bad_value = || invalid_database(&format!("bad value for '{}': {}", field, value));
+
+            match field {
+                "title" => info.title = value.to_string(),
+                "author" => info.author = Some(value.to_string()),
+                "year" => info.year = Some(value.parse().map_err(|_| bad_value())?),
+                "platform" => info.platform = match value {
+                    "chip8" => Platform::Chip8,
+                    "schip" => Platform::SuperChip,
+                    "xochip" => Platform::XoChip,
+                    _ => return Err(bad_value())
+                },
+                "quirks" => info.quirks = value.split_whitespace()
+                    .map(|name| Quirk::parse(name).ok_or_else(bad_value))
+                    .collect::<io::Result<Vec<Quirk>>>()?,
+                "speed" => info.speed = Some(value.parse().ok().filter(|&speed| speed > 0).ok_or_else(bad_value)?),
+                "colours" => {
+                    let colours = value.split_whitespace().map(parse_colour).collect::<Option<Vec<Color>>>();
+                    info.colours = match colours.as_ref().map(|c| &c[..]) {
+                        Some(&[foreground, background]) => Some((foreground, background)),
+                        _ => return Err(bad_value())
+                    }
+                },
+                "key" => {
+                    let (key, text) = value.split_at(value.find(char::is_whitespace).ok_or_else(bad_value)?);
+                    let key_i = usize::from_str_radix(key, 16).ok().filter(|&k| k < KEYS).ok_or_else(bad_value)?;
+                    info.keys.push((key_i, text.trim().to_string()))
+                },
+                _ => return Err(invalid_database(&format!("unknown field '{}'", field)))
+            }
+        }
+        roms.extend(current);
+
+        Ok(Library { roms })
+    }
+
+    // Entries from other replace the ones for the same ROM
+    pub fn merge(&mut self, other: Library) {
+        self.roms.extend(other.roms)
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn lookup(&self, rom_bytes: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&rom_hash(rom_bytes))
+    }
+}
+
+/*
+Guesses the platform of an unknown ROM from instructions only the later
+platforms have. Every aligned word is checked, so sprite data can give a
+false hint.
+*/
+pub fn detect_platform(rom_bytes: &[u8]) -> Platform {
+    rom_bytes.chunks_exact(2)
+        .map(BigEndian::read_u16)
+        .filter_map(extension)
+        .map(|(platform, _)| platform)
+        .max()
+        .unwrap_or(Platform::Chip8)
+}
+
+// The platform that added an instruction CHIP-8 doesn't have, and what it does
+pub fn extension(instruction: u16) -> Option<(Platform, &'static str)> {
+    let (x, y, n) = ((instruction >> 8) & 0xF, (instruction >> 4) & 0xF, instruction & 0xF);
+    let (platform, name) = match (instruction >> 12, x, y, n) {
+        (0x0, 0x0, 0xD, _) => (Platform::XoChip, "scroll up"),
+        (0x5, _, _, 0x2) => (Platform::XoChip, "save register range"),
+        (0x5, _, _, 0x3) => (Platform::XoChip, "load register range"),
+        (0xF, 0x0, 0x0, 0x0) => (Platform::XoChip, "long I"),
+        (0xF, _, 0x0, 0x1) => (Platform::XoChip, "select planes"),
+        (0xF, 0x0, 0x0, 0x2) => (Platform::XoChip, "audio pattern"),
+        (0xF, _, 0x3, 0xA) => (Platform::XoChip, "pitch"),
+        (0x0, 0x0, 0xC, _) => (Platform::SuperChip, "scroll down"),
+        (0x0, 0x0, 0xF, 0xB) => (Platform::SuperChip, "scroll right"),
+        (0x0, 0x0, 0xF, 0xC) => (Platform::SuperChip, "scroll left"),
+        (0x0, 0x0, 0xF, 0xD) => (Platform::SuperChip, "exit"),
+        (0x0, 0x0, 0xF, 0xE) => (Platform::SuperChip, "lores"),
+        (0x0, 0x0, 0xF, 0xF) => (Platform::SuperChip, "hires"),
+        (0xD, _, _, 0x0) => (Platform::SuperChip, "16x16 sprite"),
+        (0xF, _, 0x3, 0x0) => (Platform::SuperChip, "big font"),
+        (0xF, _, 0x7, 0x5) => (Platform::SuperChip, "save flags"),
+        (0xF, _, 0x8, 0x5) => (Platform::SuperChip, "load flags"),
+        _ => return None
+    };
+    Some((platform, name))
+}
+
+fn parse_colour(hex: &str) -> Option<Color> {
+    if hex.len() != 6 { return None }
+    u32::from_str_radix(hex, 16).ok()
+        .map(|rgb| Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+fn invalid_database(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid ROM database: {}", message))
+}
@@ -4,31 +4,206 @@ Chip8 Emulator in Rust v2
 Patrick Neilson 2021
 */
 
-extern crate sdl2;
-extern crate rand;
-extern crate byteorder;
-
-mod memory;
-mod rom_menu;
-mod audio;
-mod display;
-mod chip8;
-mod io;
-mod constants;
-mod rng;
-mod opcode;
-mod emulator;
-mod command;
-mod router;
-
-use rom_menu::choose_rom;
-use emulator::Chip8Emulator;
-
-#[cfg(test)]
-mod tests;
+extern crate chip8_emulator;
+
+use std::fs;
+use std::process;
+use std::thread;
+
+use chip8_emulator::rom_menu::choose_rom;
+use chip8_emulator::rom_browser::RomBrowser;
+use chip8_emulator::rom_files::RomSource;
+use chip8_emulator::rom_loader::load_rom;
+use chip8_emulator::library::{Library, Platform, Quirk, detect_platform};
+use chip8_emulator::chip8::Quirks;
+use chip8_emulator::pacing::CYCLES_PER_SECOND;
+use chip8_emulator::emulator::Chip8Emulator;
+use chip8_emulator::movie::Movie;
+use chip8_emulator::options::Options;
+use chip8_emulator::rng::{random_seed, from_seed};
+use chip8_emulator::io::{IO, RemoteIO};
+use chip8_emulator::router::ChannelRouter;
+use chip8_emulator::gdb::GdbStub;
+use chip8_emulator::script::{Script, ScriptError};
+use chip8_emulator::cheats::CheatFile;
 
 fn main() {
-    let rom = choose_rom(); //"./ROMs/PONG";
-    let mut emulator = Chip8Emulator::new();
-    emulator.start_game(&rom)
+    let options = Options::from_args().unwrap_or_else(|e| {
+        println!("{}", e);
+        process::exit(1)
+    });
+
+    let mut replay = options.replay_path.as_ref().map(|path| Movie::load(path)
+        .unwrap_or_else(|e| {
+            println!("Failed to load movie '{}': {}", path, e);
+            process::exit(1)
+        }));
+
+    // Read once and checked here, each game compiles its own copy on the emulator thread
+    let script_source = options.script_path.as_ref().map(|path| {
+        let source = fs::read_to_string(path).map_err(ScriptError::Unreadable)
+            .and_then(|source| Script::compile(&source).map(|_| source));
+        source.unwrap_or_else(|e| {
+            println!("Failed to load script '{}': {}", path, e);
+            process::exit(1)
+        })
+    });
+
+    let cheat_file = options.cheats_path.as_ref().map(|path| CheatFile::load(path)
+        .unwrap_or_else(|e| {
+            println!("Failed to load cheats '{}': {}", path, e);
+            process::exit(1)
+        }));
+
+    // Entries in the user's database take the place of the bundled ones
+    let mut library = Library::bundled();
+    if let Some(ref path) = options.rom_db_path {
+        library.merge(Library::load(path).unwrap_or_else(|e| {
+            println!("Failed to load ROM database '{}': {}", path, e);
+            process::exit(1)
+        }))
+    }
+
+    let files = GameFiles { library, cheat_file, script_source };
+
+    if options.is_terminal_menu {
+        // No ROM chosen or none found, choose_rom has said which
+        let rom = match choose_rom(&options.rom_dirs, &options.menu_history_path) {
+            Some(rom) => rom,
+            None => return
+        };
+        let mut io = IO::new();
+        // A failed script fails the run, e.g. for a regression scenario
+        if run_game(&mut io, &rom, &options, &files, replay, options.record_path.clone()) {
+            process::exit(1)
+        }
+        return
+    }
+
+    let mut browser = RomBrowser::from_roots(&options.rom_dirs, &files.library);
+    if browser.is_empty() {
+        println!("No ROMs found in {}.", options.rom_dirs.join(", "));
+        process::exit(1)
+    }
+
+    // Games return to the browser when they exit, closing the window quits.
+    // A movie is recorded or replayed for the first game only.
+    let mut io = IO::new();
+    let mut record_path = options.record_path.clone();
+    while let Some(rom) = io.run_browser(&mut browser) {
+        run_game(&mut io, &rom, &options, &files, replay.take(), record_path.take());
+        if io.is_quit() { break }
+        io.reset_game()
+    }
+}
+
+// Loaded when the emulator starts and shared by every game played
+struct GameFiles {
+    library: Library,
+    cheat_file: Option<CheatFile>,
+    script_source: Option<String>
+}
+
+// Whether a script ended the game by failing
+fn run_game(io: &mut IO, rom: &RomSource, options: &Options, files: &GameFiles, replay: Option<Movie>,
+    record_path: Option<String>) -> bool
+{
+    // CHIP-8 is the only mode the emulator runs in
+    let rom_bytes = match load_rom(rom, Platform::Chip8) {
+        Ok(checked) => {
+            for warning in checked.warnings {
+                println!("Warning: {}", warning);
+                io.show_message(&warning)
+            }
+            checked.bytes
+        },
+        Err(e) => {
+            println!("Failed to load ROM '{}': {}", rom, e);
+            io.show_message(&format!("Failed to load ROM: {}", e));
+            return false
+        }
+    };
+    let mut cycles_per_second = options.cycles_per_second;
+    let mut quirks = Quirks::default();
+
+    // Known ROMs get their recommended settings, unknown ones a platform hint
+    match files.library.lookup(&rom_bytes) {
+        Some(info) => {
+            println!("\n{} [{}]", info.credits(), info.platform);
+            info.keys.iter().for_each(|(key_i, text)| println!("  key {:X}: {}", key_i, text));
+            if !info.quirks.is_empty() {
+                let names = info.quirks.iter().map(Quirk::name).collect::<Vec<&str>>();
+                println!("  quirks: {}", names.join(", "))
+            }
+            io.show_message(&info.title);
+            for warning in info.warnings() {
+                println!("Warning: {}", warning);
+                io.show_message(&warning)
+            }
+            if let Some((foreground, background)) = info.colours {
+                io.set_colours(foreground, background)
+            }
+            cycles_per_second = cycles_per_second.or(info.speed);
+            quirks = Quirks::new(&info.quirks)
+        },
+        None => {
+            let platform = detect_platform(&rom_bytes);
+            println!("\nUnknown ROM, looks like {}", platform);
+            if platform != Platform::Chip8 {
+                io.show_message(&format!("Looks like a {} ROM, only CHIP-8 is supported", platform))
+            }
+        }
+    }
+
+    // Movies need a seeded RNG, otherwise RND uses the OS seeded generator
+    let rng_seed = replay.as_ref().map(|movie| movie.rng_seed)
+        .or(options.rng_seed)
+        .or_else(|| record_path.as_ref().map(|_| random_seed()));
+
+    let (emulator_link, mut front_end_link) = ChannelRouter::pair();
+
+    // The emulator is built on its own thread, SDL stays on the main thread
+    let engine = options.engine;
+    let cycles_per_second = cycles_per_second.unwrap_or(CYCLES_PER_SECOND);
+    let fast_forward_multiplier = options.fast_forward_multiplier;
+    let profile_path = options.profile_path.clone();
+    let gdb_port = options.gdb_port;
+    let script_source = files.script_source.clone();
+    let cheats = files.cheat_file.as_ref().map_or_else(Vec::new, |cheat_file| cheat_file.lookup(&rom_bytes).to_vec());
+    let emulator_thread = thread::spawn(move || {
+        let mut emulator = Chip8Emulator::new(Box::new(RemoteIO::new(emulator_link)), from_seed(rng_seed));
+        emulator.set_engine(engine);
+        emulator.set_quirks(quirks);
+        emulator.set_pacing(cycles_per_second, fast_forward_multiplier);
+        if let Some(movie) = replay { emulator.replay_movie(movie) }
+        if let (Some(path), Some(seed)) = (record_path, rng_seed) {
+            emulator.record_movie(&path, seed)
+        }
+        if let Some(path) = profile_path { emulator.profile_game(&path) }
+        emulator.enable_cheats(&cheats);
+        if let Some(port) = gdb_port {
+            match GdbStub::listen(port) {
+                Ok(gdb) => emulator.attach_debugger(Box::new(gdb)),
+                Err(e) => println!("Failed to start the GDB server: {}", e)
+            }
+        }
+        // Scripts can't be sent between threads, so the source is compiled here
+        if let Some(source) = script_source {
+            match Script::compile(&source) {
+                Ok(script) => emulator.attach_script(script),
+                Err(e) => {
+                    println!("Failed to load script: {}", e);
+                    return Ok(true)
+                }
+            }
+        }
+        emulator.run_game(&rom_bytes).map(|_| emulator.script_error().is_some())
+    });
+
+    io.run_front_end(&mut front_end_link);
+    drop(front_end_link);
+    emulator_thread.join().expect("Emulator thread panicked").unwrap_or_else(|e| {
+        println!("Failed to load ROM '{}': {}", rom, e);
+        false
+    })
 }
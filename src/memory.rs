@@ -7,11 +7,14 @@ Memory map:
 
 use constants::*;
 use command::{CommandEmulator, Command, 
-    MemoryCommand::SendRAM};
+    MemoryCommand::{SendRAM, SendByte, SendPointers}};
 use router::Router;
+use library::Platform;
+use rom_loader::{RomError, check_size};
 
 pub struct Memory {
     ram: [u8; RAM_BYTES],
+    ram_changed: bool,
     commands: Router<Command>
 }
 
@@ -19,6 +22,7 @@ impl Memory {
     pub fn new() -> Self {
         Memory {
             ram: [0; RAM_BYTES],
+            ram_changed: false,
             commands: Router::<Command>::new()
         }
     }
@@ -27,8 +31,10 @@ impl Memory {
         self.load_bytes_from(FONT_ADDR, &FONT_SPRITES)
     }
 
-    pub fn load_rom(&mut self, rom_bytes: &[u8]) {
+    pub fn load_rom(&mut self, rom_bytes: &[u8]) -> Result<(), RomError> {
+        check_size(rom_bytes, Platform::Chip8)?;
         self.load_bytes_from(ROM_ADDR, rom_bytes);
+        Ok(())
     }
 
     fn load_bytes_from(&mut self, start_addr: usize, bytes: &[u8]) {
@@ -37,6 +43,7 @@ impl Memory {
             self.ram[addr] = *byte;
             addr += 1
         }
+        self.ram_changed = true
     }
 }
 
@@ -48,14 +55,21 @@ impl CommandEmulator for Memory {
     fn process_inbound_command(&mut self, command: &Command) {
         match command {
             Command::Memory(c) => match c {
-                SendRAM(bytes) => self.ram.copy_from_slice(bytes)
+                SendRAM(bytes) => self.ram.copy_from_slice(&bytes[..]),
+                SendByte(addr, value) => self.ram[*addr as usize % RAM_BYTES] = *value,
+                SendPointers(..) => {}
             },
             _ => {}
         }
     }
 
+    // The CPU keeps its own copy of RAM and sends back each byte it writes,
+    // so the whole RAM is only sent after it was loaded
     fn emulate_cycle(&mut self) {
-        self.commands.send_outbound(Command::Memory(
-            SendRAM(self.ram.clone())));
+        if self.ram_changed {
+            self.commands.send_outbound(Command::Memory(
+                SendRAM(Box::new(self.ram))));
+            self.ram_changed = false
+        }
     }
 }
@@ -0,0 +1,325 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use constants::RAM_BYTES;
+use command::MemoryCommand::{self, SendRAM, SendByte, SendPointers};
+use cheats::{RamSearch, SearchFilter};
+
+pub const BYTES_PER_ROW: usize = 0x10;
+// Rows shown at once, and how far Page Up/Down move
+pub const VIEWER_ROWS: usize = 32;
+// The tallest sprite DRW can draw
+pub const SPRITE_ROWS: usize = 15;
+
+// Written bytes flash, fading out over this long
+const FLASH_TIME: Duration = Duration::from_millis(750);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewerInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    HexDigit(u8),
+    Erase,
+    GoTo,
+    GoToI,
+    GoToPc,
+    Confirm,
+    Cancel,
+    StartSearch,
+    // Keeps the candidates equal to a value typed next
+    SearchEqual,
+    Search(SearchFilter),
+    NextCandidate,
+    Freeze
+}
+
+// What the front end sends on after an input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewerEdit {
+    Write(u16, u8),
+    Freeze(u16, Option<u8>)
+}
+
+// What a byte is marked as, earlier marks over later ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    None,
+    Candidate,
+    Frozen,
+    I,
+    Pc,
+    Cursor
+}
+
+// What's being typed, an address to go to or a value to search for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Entry {
+    Address,
+    Value
+}
+
+impl Entry {
+    fn digits(&self) -> usize {
+        match self {
+            Entry::Address => 3,
+            Entry::Value => 2
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewerCell {
+    pub value: u8,
+    pub highlight: Highlight,
+    // Opacity (0-255) of the flash after a write
+    pub flash: u8
+}
+
+/*
+Hex dump of RAM for the memory window. Keeps its own copy of RAM, updated by
+the emulator's memory commands, marks I, the instruction at PC and the cursor,
+and flashes bytes as they're written. Two hex digits typed at the cursor write
+a byte, G then an address and Enter moves the cursor there. It also runs RAM
+searches on its copy and keeps track of the bytes it froze.
+*/
+pub struct MemoryViewer {
+    ram: Box<[u8; RAM_BYTES]>,
+    i: u16,
+    pc: u16,
+    cursor: usize,
+    scroll: usize,
+    // The first digit of a byte being typed at the cursor
+    high_digit: Option<u8>,
+    entry: Option<(Entry, String)>,
+    writes: HashMap<usize, Instant>,
+    search: Option<RamSearch>,
+    frozen: HashSet<u16>,
+    is_dirty: bool
+}
+
+impl Default for MemoryViewer {
+    fn default() -> Self {
+        MemoryViewer::new()
+    }
+}
+
+impl MemoryViewer {
+    pub fn new() -> Self {
+        MemoryViewer {
+            ram: Box::new([0; RAM_BYTES]),
+            i: 0,
+            pc: 0,
+            cursor: 0,
+            scroll: 0,
+            high_digit: None,
+            entry: None,
+            writes: HashMap::new(),
+            search: None,
+            frozen: HashSet::new(),
+            is_dirty: true
+        }
+    }
+
+    pub fn process(&mut self, command: &MemoryCommand, now: Instant) {
+        match command {
+            SendRAM(bytes) => {
+                self.ram.copy_from_slice(&bytes[..]);
+                self.writes.clear()
+            },
+            SendByte(addr, value) => self.write(*addr as usize, *value, now),
+            SendPointers(i, pc) => {
+                self.i = *i;
+                self.pc = *pc
+            }
+        }
+        self.is_dirty = true
+    }
+
+    // The byte written when the input finished typing one, or the byte frozen or let go
+    pub fn input(&mut self, input: ViewerInput, now: Instant) -> Option<ViewerEdit> {
+        self.is_dirty = true;
+        if let Some((entry, ref mut digits)) = self.entry {
+            match input {
+                ViewerInput::HexDigit(digit) if digits.len() < entry.digits() => digits.push_str(&format!("{:X}", digit)),
+                ViewerInput::Erase => { digits.pop(); },
+                ViewerInput::Confirm => {
+                    let value = usize::from_str_radix(digits, 16).ok();
+                    self.entry = None;
+                    match (entry, value) {
+                        (Entry::Address, Some(addr)) => self.move_cursor(addr),
+                        (Entry::Value, Some(value)) => self.search(SearchFilter::Equal(value as u8)),
+                        (_, None) => {}
+                    }
+                },
+                ViewerInput::Cancel => self.entry = None,
+                _ => {}
+            }
+            return None
+        }
+
+        if let ViewerInput::HexDigit(digit) = input {
+            match self.high_digit.take() {
+                Some(high_digit) => {
+                    let (addr, value) = (self.cursor, high_digit << 4 | digit);
+                    self.write(addr, value, now);
+                    self.move_cursor((addr + 1).min(RAM_BYTES - 1));
+                    return Some(ViewerEdit::Write(addr as u16, value))
+                },
+                None => self.high_digit = Some(digit)
+            }
+            return None
+        }
+
+        self.high_digit = None;
+        let cursor = self.cursor as isize;
+        let page = (VIEWER_ROWS * BYTES_PER_ROW) as isize;
+        match input {
+            ViewerInput::Up => self.move_cursor_by(cursor - BYTES_PER_ROW as isize),
+            ViewerInput::Down => self.move_cursor_by(cursor + BYTES_PER_ROW as isize),
+            ViewerInput::Left => self.move_cursor_by(cursor - 1),
+            ViewerInput::Right => self.move_cursor_by(cursor + 1),
+            ViewerInput::PageUp => self.move_cursor_by(cursor - page),
+            ViewerInput::PageDown => self.move_cursor_by(cursor + page),
+            ViewerInput::GoTo => self.entry = Some((Entry::Address, String::new())),
+            ViewerInput::GoToI => self.move_cursor(self.i as usize),
+            ViewerInput::GoToPc => self.move_cursor(self.pc as usize),
+            ViewerInput::StartSearch => self.search = Some(RamSearch::new(&self.ram[..])),
+            ViewerInput::SearchEqual => self.entry = Some((Entry::Value, String::new())),
+            ViewerInput::Search(filter) => self.search(filter),
+            ViewerInput::NextCandidate => {
+                let next = self.search.as_ref().and_then(|search| {
+                    let candidates = search.candidates();
+                    candidates.iter().find(|&&addr| addr as usize > self.cursor).or_else(|| candidates.first()).cloned()
+                });
+                if let Some(addr) = next { self.move_cursor(addr as usize) }
+            },
+            ViewerInput::Freeze => {
+                let addr = self.cursor as u16;
+                let value = if self.frozen.remove(&addr) { None } else { Some(self.ram[self.cursor]) };
+                if value.is_some() { self.frozen.insert(addr); }
+                return Some(ViewerEdit::Freeze(addr, value))
+            },
+            _ => {}
+        }
+        None
+    }
+
+    // Filters from the snapshot, a search is started first if there isn't one
+    fn search(&mut self, filter: SearchFilter) {
+        let ram = &self.ram[..];
+        self.search.get_or_insert_with(|| RamSearch::new(ram)).filter(ram, filter)
+    }
+
+    // Addresses still matching every filter, if a search was started
+    pub fn candidates(&self) -> Option<&[u16]> {
+        self.search.as_ref().map(RamSearch::candidates)
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram[..]
+    }
+
+    // Something changed or is still flashing since rows were last taken
+    pub fn needs_redraw(&self) -> bool {
+        self.is_dirty || !self.writes.is_empty()
+    }
+
+    // The rows on screen, each its first address and its bytes
+    pub fn rows(&mut self, now: Instant) -> Vec<(usize, Vec<ViewerCell>)> {
+        self.is_dirty = false;
+        self.writes.retain(|_, written| now.saturating_duration_since(*written) < FLASH_TIME);
+
+        (self.scroll..self.scroll + VIEWER_ROWS)
+            .map(|row| row * BYTES_PER_ROW)
+            .map(|start| (start, (start..start + BYTES_PER_ROW).map(|addr| ViewerCell {
+                value: self.ram[addr],
+                highlight: self.highlight(addr),
+                flash: self.writes.get(&addr).map_or(0, |written| {
+                    let remaining = FLASH_TIME - now.saturating_duration_since(*written);
+                    (0xFF * remaining.as_millis() / FLASH_TIME.as_millis()) as u8
+                })
+            }).collect()))
+            .collect()
+    }
+
+    // What's being typed, or the cursor, registers and search candidates left
+    pub fn status_line(&self) -> String {
+        match self.entry {
+            Some((Entry::Address, ref digits)) => format!("GO TO: {}_", digits),
+            Some((Entry::Value, ref digits)) => format!("EQUAL TO: {}_", digits),
+            None => {
+                let value = match self.high_digit {
+                    Some(digit) => format!("{:X}_", digit),
+                    None => format!("{:02X}", self.ram[self.cursor])
+                };
+                let search = match self.search {
+                    Some(ref search) => format!("   SEARCH {}", search.candidates().len()),
+                    None => String::new()
+                };
+                format!("{:03X}: {}   I {:03X}   PC {:03X}{}", self.cursor, value, self.i, self.pc, search)
+            }
+        }
+    }
+
+    // The bytes at I as DRW would draw them, a row of 8 pixels a byte
+    pub fn sprite(&self) -> Vec<[bool; 8]> {
+        (0..SPRITE_ROWS)
+            .map(|row| self.ram[(self.i as usize + row) % RAM_BYTES])
+            .map(|byte| {
+                let mut pixels = [false; 8];
+                pixels.iter_mut().enumerate().for_each(|(x, pixel)| *pixel = byte >> (7 - x) & 1 == 1);
+                pixels
+            })
+            .collect()
+    }
+
+    fn write(&mut self, addr: usize, value: u8, now: Instant) {
+        self.ram[addr % RAM_BYTES] = value;
+        self.writes.insert(addr % RAM_BYTES, now);
+    }
+
+    fn highlight(&self, addr: usize) -> Highlight {
+        let pc = self.pc as usize;
+        if addr == self.cursor {
+            Highlight::Cursor
+        } else if addr == pc || addr == (pc + 1) % RAM_BYTES {
+            Highlight::Pc
+        } else if addr == self.i as usize {
+            Highlight::I
+        } else if self.frozen.contains(&(addr as u16)) {
+            Highlight::Frozen
+        } else if self.search.as_ref().is_some_and(|search| search.is_candidate(addr as u16)) {
+            Highlight::Candidate
+        } else {
+            Highlight::None
+        }
+    }
+
+    fn move_cursor_by(&mut self, cursor: isize) {
+        self.move_cursor(cursor.max(0).min(RAM_BYTES as isize - 1) as usize)
+    }
+
+    // Scrolls as little as it takes to keep the cursor on screen
+    fn move_cursor(&mut self, addr: usize) {
+        self.cursor = addr % RAM_BYTES;
+        let row = self.cursor / BYTES_PER_ROW;
+        if row < self.scroll {
+            self.scroll = row
+        } else if row >= self.scroll + VIEWER_ROWS {
+            self.scroll = row + 1 - VIEWER_ROWS
+        }
+    }
+}
+
+// Printable ASCII, anything else is shown as a dot
+pub fn ascii(value: u8) -> char {
+    if (0x20..0x7F).contains(&value) { value as char } else { '.' }
+}
@@ -0,0 +1,95 @@
+/*
+Menu history file (plain text, one entry per line):
+recent <name>     - ROMs played, most recent first
+favourite <name>  - ROMs marked as favourites, in the order they were marked
+Names are ROM names in the library, which may contain spaces.
+*/
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write, Error, ErrorKind};
+
+pub const MAX_RECENT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuHistory {
+    pub recent: Vec<String>,
+    pub favourites: Vec<String>
+}
+
+impl Default for MenuHistory {
+    fn default() -> Self {
+        MenuHistory::new()
+    }
+}
+
+impl MenuHistory {
+    pub fn new() -> Self {
+        MenuHistory {
+            recent: Vec::new(),
+            favourites: Vec::new()
+        }
+    }
+
+    // A missing file is an empty history
+    pub fn load(path: &str) -> io::Result<Self> {
+        match File::open(path) {
+            Ok(file) => MenuHistory::read_from(BufReader::new(file)),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(MenuHistory::new()),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let temp_path = format!("{}.tmp", path);
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(temp_path, path)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for name in &self.recent {
+            writeln!(writer, "recent {}", name)?
+        }
+        for name in &self.favourites {
+            writeln!(writer, "favourite {}", name)?
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut history = MenuHistory::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() { continue }
+
+            match line.split_at(line.find(' ').unwrap_or(line.len())) {
+                ("recent", name) => history.recent.push(name.trim().to_string()),
+                ("favourite", name) => history.favourites.push(name.trim().to_string()),
+                _ => return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Invalid menu history: bad entry: {}", line)))
+            }
+        }
+        history.recent.truncate(MAX_RECENT);
+        Ok(history)
+    }
+
+    pub fn played(&mut self, name: &str) {
+        self.recent.retain(|recent| recent != name);
+        self.recent.insert(0, name.to_string());
+        self.recent.truncate(MAX_RECENT)
+    }
+
+    pub fn is_favourite(&self, name: &str) -> bool {
+        self.favourites.iter().any(|favourite| favourite == name)
+    }
+
+    pub fn toggle_favourite(&mut self, name: &str) {
+        if self.is_favourite(name) {
+            self.favourites.retain(|favourite| favourite != name)
+        } else {
+            self.favourites.push(name.to_string())
+        }
+    }
+}
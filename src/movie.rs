@@ -0,0 +1,166 @@
+/*
+Movie file format (plain text, one entry per line):
+CHIP8MOVIE 2           - header and format version
+rom <sha1>             - SHA-1 of the ROM bytes the movie was recorded on
+seed <u64>             - seed of the RND byte generator
+quirks <quirk>...      - quirks the CPU ran with, as in the ROM database, none
+                         listed when it ran without. Version 1 has no line.
+<frame> <key> <down|up> - key event, key index in hex, in frame order
+*/
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write, Error, ErrorKind};
+
+use sha1_smol::Sha1;
+
+use constants::KEYS;
+use command::KeyCommand::{self, KeyDownUp};
+use library::Quirk;
+
+const MOVIE_HEADER: &str = "CHIP8MOVIE";
+const MOVIE_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_hash: String,
+    pub rng_seed: u64,
+    pub quirks: Vec<Quirk>,
+    pub events: Vec<(u64, KeyCommand)>
+}
+
+impl Movie {
+    pub fn new(rom_hash: &str, rng_seed: u64) -> Self {
+        Movie {
+            rom_hash: rom_hash.to_string(),
+            rng_seed,
+            quirks: Vec::new(),
+            events: Vec::new()
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, key: KeyCommand) {
+        self.events.push((frame, key))
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        Movie::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{} {}", MOVIE_HEADER, MOVIE_VERSION)?;
+        writeln!(writer, "rom {}", self.rom_hash)?;
+        writeln!(writer, "seed {}", self.rng_seed)?;
+        writeln!(writer, "quirks{}", self.quirks.iter().map(|quirk| format!(" {}", quirk.name())).collect::<String>())?;
+
+        for (frame, KeyDownUp(key_i, key_is_down)) in &self.events {
+            writeln!(writer, "{} {:x} {}",
+                frame,
+                key_i,
+                if *key_is_down { "down" } else { "up" })?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let mut next_line = || lines.next()
+            .unwrap_or_else(|| Err(invalid_movie("unexpected end of file")));
+
+        let version: u32 = parse_field(&next_line()?, MOVIE_HEADER)?;
+        if version == 0 || version > MOVIE_VERSION {
+            return Err(invalid_movie(&format!("unsupported version {}", version)))
+        }
+        let rom_hash: String = parse_field(&next_line()?, "rom")?;
+        let rng_seed: u64 = parse_field(&next_line()?, "seed")?;
+
+        let mut movie = Movie::new(&rom_hash, rng_seed);
+        if version >= 2 { movie.quirks = parse_quirks(&next_line()?)? }
+        for line in lines {
+            let line = line?;
+            if !line.trim().is_empty() {
+                let (frame, key) = parse_event(&line)?;
+                movie.record(frame, key)
+            }
+        }
+
+        Ok(movie)
+    }
+}
+
+// Replays a movie's key events frame by frame
+pub struct MoviePlayer {
+    movie: Movie,
+    next_event: usize
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> Self {
+        MoviePlayer {
+            movie,
+            next_event: 0
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn events_at(&mut self, frame: u64) -> Vec<KeyCommand> {
+        let mut events = Vec::new();
+        while let Some(&(event_frame, key)) = self.movie.events.get(self.next_event) {
+            if event_frame > frame { break }
+            events.push(key);
+            self.next_event += 1
+        }
+        events
+    }
+}
+
+pub fn rom_hash(rom_bytes: &[u8]) -> String {
+    Sha1::from(rom_bytes).digest().to_string()
+}
+
+fn parse_field<T: ::std::str::FromStr>(line: &str, name: &str) -> io::Result<T> {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some(key), Some(value), None) if key == name => value.parse::<T>()
+            .map_err(|_| invalid_movie(&format!("bad value for '{}': {}", name, value))),
+        _ => Err(invalid_movie(&format!("expected '{}' line, found: {}", name, line)))
+    }
+}
+
+fn parse_quirks(line: &str) -> io::Result<Vec<Quirk>> {
+    let mut words = line.split_whitespace();
+    if words.next() != Some("quirks") {
+        return Err(invalid_movie(&format!("expected 'quirks' line, found: {}", line)))
+    }
+    words.map(|name| Quirk::parse(name).ok_or_else(|| invalid_movie(&format!("unknown quirk: {}", name))))
+        .collect()
+}
+
+fn parse_event(line: &str) -> io::Result<(u64, KeyCommand)> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let bad_event = || invalid_movie(&format!("bad key event: {}", line));
+
+    if words.len() != 3 { return Err(bad_event()) }
+    let frame = words[0].parse::<u64>().map_err(|_| bad_event())?;
+    let key_i = usize::from_str_radix(words[1], 16).map_err(|_| bad_event())?;
+    let key_is_down = match words[2] {
+        "down" => true,
+        "up" => false,
+        _ => return Err(bad_event())
+    };
+
+    if key_i >= KEYS { return Err(bad_event()) }
+    Ok((frame, KeyDownUp(key_i, key_is_down)))
+}
+
+fn invalid_movie(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid movie file: {}", message))
+}
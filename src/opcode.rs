@@ -1,7 +1,7 @@
 use std::fmt::{self, Debug, Display, Formatter};
 use self::{OpcodeType::*, Operation::*};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Operation {
     SYS,
     CLS,
@@ -27,7 +27,7 @@ pub enum Operation {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OpcodeType {
     NONE,               // **** -> exact match
     NNN(u16),           // *NNN -> address nnn
@@ -74,7 +74,7 @@ impl Display for OpcodeType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Opcode(pub Operation, pub OpcodeType);
 
 impl Display for Opcode {
@@ -117,9 +117,9 @@ impl OpcodeDisassembler {
                     0x3 => Opcode(XOR, XY(x, y)),   // Vx = Vx ^ Vy
                     0x4 => Opcode(ADD, XY(x, y)),   // Vx += Vy
                     0x5 => Opcode(SUB, XY(x, y)),   // Vx -= Vy
-                    0x6 => Opcode(SHR, X(x)),       // Vx >> 1
+                    0x6 => Opcode(SHR, XY(x, y)),   // Vx >> 1, or Vy with the shift_vy quirk
                     0x7 => Opcode(SUBN, XY(x, y)),  // Vx = Vy - Vx
-                    0xE => Opcode(SHL, X(x)),       // Vx << 1
+                    0xE => Opcode(SHL, XY(x, y)),   // Vx << 1, or Vy with the shift_vy quirk
                     _ => Opcode(UNDEFINED, NONE)
                 },
                 0x9 => match n {
@@ -152,3 +152,40 @@ impl OpcodeDisassembler {
         }
     }
 }
+
+// Disassembled opcodes by address, so code that runs repeatedly is decoded once.
+// Writes to memory must invalidate the addresses they touch.
+pub struct OpcodeCache {
+    opcodes: Vec<Option<Opcode>>
+}
+
+impl OpcodeCache {
+    pub fn new(size: usize) -> Self {
+        OpcodeCache {
+            opcodes: vec![None; size]
+        }
+    }
+
+    pub fn get_or_decode(&mut self, memory: &[u8], addr: usize) -> Opcode {
+        match self.opcodes[addr] {
+            Some(opcode) => opcode,
+            None => {
+                let instruction = (memory[addr] as u16) << 8 | memory[(addr + 1) % memory.len()] as u16;
+                let opcode = OpcodeDisassembler::disassemble(instruction);
+                self.opcodes[addr] = Some(opcode);
+                opcode
+            }
+        }
+    }
+
+    // A written byte is part of the instructions starting at it and the byte before
+    pub fn invalidate(&mut self, addr: usize) {
+        let size = self.opcodes.len();
+        self.opcodes[addr % size] = None;
+        self.opcodes[(addr + size - 1) % size] = None
+    }
+
+    pub fn clear(&mut self) {
+        self.opcodes.iter_mut().for_each(|opcode| *opcode = None)
+    }
+}
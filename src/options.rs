@@ -0,0 +1,105 @@
+use std::env;
+
+use chip8::Engine;
+use pacing::FAST_FORWARD_MULTIPLIER;
+
+const USAGE: &str = "Usage: chip8-emulator [--seed <u64>] [--record <movie>] [--replay <movie>] \
+    [--engine <interpreter|translated>] [--speed <cycles per second>] [--fast-forward <multiplier>] \
+    [--rom-dir <directory>]... [--rom-db <file>] [--terminal-menu] [--menu-history <file>] [--profile <file>] [--gdb <port>] [--script <file>] [--cheats <file>]";
+
+const DEFAULT_ROM_DIR: &str = "./ROMs/";
+const DEFAULT_MENU_HISTORY: &str = "./menu_history.txt";
+
+pub struct Options {
+    pub rng_seed: Option<u64>,
+    pub record_path: Option<String>,
+    pub replay_path: Option<String>,
+    pub engine: Engine,
+    // Unless set, the ROM's recommended speed or the default is used
+    pub cycles_per_second: Option<u32>,
+    pub fast_forward_multiplier: u32,
+    pub rom_dirs: Vec<String>,
+    pub rom_db_path: Option<String>,
+    pub is_terminal_menu: bool,
+    // Recently played ROMs and favourites for the terminal menu
+    pub menu_history_path: String,
+    // Where to write the coverage and hotspot report when the game exits
+    pub profile_path: Option<String>,
+    // Waits for GDB to connect on this local port before the game starts
+    pub gdb_port: Option<u16>,
+    // Rhai script with hooks that drive the game, e.g. a bot
+    pub script_path: Option<String>,
+    // Cheat codes by ROM, the ones for the game played are turned on
+    pub cheats_path: Option<String>
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, String> {
+        Options::parse(env::args().skip(1))
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Options {
+            rng_seed: None,
+            record_path: None,
+            replay_path: None,
+            engine: Engine::Interpreter,
+            cycles_per_second: None,
+            fast_forward_multiplier: FAST_FORWARD_MULTIPLIER,
+            rom_dirs: Vec::new(),
+            rom_db_path: None,
+            is_terminal_menu: false,
+            menu_history_path: DEFAULT_MENU_HISTORY.to_string(),
+            profile_path: None,
+            gdb_port: None,
+            script_path: None,
+            cheats_path: None
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => options.rng_seed = Some(Options::value(&arg, &mut args)?
+                    .parse::<u64>()
+                    .map_err(|_| format!("Seed must be an unsigned integer.\n{}", USAGE))?),
+                "--record" => options.record_path = Some(Options::value(&arg, &mut args)?),
+                "--replay" => options.replay_path = Some(Options::value(&arg, &mut args)?),
+                "--engine" => options.engine = match Options::value(&arg, &mut args)?.as_str() {
+                    "interpreter" => Engine::Interpreter,
+                    "translated" => Engine::Translated,
+                    _ => return Err(format!("Unknown engine.\n{}", USAGE))
+                },
+                "--speed" => options.cycles_per_second = Some(Options::positive(&arg, &mut args)?),
+                "--fast-forward" => options.fast_forward_multiplier = Options::positive(&arg, &mut args)?,
+                "--rom-dir" => options.rom_dirs.push(Options::value(&arg, &mut args)?),
+                "--rom-db" => options.rom_db_path = Some(Options::value(&arg, &mut args)?),
+                "--terminal-menu" => options.is_terminal_menu = true,
+                "--menu-history" => options.menu_history_path = Options::value(&arg, &mut args)?,
+                "--profile" => options.profile_path = Some(Options::value(&arg, &mut args)?),
+                "--gdb" => options.gdb_port = Some(Options::value(&arg, &mut args)?
+                    .parse::<u16>()
+                    .map_err(|_| format!("GDB port must be a number from 0 to 65535.\n{}", USAGE))?),
+                "--script" => options.script_path = Some(Options::value(&arg, &mut args)?),
+                "--cheats" => options.cheats_path = Some(Options::value(&arg, &mut args)?),
+                _ => return Err(format!("Unknown argument '{}'.\n{}", arg, USAGE))
+            }
+        }
+
+        if options.rom_dirs.is_empty() {
+            options.rom_dirs.push(DEFAULT_ROM_DIR.to_string())
+        }
+
+        Ok(options)
+    }
+
+    fn positive<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<u32, String> {
+        Options::value(flag, args)?
+            .parse::<u32>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or(format!("'{}' must be a positive integer.\n{}", flag, USAGE))
+    }
+
+    fn value<I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<String, String> {
+        args.next().ok_or(format!("Missing value for '{}'.\n{}", flag, USAGE))
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use pacing::RateMeter;
+
+// Messages stay up this long, fading out over the last FADE_TIME
+pub const MESSAGE_TIME: Duration = Duration::from_secs(3);
+const FADE_TIME: Duration = Duration::from_secs(1);
+const MAX_MESSAGES: usize = 4;
+
+// Glyphs are 3x5 font pixels, with a blank column between characters
+pub const GLYPH_W: usize = 3;
+pub const GLYPH_H: usize = 5;
+const GLYPH_ADVANCE: usize = GLYPH_W + 1;
+
+// Emulator state reported to the front end for the status line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Status {
+    pub cycles_per_second: u32,
+    pub is_paused: bool
+}
+
+/*
+On-screen display drawn over the game: a status line with the frame rate,
+emulated cycles per second and pause state, and the latest messages, which fade
+out after a few seconds. The status line can be toggled, but a paused game is
+always marked as paused.
+*/
+pub struct Osd {
+    is_status_shown: bool,
+    status: Status,
+    fps: u32,
+    frame_meter: RateMeter,
+    messages: VecDeque<(String, Instant)>,
+    is_dirty: bool
+}
+
+impl Default for Osd {
+    fn default() -> Self {
+        Osd::new()
+    }
+}
+
+impl Osd {
+    pub fn new() -> Self {
+        Osd {
+            is_status_shown: false,
+            status: Status { cycles_per_second: 0, is_paused: false },
+            fps: 0,
+            frame_meter: RateMeter::new(Instant::now()),
+            messages: VecDeque::new(),
+            is_dirty: false
+        }
+    }
+
+    pub fn toggle_status(&mut self) {
+        self.is_status_shown = !self.is_status_shown;
+        self.is_dirty = true
+    }
+
+    pub fn set_status(&mut self, status: Status) {
+        self.is_dirty |= self.is_status_shown || status.is_paused != self.status.is_paused;
+        self.status = status
+    }
+
+    pub fn show_message(&mut self, text: &str, now: Instant) {
+        if self.messages.len() == MAX_MESSAGES { self.messages.pop_front(); }
+        self.messages.push_back((text.to_uppercase(), now));
+        self.is_dirty = true
+    }
+
+    pub fn frame_presented(&mut self, now: Instant) {
+        if let Some(fps) = self.frame_meter.tick(now) {
+            self.fps = fps
+        }
+    }
+
+    // Something changed or is still fading since the last time lines were taken
+    pub fn needs_redraw(&self) -> bool {
+        self.is_dirty || !self.messages.is_empty()
+    }
+
+    // Text to draw, one entry per line from the top, with its opacity (0-255)
+    pub fn lines(&mut self, now: Instant) -> Vec<(String, u8)> {
+        self.is_dirty = false;
+        self.messages.retain(|(_, shown)| now.saturating_duration_since(*shown) < MESSAGE_TIME);
+
+        let status_line = match (self.is_status_shown, self.status.is_paused) {
+            (true, is_paused) => Some(format!("FPS {} CPS {}{}",
+                self.fps,
+                self.status.cycles_per_second,
+                if is_paused { " PAUSED" } else { "" })),
+            (false, true) => Some("PAUSED".to_string()),
+            (false, false) => None
+        };
+
+        status_line.map(|line| (line, 0xFF)).into_iter()
+            .chain(self.messages.iter().map(|(text, shown)| {
+                let remaining = MESSAGE_TIME - now.saturating_duration_since(*shown);
+                let alpha = (0xFF * remaining.as_millis() / FADE_TIME.as_millis()).min(0xFF);
+                (text.clone(), alpha as u8)
+            }))
+            .collect()
+    }
+}
+
+pub fn text_width(text: &str) -> usize {
+    (text.chars().count() * GLYPH_ADVANCE).saturating_sub(1)
+}
+
+// Lit font pixels (x, y) of a line of text, from its top left corner
+pub fn text_pixels(text: &str) -> Vec<(usize, usize)> {
+    text.chars()
+        .enumerate()
+        .flat_map(|(i, c)| {
+            let rows = glyph(c);
+            (0..GLYPH_H).flat_map(move |y| (0..GLYPH_W)
+                .filter(move |x| rows[y] & (0b100 >> x) != 0)
+                .map(move |x| (i * GLYPH_ADVANCE + x, y)))
+        })
+        .collect()
+}
+
+// Rows of a 3x5 glyph, the high bit of each row is the leftmost pixel
+fn glyph(c: char) -> [u8; GLYPH_H] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010] // '?'
+    }
+}
@@ -0,0 +1,113 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+pub const CYCLES_PER_SECOND: u32 = 1200;
+pub const FAST_FORWARD_MULTIPLIER: u32 = 5;
+
+// Sleeps shorter than this are too coarse to be worth it, the wait builds up instead
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+
+// Further behind than this, e.g. after the host stalled, the schedule restarts
+// instead of racing through the missed cycles
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/*
+Keeps emulation at a steady rate. Each cycle is due at a fixed time counted from
+the start of the schedule, rather than after a fixed sleep, so time spent
+emulating and oversleeping is made up and doesn't drift.
+*/
+pub struct Pacer {
+    cycles_per_second: u32,
+    multiplier: u32,
+    start: Instant,
+    cycles: u64
+}
+
+impl Pacer {
+    pub fn new(cycles_per_second: u32) -> Self {
+        Pacer {
+            cycles_per_second,
+            multiplier: 1,
+            start: Instant::now(),
+            cycles: 0
+        }
+    }
+
+    pub fn cycles_per_second(&self) -> u32 {
+        self.cycles_per_second
+    }
+
+    pub fn set_multiplier(&mut self, multiplier: u32, now: Instant) {
+        if multiplier != self.multiplier {
+            self.multiplier = multiplier;
+            self.restart(now)
+        }
+    }
+
+    pub fn restart(&mut self, now: Instant) {
+        self.start = now;
+        self.cycles = 0
+    }
+
+    pub fn wait_cycles(&mut self, cycles: u64) {
+        if let Some(delay) = self.cycles_delay(cycles, Instant::now()) {
+            sleep(delay)
+        }
+    }
+
+    // Counts a finished cycle and returns how long to wait until the next one is due
+    pub fn cycle_delay(&mut self, now: Instant) -> Option<Duration> {
+        self.cycles_delay(1, now)
+    }
+
+    // The same for cycles run in one go
+    pub fn cycles_delay(&mut self, cycles: u64, now: Instant) -> Option<Duration> {
+        self.cycles += cycles;
+        let rate = self.cycles_per_second as u128 * self.multiplier as u128;
+        let due = self.start + Duration::from_nanos((self.cycles as u128 * 1_000_000_000 / rate) as u64);
+
+        if due > now {
+            Some(due - now).filter(|&delay| delay >= MIN_SLEEP)
+        } else {
+            if now - due > MAX_LAG { self.restart(now) }
+            None
+        }
+    }
+}
+
+// Counts events, e.g. cycles or presented frames, over windows of about a second
+pub struct RateMeter {
+    window_start: Instant,
+    count: u32
+}
+
+impl RateMeter {
+    pub fn new(now: Instant) -> Self {
+        RateMeter {
+            window_start: now,
+            count: 0
+        }
+    }
+
+    pub fn restart(&mut self, now: Instant) {
+        self.window_start = now;
+        self.count = 0
+    }
+
+    // Counts an event and returns the rate per second once the window has passed
+    pub fn tick(&mut self, now: Instant) -> Option<u32> {
+        self.tick_by(1, now)
+    }
+
+    pub fn tick_by(&mut self, count: u32, now: Instant) -> Option<u32> {
+        self.count += count;
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < RATE_WINDOW { return None }
+
+        let rate = self.count as u128 * 1_000_000_000 / elapsed.as_nanos();
+        self.restart(now);
+        Some(rate as u32)
+    }
+}
@@ -0,0 +1,124 @@
+/*
+Profile report format (plain text):
+- instructions run, in total and by operation, most run first
+- annotated disassembly of the ROM: address, instruction word, hits,
+  % of all instructions run and the instruction. Code run at odd
+  addresses is shown from there, with the byte before it as data.
+- never executed code: instructions reachable from the start of the ROM
+  that didn't run, as address ranges
+Hits count instructions at their address in RAM, so code the ROM
+overwrote is shown as the ROM's original bytes.
+*/
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use byteorder::{ByteOrder, BigEndian};
+
+use analysis::trace;
+use constants::{RAM_BYTES, ROM_ADDR};
+use opcode::{OpcodeDisassembler, Operation};
+
+pub struct Profile {
+    hits: Vec<u64>,
+    operations: HashMap<Operation, u64>,
+    total: u64
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::new()
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile {
+            hits: vec![0; RAM_BYTES],
+            operations: HashMap::new(),
+            total: 0
+        }
+    }
+
+    pub fn record(&mut self, addr: usize, operation: Operation) {
+        self.hits[addr % RAM_BYTES] += 1;
+        *self.operations.entry(operation).or_insert(0) += 1;
+        self.total += 1
+    }
+
+    pub fn hits(&self, addr: usize) -> u64 {
+        self.hits[addr % RAM_BYTES]
+    }
+
+    pub fn operation_hits(&self, operation: Operation) -> u64 {
+        self.operations.get(&operation).cloned().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    // Start and end addresses of reachable code that never ran
+    pub fn never_executed(&self, rom_bytes: &[u8]) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for &addr in trace(rom_bytes).code.keys().filter(|&&addr| self.hits(addr) == 0) {
+            match ranges.last_mut() {
+                Some(&mut (_, ref mut end)) if *end + 2 == addr => *end = addr,
+                _ => ranges.push((addr, addr))
+            }
+        }
+        ranges
+    }
+
+    pub fn save(&self, path: &str, rom_bytes: &[u8]) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_report(&mut writer, rom_bytes)?;
+        writer.flush()
+    }
+
+    pub fn write_report<W: Write>(&self, writer: &mut W, rom_bytes: &[u8]) -> io::Result<()> {
+        writeln!(writer, "Instructions run: {}", self.total)?;
+
+        let mut operations = self.operations.iter().collect::<Vec<(&Operation, &u64)>>();
+        operations.sort_by(|a, b| b.1.cmp(a.1).then_with(|| format!("{:?}", a.0).cmp(&format!("{:?}", b.0))));
+        for (operation, &hits) in operations {
+            writeln!(writer, "  {:<9} {:>12} {:>6.2}%", format!("{:?}", operation), hits, self.percent(hits))?
+        }
+
+        writeln!(writer, "\nDisassembly:")?;
+        let end = ROM_ADDR + rom_bytes.len();
+        let mut addr = ROM_ADDR;
+        while addr < end {
+            let offset = addr - ROM_ADDR;
+            if addr + 1 == end || (self.hits(addr) == 0 && self.hits(addr + 1) > 0) {
+                writeln!(writer, "{:03x}: {:02X}", addr, rom_bytes[offset])?;
+                addr += 1;
+                continue
+            }
+
+            let instruction = BigEndian::read_u16(&rom_bytes[offset..offset + 2]);
+            let hits = self.hits(addr);
+            if hits > 0 {
+                writeln!(writer, "{:03x}: {:04X} {:>12} {:>6.2}%  '{}'", addr, instruction, hits, self.percent(hits),
+                    OpcodeDisassembler::disassemble(instruction))?
+            } else {
+                writeln!(writer, "{:03x}: {:04X} {:>12} {:>7}  '{}'", addr, instruction, "-", "",
+                    OpcodeDisassembler::disassemble(instruction))?
+            }
+            addr += 2
+        }
+
+        writeln!(writer, "\nNever executed:")?;
+        let never_executed = self.never_executed(rom_bytes);
+        if never_executed.is_empty() { writeln!(writer, "  none")? }
+        for (start, end) in never_executed {
+            writeln!(writer, "  {:03x}-{:03x} ({} instructions)", start, end, (end - start) / 2 + 1)?
+        }
+        Ok(())
+    }
+
+    fn percent(&self, hits: u64) -> f64 {
+        if self.total == 0 { 0.0 } else { hits as f64 * 100.0 / self.total as f64 }
+    }
+}
@@ -1,5 +1,120 @@
-use rand::Rng;
+use rand::{self, Rng};
 
-pub fn rng_byte() -> u8 {
-    rand::thread_rng().gen_range(0,256) as u8
+// Source of the bytes returned by RND Vx, nn
+pub trait RandomSource {
+    fn rng_byte(&mut self) -> u8;
+
+    // State to resume the same byte sequence from, None if it can't be reproduced
+    fn state(&self) -> Option<RngState>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RngState {
+    Seeded([u32; 4]),
+    Scripted(Vec<u8>, usize)
+}
+
+impl RngState {
+    pub fn restore(&self) -> Box<dyn RandomSource> {
+        match self {
+            RngState::Seeded(state) => Box::new(SeededRandom { state: *state }),
+            RngState::Scripted(bytes, next) => Box::new(ScriptedRandom {
+                bytes: bytes.clone(),
+                next: *next
+            })
+        }
+    }
+}
+
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen::<u64>()
+}
+
+pub fn from_seed(seed: Option<u64>) -> Box<dyn RandomSource> {
+    match seed {
+        Some(seed) => Box::new(SeededRandom::new(seed)),
+        None => Box::new(OsRandom)
+    }
+}
+
+// xorshift128 generator, fully determined by its seed
+pub struct SeededRandom {
+    state: [u32; 4]
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 spreads the seed bits so nearby seeds give unrelated sequences
+        let mut split = seed;
+        let mut next_word = || {
+            split = split.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = split;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let (a, b) = (next_word(), next_word());
+        let mut state = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+
+        // xorshift never leaves the all zero state
+        if state == [0; 4] { state[0] = 1 }
+
+        SeededRandom { state }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn rng_byte(&mut self) -> u8 {
+        let [x, y, z, w] = self.state;
+        let t = x ^ (x << 11);
+        let next = w ^ (w >> 19) ^ t ^ (t >> 8);
+        self.state = [y, z, w, next];
+
+        (next >> 24) as u8
+    }
+
+    fn state(&self) -> Option<RngState> {
+        Some(RngState::Seeded(self.state))
+    }
+}
+
+// Thread generator seeded by the OS, not reproducible
+pub struct OsRandom;
+
+impl RandomSource for OsRandom {
+    fn rng_byte(&mut self) -> u8 {
+        rand::thread_rng().gen::<u8>()
+    }
+
+    fn state(&self) -> Option<RngState> {
+        None
+    }
+}
+
+// Repeats a fixed byte sequence, for tests
+pub struct ScriptedRandom {
+    bytes: Vec<u8>,
+    next: usize
+}
+
+impl ScriptedRandom {
+    pub fn new(bytes: &[u8]) -> Self {
+        assert!(!bytes.is_empty(), "Scripted random source needs at least one byte");
+        ScriptedRandom {
+            bytes: bytes.to_vec(),
+            next: 0
+        }
+    }
+}
+
+impl RandomSource for ScriptedRandom {
+    fn rng_byte(&mut self) -> u8 {
+        let byte = self.bytes[self.next];
+        self.next = (self.next + 1) % self.bytes.len();
+        byte
+    }
+
+    fn state(&self) -> Option<RngState> {
+        Some(RngState::Scripted(self.bytes.clone(), self.next))
+    }
 }
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use sdl2::pixels::Color;
+
+use chip8::Quirks;
+use constants::{BLACK, WHITE};
+use emulator::Chip8Emulator;
+use io::HeadlessIO;
+use pacing::CYCLES_PER_SECOND;
+use rng::SeededRandom;
+use library::{Library, RomInfo};
+use rom_files::{RomEntry, RomSource, scan_roots};
+
+// ROMs listed at once, and how far Page Up/Down move
+pub const BROWSER_ROWS: usize = 24;
+
+// How long a game runs before its screen is shown, at its own speed
+const PREVIEW_SECONDS: u64 = 5;
+const PREVIEW_SEED: u64 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BrowserInput {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    First,
+    Last
+}
+
+/*
+In-window ROM picker. Lists the ROMs found in the library roots, keeps the
+selection on screen and previews the selected ROM by running it headlessly,
+with the settings the library has for it.
+*/
+pub struct RomBrowser {
+    roms: Vec<(String, RomSource, Option<RomInfo>)>,
+    selected: usize,
+    scroll: usize,
+    previews: HashMap<usize, Option<Vec<bool>>>
+}
+
+impl RomBrowser {
+    pub fn new(roms: Vec<RomEntry>) -> Self {
+        RomBrowser::with_info(roms.into_iter().map(|rom| (rom, None)).collect())
+    }
+
+    fn with_info(roms: Vec<(RomEntry, Option<RomInfo>)>) -> Self {
+        RomBrowser {
+            roms: roms.into_iter().map(|(rom, info)| (rom.name, rom.source, info)).collect(),
+            selected: 0,
+            scroll: 0,
+            previews: HashMap::new()
+        }
+    }
+
+    // ROMs found under the roots, sorted by name. ROMs in the library are listed by
+    // title, others by their path below the root. What can't be read is reported.
+    pub fn from_roots(roots: &[String], library: &Library) -> Self {
+        let scan = scan_roots(roots);
+        scan.errors.iter().for_each(|e| println!("Warning: {}", e));
+
+        let mut roms = scan.roms.into_iter()
+            .map(|rom| {
+                let info = rom.source.read().ok()
+                    .and_then(|rom_bytes| library.lookup(&rom_bytes).cloned());
+                let name = info.as_ref().map_or(rom.name, |info| info.title.clone());
+                (RomEntry { name, source: rom.source }, info)
+            })
+            .collect::<Vec<(RomEntry, Option<RomInfo>)>>();
+        roms.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        RomBrowser::with_info(roms)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn selected_rom(&self) -> Option<&RomSource> {
+        self.roms.get(self.selected).map(|(_, source, _)| source)
+    }
+
+    pub fn move_selection(&mut self, input: BrowserInput) {
+        let last = self.roms.len().saturating_sub(1);
+        self.selected = match input {
+            BrowserInput::Up => self.selected.saturating_sub(1),
+            BrowserInput::Down => (self.selected + 1).min(last),
+            BrowserInput::PageUp => self.selected.saturating_sub(BROWSER_ROWS),
+            BrowserInput::PageDown => (self.selected + BROWSER_ROWS).min(last),
+            BrowserInput::First => 0,
+            BrowserInput::Last => last
+        };
+
+        // Scroll just far enough to keep the selection in view
+        if self.selected < self.scroll {
+            self.scroll = self.selected
+        } else if self.selected >= self.scroll + BROWSER_ROWS {
+            self.scroll = self.selected + 1 - BROWSER_ROWS
+        }
+    }
+
+    // Names of the ROMs in view, flagged when selected
+    pub fn visible_rows(&self) -> Vec<(String, bool)> {
+        self.roms.iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(BROWSER_ROWS)
+            .map(|(i, (name, _, _))| (name.clone(), i == self.selected))
+            .collect()
+    }
+
+    // Screen of the selected ROM after a few seconds and the colours to draw it in,
+    // None if it can't be run
+    pub fn preview(&mut self) -> Option<(&[bool], (Color, Color))> {
+        let RomBrowser {roms, selected, previews, ..} = self;
+        let (_, source, info) = roms.get(*selected)?;
+        let colours = info.as_ref().and_then(|info| info.colours).unwrap_or((WHITE, BLACK));
+        previews.entry(*selected)
+            .or_insert_with(|| source.read().ok().and_then(|rom_bytes| run_preview(&rom_bytes, info.as_ref())))
+            .as_ref()
+            .map(|pixels| (&pixels[..], colours))
+    }
+}
+
+// Runs a ROM without input and returns its screen, None if it can't be loaded
+pub fn run_preview(rom_bytes: &[u8], info: Option<&RomInfo>) -> Option<Vec<bool>> {
+    let mut emulator = Chip8Emulator::new(
+        Box::new(HeadlessIO::new()),
+        Box::new(SeededRandom::new(PREVIEW_SEED)));
+    let speed = info.and_then(|info| info.speed).unwrap_or(CYCLES_PER_SECOND);
+    if let Some(info) = info { emulator.set_quirks(Quirks::new(&info.quirks)) }
+    emulator.load_game(rom_bytes).ok()?;
+    emulator.run_cycles(PREVIEW_SECONDS * speed as u64);
+    Some(emulator.get_pixels().to_vec())
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use zip::ZipArchive;
+
+// Files with these extensions, or none at all like the bundled ROMs, are listed
+pub const ROM_EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+const ARCHIVE_EXTENSION: &str = "zip";
+
+// Guards against symlink loops
+const MAX_DEPTH: usize = 16;
+
+// Where a ROM's bytes are read from
+#[derive(Debug, Clone, PartialEq)]
+pub enum RomSource {
+    File(PathBuf),
+    Zipped(PathBuf, String)
+}
+
+impl RomSource {
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        match self {
+            RomSource::File(path) => fs::read(path),
+            RomSource::Zipped(archive_path, entry_name) => {
+                let mut archive = ZipArchive::new(File::open(archive_path)?).map_err(zip_error)?;
+                let mut entry = archive.by_name(entry_name).map_err(zip_error)?;
+                let mut rom_bytes = Vec::new();
+                entry.read_to_end(&mut rom_bytes)?;
+                Ok(rom_bytes)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RomSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomSource::File(path) => write!(f, "{}", path.display()),
+            RomSource::Zipped(archive_path, entry_name) => write!(f, "{}:{}", archive_path.display(), entry_name)
+        }
+    }
+}
+
+// A ROM found in a library, named by its path below the library root
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomEntry {
+    pub name: String,
+    pub source: RomSource
+}
+
+// ROMs found under the library roots, sorted by name, and what couldn't be read
+pub struct Scan {
+    pub roms: Vec<RomEntry>,
+    pub errors: Vec<String>
+}
+
+/*
+Lists the ROMs under each root, looking through subdirectories and inside zip
+archives. Hidden files are skipped. Entries that can't be read are reported in
+the scan's errors instead of stopping it.
+*/
+pub fn scan_roots(roots: &[String]) -> Scan {
+    let mut scan = Scan { roms: Vec::new(), errors: Vec::new() };
+    for root in roots {
+        scan_dir(Path::new(root), "", 0, &mut scan)
+    }
+    scan.roms.sort_by(|a, b| a.name.cmp(&b.name));
+    scan
+}
+
+fn scan_dir(dir: &Path, prefix: &str, depth: usize, scan: &mut Scan) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return scan.errors.push(format!("Can't read '{}': {}", dir.display(), e))
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => { scan.errors.push(format!("Can't read an entry of '{}': {}", dir.display(), e)); continue }
+        };
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.starts_with('.') { continue }
+        let name = format!("{}{}", prefix, file_name);
+
+        if path.is_dir() {
+            if depth < MAX_DEPTH {
+                scan_dir(&path, &format!("{}/", name), depth + 1, scan)
+            }
+        } else if has_extension(&path, ARCHIVE_EXTENSION) {
+            scan_archive(&path, &name, scan)
+        } else if is_rom_name(&path) {
+            scan.roms.push(RomEntry { name, source: RomSource::File(path) })
+        }
+    }
+}
+
+fn scan_archive(archive_path: &Path, name: &str, scan: &mut Scan) {
+    let archive = File::open(archive_path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| ZipArchive::new(file).map_err(|e| e.to_string()));
+    let archive = match archive {
+        Ok(archive) => archive,
+        Err(e) => return scan.errors.push(format!("Can't read '{}': {}", archive_path.display(), e))
+    };
+
+    for entry_name in archive.file_names() {
+        if !entry_name.ends_with('/') && is_rom_name(Path::new(entry_name)) {
+            scan.roms.push(RomEntry {
+                name: format!("{}/{}", name, entry_name),
+                source: RomSource::Zipped(archive_path.to_path_buf(), entry_name.to_string())
+            })
+        }
+    }
+}
+
+fn is_rom_name(path: &Path) -> bool {
+    match path.extension() {
+        Some(_) => ROM_EXTENSIONS.iter().any(|extension| has_extension(path, extension)),
+        None => path.file_name().is_some_and(|name| !name.to_string_lossy().starts_with('.'))
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case(extension))
+}
+
+fn zip_error(e: ::zip::result::ZipError) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
@@ -0,0 +1,91 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use constants::{RAM_BYTES, ROM_ADDR};
+use library::Platform;
+use analysis::trace;
+use opcode::{Opcode, Operation::*};
+use rom_files::RomSource;
+
+// Addresses listed in a warning before the rest are counted
+const MAX_LISTED_ADDRS: usize = 4;
+
+#[derive(Debug)]
+pub enum RomError {
+    Unreadable(io::Error),
+    Empty,
+    TooLarge { size: usize, max: usize, platform: Platform }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Unreadable(e) => write!(f, "can't read the ROM: {}", e),
+            RomError::Empty => write!(f, "the ROM is empty"),
+            RomError::TooLarge { size, max, platform } =>
+                write!(f, "the ROM is {} bytes, {} memory fits at most {}", size, platform, max)
+        }
+    }
+}
+
+impl error::Error for RomError {}
+
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        RomError::Unreadable(e)
+    }
+}
+
+// ROM bytes that fit in memory, with what may stop them running right
+#[derive(Debug)]
+pub struct CheckedRom {
+    pub bytes: Vec<u8>,
+    pub warnings: Vec<String>
+}
+
+pub fn load_rom(source: &RomSource, platform: Platform) -> Result<CheckedRom, RomError> {
+    check_rom(source.read()?, platform)
+}
+
+pub fn check_rom(rom_bytes: Vec<u8>, platform: Platform) -> Result<CheckedRom, RomError> {
+    check_size(&rom_bytes, platform)?;
+
+    let mut warnings = Vec::new();
+    if rom_bytes.len() % 2 == 1 {
+        warnings.push(format!("The ROM is {} bytes, an odd length, so it may be truncated", rom_bytes.len()))
+    }
+
+    let unsupported = unsupported_addrs(&rom_bytes);
+    if !unsupported.is_empty() {
+        let listed = unsupported.iter()
+            .take(MAX_LISTED_ADDRS)
+            .map(|addr| format!("{:03X}", addr))
+            .collect::<Vec<String>>();
+        let more = unsupported.len().saturating_sub(MAX_LISTED_ADDRS);
+        warnings.push(format!("Instructions not supported in {} mode at {}{}", platform, listed.join(", "),
+            if more > 0 { format!(" and {} more", more) } else { String::new() }))
+    }
+
+    Ok(CheckedRom { bytes: rom_bytes, warnings })
+}
+
+// The emulator's memory also caps platforms with more
+pub fn check_size(rom_bytes: &[u8], platform: Platform) -> Result<(), RomError> {
+    let max = platform.memory_bytes().min(RAM_BYTES) - ROM_ADDR;
+    if rom_bytes.is_empty() {
+        Err(RomError::Empty)
+    } else if rom_bytes.len() > max {
+        Err(RomError::TooLarge { size: rom_bytes.len(), max, platform })
+    } else {
+        Ok(())
+    }
+}
+
+// Undefined and machine code instructions the program can reach from its start
+pub fn unsupported_addrs(rom_bytes: &[u8]) -> Vec<usize> {
+    trace(rom_bytes).code.values()
+        .filter(|instruction| matches!(instruction.opcode, Opcode(UNDEFINED, _) | Opcode(SYS, _)))
+        .map(|instruction| instruction.addr)
+        .collect()
+}
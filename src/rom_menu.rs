@@ -1,42 +1,190 @@
-use std::fs::read_dir;
-use std::path::Path;
-use std::io::stdin;
+use std::io::{self, stdin, stdout, IsTerminal, Stdout, Write};
+
+use crossterm::{queue, execute};
+use crossterm::cursor::MoveTo;
+use crossterm::event::{read, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Print, SetAttribute, Attribute};
+use crossterm::terminal::{enable_raw_mode, disable_raw_mode, Clear, ClearType};
+
+use rom_files::{RomSource, scan_roots};
+use rom_search::{search, suggest};
+use menu_history::MenuHistory;
 
 const MENU_ROWS: usize = 4;
 const MENU_COL_LEN: usize = 10;
 
-pub fn choose_rom() -> String {
-    let roms_dir = Path::new("./ROMs/");
-    let rom_name: String;
-    print_roms(&get_rom_names(&roms_dir));
+// ROMs listed at once in the interactive menu
+const MENU_HEIGHT: usize = 15;
+
+/*
+Asks for a ROM in the terminal. In an interactive terminal the list is filtered
+by fuzzy search as you type, otherwise the name is read from a line of input.
+Played ROMs and favourites are kept in the history file. None when there are
+no ROMs or none was chosen.
+*/
+pub fn choose_rom(roots: &[String], history_path: &str) -> Option<RomSource> {
+    let scan = scan_roots(roots);
+    scan.errors.iter().for_each(|e| println!("Warning: {}", e));
+    if scan.roms.is_empty() {
+        println!("No ROMs found in {}.", roots.join(", "));
+        return None
+    }
+
+    let mut history = MenuHistory::load(history_path).unwrap_or_else(|e| {
+        println!("Warning: failed to load menu history '{}': {}", history_path, e);
+        MenuHistory::new()
+    });
+
+    let names = scan.roms.iter().map(|rom| rom.name.as_str()).collect::<Vec<&str>>();
+    let chosen = if stdin().is_terminal() {
+        run_interactive(&names, &mut history).unwrap_or_else(|e| {
+            println!("Interactive menu failed ({}), falling back to typing the name.", e);
+            prompt_for_rom(&names)
+        })
+    } else {
+        prompt_for_rom(&names)
+    };
+
+    if let Some(i) = chosen { history.played(names[i]) }
+    if let Err(e) = history.save(history_path) {
+        println!("Warning: failed to save menu history '{}': {}", history_path, e)
+    }
+    chosen.map(|i| scan.roms[i].source.clone())
+}
 
-    'getrom: loop {
-        match get_valid_rom(&roms_dir) {
-            Ok(good_name) => { rom_name = good_name; break 'getrom },
-            Err(bad_name) => println!("Sorry the ROM '{}' does not exist.", bad_name)
+// Favourites, then recently played, then the rest by name while the query is
+// empty, otherwise the best fuzzy matches first
+pub fn menu_order(query: &str, names: &[&str], history: &MenuHistory) -> Vec<usize> {
+    if !query.trim().is_empty() {
+        return search(query, names)
+    }
+
+    let position = |name: &String| names.iter().position(|n| n == name);
+    let mut order = history.favourites.iter().filter_map(position).collect::<Vec<usize>>();
+    for i in history.recent.iter().filter_map(position).chain(0..names.len()) {
+        if !order.contains(&i) { order.push(i) }
+    }
+    order
+}
+
+// Restores the terminal however the menu is left
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+    }
+}
+
+fn run_interactive(names: &[&str], history: &mut MenuHistory) -> io::Result<Option<usize>> {
+    let raw_mode = RawMode::enable()?;
+    let mut stdout = stdout();
+    let mut query = String::new();
+    let mut selected = 0;
+
+    let chosen = loop {
+        let order = menu_order(&query, names, history);
+        selected = selected.min(order.len().saturating_sub(1));
+        let suggestion = if order.is_empty() { suggest(&query, names) } else { None };
+        draw_menu(&mut stdout, &query, names, history, &order, selected, suggestion)?;
+
+        let key = match read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue
+        };
+        let is_control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => break None,
+            KeyCode::Char('c') if is_control => break None,
+            KeyCode::Char('f') if is_control => {
+                if let Some(&i) = order.get(selected) { history.toggle_favourite(names[i]) }
+            },
+            KeyCode::Enter => {
+                if let Some(i) = order.get(selected).cloned().or(suggestion) { break Some(i) }
+            },
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected += 1,
+            KeyCode::PageUp => selected = selected.saturating_sub(MENU_HEIGHT),
+            KeyCode::PageDown => selected += MENU_HEIGHT,
+            KeyCode::Backspace => { query.pop(); selected = 0 },
+            KeyCode::Char(c) if !is_control => { query.push(c); selected = 0 },
+            _ => {}
+        }
+    };
+
+    execute!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
+    drop(raw_mode);
+    Ok(chosen)
+}
+
+fn draw_menu(stdout: &mut Stdout, query: &str, names: &[&str], history: &MenuHistory,
+    order: &[usize], selected: usize, suggestion: Option<usize>) -> io::Result<()>
+{
+    // Raw mode needs explicit carriage returns
+    queue!(stdout, Clear(ClearType::All), MoveTo(0, 0),
+        Print("Up/Down to select, Enter to play, Ctrl+F to mark a favourite, Esc to quit\r\n\r\n"))?;
+
+    let first = (selected + 1).saturating_sub(MENU_HEIGHT);
+    for (row, &i) in order.iter().enumerate().skip(first).take(MENU_HEIGHT) {
+        let tag = if history.is_favourite(names[i]) {
+            " (favourite)"
+        } else if history.recent.iter().any(|recent| recent == names[i]) {
+            " (recent)"
+        } else {
+            ""
+        };
+
+        if row == selected {
+            queue!(stdout, SetAttribute(Attribute::Reverse), Print(format!("> {}{}", names[i], tag)),
+                SetAttribute(Attribute::Reset), Print("\r\n"))?
+        } else {
+            queue!(stdout, Print(format!("  {}{}\r\n", names[i], tag)))?
         }
     }
 
-    roms_dir.join(rom_name).as_path().to_str().unwrap().to_string()
+    if order.is_empty() {
+        queue!(stdout, Print(format!("  No ROM matches '{}'.", query)))?;
+        if let Some(i) = suggestion {
+            queue!(stdout, Print(format!(" Did you mean '{}'? Enter to play it.", names[i])))?
+        }
+        queue!(stdout, Print("\r\n"))?
+    }
+
+    queue!(stdout, Print(format!("\r\n{} of {} ROMs\r\nSearch: {}", order.len(), names.len(), query)))?;
+    stdout.flush()
 }
 
-fn get_rom_names(roms_dir: &Path) -> Vec<String> {
-    read_dir(&roms_dir).expect("ROM directory doesn't exist.")
-        .into_iter()
-        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
-        .collect::<Vec<String>>()
+fn prompt_for_rom(names: &[&str]) -> Option<usize> {
+    print_roms(names);
+
+    loop {
+        match get_valid_rom(names) {
+            Ok(chosen) => return chosen,
+            Err(bad_name) => match suggest(&bad_name, names) {
+                Some(i) => println!("Sorry the ROM '{}' does not exist. Did you mean '{}'?", bad_name, names[i]),
+                None => println!("Sorry the ROM '{}' does not exist.", bad_name)
+            }
+        }
+    }
 }
 
-fn print_roms(rom_names: &Vec<String>) {
-    let mut rom_names_iter = rom_names.clone().into_iter().peekable();
+fn print_roms(rom_names: &[&str]) {
+    let mut rom_names_iter = rom_names.iter().peekable();
     let mut rom_names_grid: Vec<Vec<String>> = Vec::new();
 
-    while let Some(_) = (&mut rom_names_iter).peek() { 
+    while rom_names_iter.peek().is_some() {
         rom_names_grid.push((&mut rom_names_iter)
             .take(MENU_ROWS)
             .map(|name| format!("{:padding$}", name, padding = MENU_COL_LEN))
             .collect::<Vec<String>>()
-        ); 
+        );
     };
 
     println!("\nAvailable ROMS:\n");
@@ -44,16 +192,18 @@ fn print_roms(rom_names: &Vec<String>) {
         .for_each(|v| println!("{}", v.concat()))
 }
 
-fn get_valid_rom(roms_dir: &Path) -> Result<String, String> {
+// Ok(None) once input has run out
+fn get_valid_rom(names: &[&str]) -> Result<Option<usize>, String> {
     let mut rom_name = String::new();
 
     println!("\nPlease choose a ROM to play:");
-    stdin().read_line(&mut rom_name).expect("Failed to read line");
+    if stdin().read_line(&mut rom_name).expect("Failed to read line") == 0 {
+        return Ok(None)
+    }
     rom_name = rom_name.trim_end_matches(char::is_control).to_string();
 
-    if roms_dir.join(&rom_name).exists() {
-        Ok(rom_name)
-    } else {
-        Err(rom_name)
-    }
+    names.iter()
+        .position(|name| *name == rom_name)
+        .map(Some)
+        .ok_or(rom_name)
 }
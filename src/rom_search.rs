@@ -0,0 +1,64 @@
+// Bonuses for query characters that follow the previous match or start a word
+const CONSECUTIVE_BONUS: u32 = 5;
+const WORD_START_BONUS: u32 = 3;
+
+/*
+Scores how well the query matches the name as a subsequence, ignoring case, e.g.
+"inv" and "spinv" both match "Space Invaders". None when it doesn't match. Runs of
+characters and matches at the start of words score higher.
+*/
+pub fn fuzzy_score(query: &str, name: &str) -> Option<u32> {
+    let name_chars = name.chars().flat_map(char::to_lowercase).collect::<Vec<char>>();
+    let mut score = 0;
+    let mut next = 0;
+    let mut last_match: Option<usize> = None;
+
+    for query_char in query.chars().flat_map(char::to_lowercase).filter(|c| !c.is_whitespace()) {
+        let found = (next..name_chars.len()).find(|&i| name_chars[i] == query_char)?;
+        score += 1;
+        if found > 0 && last_match == Some(found - 1) { score += CONSECUTIVE_BONUS }
+        if found == 0 || !name_chars[found - 1].is_alphanumeric() { score += WORD_START_BONUS }
+        next = found + 1;
+        last_match = Some(found)
+    }
+    Some(score)
+}
+
+// Indices of the names matching the query, best first, shorter names first on ties
+pub fn search(query: &str, names: &[&str]) -> Vec<usize> {
+    let mut matches = names.iter()
+        .enumerate()
+        .filter_map(|(i, name)| fuzzy_score(query, name).map(|score| (i, score)))
+        .collect::<Vec<(usize, u32)>>();
+    matches.sort_by_key(|&(i, score)| (u32::MAX - score, names[i].len(), i));
+    matches.into_iter().map(|(i, _)| i).collect()
+}
+
+// The name closest to a query that matches nothing, if it's near enough to be a typo
+pub fn suggest(query: &str, names: &[&str]) -> Option<usize> {
+    let query = query.to_lowercase();
+    let max_distance = (query.chars().count() / 3).max(2);
+    names.iter()
+        .enumerate()
+        .map(|(i, name)| (i, edit_distance(&query, &name.to_lowercase())))
+        .filter(|&(_, distance)| distance <= max_distance)
+        .min_by_key(|&(i, distance)| (distance, i))
+        .map(|(i, _)| i)
+}
+
+// Levenshtein distance, counting characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars = b.chars().collect::<Vec<char>>();
+    let mut row = (0..=b_chars.len()).collect::<Vec<usize>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = diagonal + (a_char != *b_char) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1)
+        }
+    }
+    row[b_chars.len()]
+}
@@ -1,3 +1,7 @@
+use std::mem;
+use std::sync::mpsc::{self, Sender, Receiver, TryRecvError, RecvTimeoutError};
+use std::time::Duration;
+
 pub struct Router<T> {
     inbound_queue: Queue<T>,
     outbound_queue: Queue<T>
@@ -26,18 +30,6 @@ impl <T> Router<T> {
     pub fn consume_all_outbound(&mut self) -> Vec<T> {
         self.outbound_queue.remove_all()
     }
-
-    pub fn forward_inbound(&mut self, other: &mut Self) {
-        self.consume_all_outbound()
-            .into_iter()
-            .for_each(|message| other.send_inbound(message))
-    }
-
-    pub fn forward_outbound(&mut self, other: &mut Self) {
-        self.consume_all_outbound()
-            .into_iter()
-            .for_each(|message| other.send_outbound(message))
-    }
 }
 
 pub struct Queue<T> {
@@ -52,11 +44,73 @@ impl <T> Queue<T> {
     }
 
     pub fn remove_all(&mut self) -> Vec<T> {
-        self.queue.drain(..)
-            .collect()
+        mem::take(&mut self.queue)
     }
 
     pub fn push(&mut self, sent: T) {
         self.queue.push(sent)
     }
 }
+
+/*
+One end of a link between threads. Messages sent outbound on one end arrive
+inbound on the other, in order. Once either end is dropped the link is
+disconnected and further messages are discarded.
+*/
+pub struct ChannelRouter<T> {
+    sender: Sender<T>,
+    receiver: Receiver<T>,
+    connected: bool
+}
+
+impl <T> ChannelRouter<T> {
+    pub fn pair() -> (Self, Self) {
+        let (sender_a, receiver_b) = mpsc::channel();
+        let (sender_b, receiver_a) = mpsc::channel();
+        (
+            ChannelRouter { sender: sender_a, receiver: receiver_a, connected: true },
+            ChannelRouter { sender: sender_b, receiver: receiver_b, connected: true }
+        )
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn send_outbound(&mut self, message: T) {
+        if self.sender.send(message).is_err() {
+            self.connected = false
+        }
+    }
+
+    pub fn consume_all_inbound(&mut self) -> Vec<T> {
+        let mut messages = Vec::new();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.connected = false;
+                    break
+                }
+            }
+        }
+        messages
+    }
+
+    // Blocks until a message arrives or the timeout passes, then takes everything queued
+    pub fn wait_all_inbound(&mut self, timeout: Duration) -> Vec<T> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(first) => {
+                let mut messages = vec![first];
+                messages.append(&mut self.consume_all_inbound());
+                messages
+            },
+            Err(RecvTimeoutError::Timeout) => Vec::new(),
+            Err(RecvTimeoutError::Disconnected) => {
+                self.connected = false;
+                Vec::new()
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+/*
+Save state file format (plain text, one entry per line, numbers in hex):
+CHIP8STATE 1                - header and format version
+pc <addr>
+i <addr>
+sp <n>
+stack <16 words>            - the whole stack, including unused entries
+v <16 bytes>                - V0-VF
+timers <delay> <sound>
+wait <x>                    - only while LD Vx, K waits for a key
+rng seeded <4 words>        - state of the RND byte generator, none for the
+rng scripted <next> <bytes>   OS seeded one, which can't be restored
+memory <bytes>              - all of RAM as one run of hex digits
+display <bytes>             - 8 pixels a byte, the leftmost in the top bit
+Keys aren't saved, they stay as they are when a state is loaded.
+*/
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write, Error, ErrorKind};
+
+use constants::{N, RAM_BYTES};
+use rng::RngState;
+
+const STATE_HEADER: &str = "CHIP8STATE";
+const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState {
+    pub pc: u16,
+    pub i: u16,
+    pub sp: u8,
+    pub stack: [u16; 0x10],
+    pub registers: [u8; 0x10],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    // Register LD Vx, K is waiting to store a key in
+    pub key_wait: Option<usize>,
+    pub rng: Option<RngState>,
+    pub memory: Vec<u8>,
+    pub pixels: Vec<bool>
+}
+
+impl SaveState {
+    pub fn load(path: &str) -> io::Result<Self> {
+        SaveState::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{} {}", STATE_HEADER, STATE_VERSION)?;
+        writeln!(writer, "pc {:x}", self.pc)?;
+        writeln!(writer, "i {:x}", self.i)?;
+        writeln!(writer, "sp {:x}", self.sp)?;
+        writeln!(writer, "stack {}", join_hex(self.stack.iter().map(|word| format!("{:x}", word))))?;
+        writeln!(writer, "v {}", join_hex(self.registers.iter().map(|byte| format!("{:x}", byte))))?;
+        writeln!(writer, "timers {:x} {:x}", self.delay_timer, self.sound_timer)?;
+        if let Some(x) = self.key_wait {
+            writeln!(writer, "wait {:x}", x)?
+        }
+        match self.rng {
+            Some(RngState::Seeded(ref state)) =>
+                writeln!(writer, "rng seeded {}", join_hex(state.iter().map(|word| format!("{:x}", word))))?,
+            Some(RngState::Scripted(ref bytes, next)) =>
+                writeln!(writer, "rng scripted {:x} {}", next, hex_bytes(bytes))?,
+            None => {}
+        }
+        writeln!(writer, "memory {}", hex_bytes(&self.memory))?;
+        let display = self.pixels.chunks(8)
+            .map(|pixels| pixels.iter().fold(0, |byte, &is_lit| byte << 1 | is_lit as u8))
+            .collect::<Vec<u8>>();
+        writeln!(writer, "display {}", hex_bytes(&display))
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines.next().unwrap_or_else(|| Err(invalid_state("the file is empty")))?;
+        match header.split_whitespace().collect::<Vec<&str>>()[..] {
+            [STATE_HEADER, version] if version == STATE_VERSION.to_string() => {},
+            [STATE_HEADER, version] => return Err(invalid_state(&format!("unsupported version {}", version))),
+            _ => return Err(invalid_state(&format!("expected '{}' line, found: {}", STATE_HEADER, header)))
+        }
+
+        let mut fields = HashMap::new();
+        for line in lines {
+            let line = line?;
+            let mut words = line.split_whitespace().map(str::to_string);
+            if let Some(name) = words.next() {
+                fields.insert(name, words.collect::<Vec<String>>());
+            }
+        }
+        let field = |name: &str, len: usize| match fields.get(name) {
+            Some(words) if words.len() == len => Ok(words.clone()),
+            _ => Err(invalid_state(&format!("expected '{}' with {} values", name, len)))
+        };
+
+        let registers = field("v", 0x10)?.iter().map(|byte| parse_hex(byte).map(|byte| byte as u8)).collect::<io::Result<Vec<u8>>>()?;
+        let stack = field("stack", 0x10)?.iter().map(|word| parse_hex(word).map(|word| word as u16)).collect::<io::Result<Vec<u16>>>()?;
+        let timers = field("timers", 2)?;
+        let memory = parse_bytes(&field("memory", 1)?[0], RAM_BYTES)?;
+        let display = parse_bytes(&field("display", 1)?[0], N / 8)?;
+
+        let mut state = SaveState {
+            pc: parse_hex(&field("pc", 1)?[0])? as u16,
+            i: parse_hex(&field("i", 1)?[0])? as u16,
+            sp: parse_hex(&field("sp", 1)?[0])? as u8 & 0xF,
+            stack: [0; 0x10],
+            registers: [0; 0x10],
+            delay_timer: parse_hex(&timers[0])? as u8,
+            sound_timer: parse_hex(&timers[1])? as u8,
+            key_wait: match fields.get("wait") {
+                Some(_) => Some(parse_hex(&field("wait", 1)?[0])? as usize & 0xF),
+                None => None
+            },
+            rng: match fields.get("rng").map(|words| words.iter().map(String::as_str).collect::<Vec<&str>>()) {
+                Some(ref words) if words.len() == 5 && words[0] == "seeded" => {
+                    let mut state = [0; 4];
+                    for (word, text) in state.iter_mut().zip(&words[1..]) { *word = parse_hex(text)? }
+                    Some(RngState::Seeded(state))
+                },
+                Some(ref words) if words.len() == 3 && words[0] == "scripted" => {
+                    let bytes = parse_bytes(words[2], words[2].len() / 2)?;
+                    Some(RngState::Scripted(bytes, parse_hex(words[1])? as usize))
+                },
+                Some(_) => return Err(invalid_state("bad rng state")),
+                None => None
+            },
+            memory,
+            pixels: display.iter().flat_map(|byte| (0..8).rev().map(move |bit| byte >> bit & 1 == 1)).collect()
+        };
+        state.stack.copy_from_slice(&stack);
+        state.registers.copy_from_slice(&registers);
+        Ok(state)
+    }
+}
+
+fn join_hex<I: Iterator<Item = String>>(values: I) -> String {
+    values.collect::<Vec<String>>().join(" ")
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> io::Result<u32> {
+    u32::from_str_radix(text, 16).map_err(|_| invalid_state(&format!("bad number: {}", text)))
+}
+
+fn parse_bytes(text: &str, len: usize) -> io::Result<Vec<u8>> {
+    if text.len() != 2 * len || !text.is_ascii() {
+        return Err(invalid_state(&format!("expected {} bytes of hex", len)))
+    }
+    (0..len).map(|i| parse_hex(&text[2 * i..2 * i + 2]).map(|byte| byte as u8)).collect()
+}
+
+fn invalid_state(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid save state: {}", message))
+}
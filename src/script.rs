@@ -0,0 +1,277 @@
+/*
+Rhai scripts that drive the emulator, e.g. bots that play a game and
+regression scenarios. The script's top level runs once as the game starts
+and registers hooks, each a function pointer: Fn("name") or a closure.
+on_frame(f)              - f() after every 60Hz video frame, i.e. every
+                           cycles per second / 60 cycles at the pacing's speed
+on_instruction(addr, f)  - f() before the instruction at addr runs
+on_write(addr, f)        - f(addr, value) after a write to addr, by the
+                           program or by poke
+
+The top level and hooks can call:
+reg(x), set_reg(x, value)          - V0-VF
+reg_i(), set_reg_i(value), pc(), set_pc(addr), sp()
+dt(), set_dt(value), st(), set_st(value)
+peek(addr), poke(addr, value)
+pixel(x, y)                        - whether the pixel is lit
+press(key), release(key)           - keys 0-F
+frame()                            - video frames run so far
+cycle()                            - cycles, i.e. instructions, run so far
+screenshot(path)                   - the screen as a plain PBM image
+save_state(path), load_state(path) - see save_state.rs
+exit()                             - ends the game
+Changes take effect when the hook returns, reading them back sees them
+straight away. An error in the script ends the game.
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::rc::Rc;
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, FuncArgs, Scope, AST};
+
+use constants::{W, H, KEYS, RAM_BYTES};
+use emulator::Chip8Emulator;
+use save_state::SaveState;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Unreadable(io::Error),
+    Syntax(String),
+    Runtime(String)
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Unreadable(e) => write!(f, "can't read the script: {}", e),
+            ScriptError::Syntax(e) => write!(f, "syntax error: {}", e),
+            ScriptError::Runtime(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl error::Error for ScriptError {}
+
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<FnPtr>,
+    instructions: HashMap<u16, Vec<FnPtr>>,
+    writes: HashMap<u16, Vec<FnPtr>>
+}
+
+// What the script sees and changes while it runs
+struct Machine {
+    state: SaveState,
+    frame: u64,
+    cycle: u64,
+    // Addresses poked, the only memory written back
+    pokes: Vec<u16>,
+    is_loaded: bool,
+    keys: Vec<(usize, bool)>,
+    is_exiting: bool
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    hooks: Rc<RefCell<Hooks>>,
+    machine: Rc<RefCell<Option<Machine>>>
+}
+
+impl Script {
+    pub fn load(path: &str) -> Result<Self, ScriptError> {
+        Script::compile(&fs::read_to_string(path).map_err(ScriptError::Unreadable)?)
+    }
+
+    pub fn compile(source: &str) -> Result<Self, ScriptError> {
+        let hooks = Rc::new(RefCell::new(Hooks::default()));
+        let machine = Rc::new(RefCell::new(None));
+        let mut engine = Engine::new();
+        register_hooks(&mut engine, &hooks);
+        register_machine(&mut engine, &machine);
+
+        let ast = engine.compile(source).map_err(|e| ScriptError::Syntax(e.to_string()))?;
+        Ok(Script { engine, ast, hooks, machine })
+    }
+
+    // Runs the top level, which registers the hooks
+    pub fn start(&mut self, emulator: &mut Chip8Emulator) -> Result<(), ScriptError> {
+        self.begin(emulator);
+        let result = self.engine.run_ast_with_scope(&mut Scope::new(), &self.ast);
+        self.end(emulator, result)
+    }
+
+    pub fn before_instruction(&mut self, emulator: &mut Chip8Emulator) -> Result<(), ScriptError> {
+        let pc = emulator.get_chip8().get_pc();
+        let hooks = self.hooks.borrow().instructions.get(&pc).cloned().unwrap_or_default();
+        self.run_hooks(emulator, &hooks, ())
+    }
+
+    pub fn after_writes(&mut self, emulator: &mut Chip8Emulator, writes: &[(u16, u8)]) -> Result<(), ScriptError> {
+        for &(addr, value) in writes {
+            let hooks = self.hooks.borrow().writes.get(&addr).cloned().unwrap_or_default();
+            self.run_hooks(emulator, &hooks, (addr as i64, value as i64))?
+        }
+        Ok(())
+    }
+
+    pub fn after_frame(&mut self, emulator: &mut Chip8Emulator) -> Result<(), ScriptError> {
+        let hooks = self.hooks.borrow().frame.clone();
+        self.run_hooks(emulator, &hooks, ())
+    }
+
+    pub fn is_watching_writes(&self) -> bool {
+        !self.hooks.borrow().writes.is_empty()
+    }
+
+    fn run_hooks<A: FuncArgs + Clone>(&mut self, emulator: &mut Chip8Emulator, hooks: &[FnPtr], args: A)
+        -> Result<(), ScriptError> {
+        if hooks.is_empty() { return Ok(()) }
+
+        self.begin(emulator);
+        let result = hooks.iter()
+            .try_for_each(|hook| hook.call::<Dynamic>(&self.engine, &self.ast, args.clone()).map(|_| ()));
+        self.end(emulator, result)
+    }
+
+    fn begin(&mut self, emulator: &mut Chip8Emulator) {
+        *self.machine.borrow_mut() = Some(Machine {
+            state: emulator.get_chip8().save_state(),
+            frame: emulator.get_video_frame(),
+            cycle: emulator.get_frame(),
+            pokes: Vec::new(),
+            is_loaded: false,
+            keys: Vec::new(),
+            is_exiting: false
+        })
+    }
+
+    // Applies what changed, even when the script failed part way
+    fn end(&mut self, emulator: &mut Chip8Emulator, result: ScriptResult<()>) -> Result<(), ScriptError> {
+        let machine = self.machine.borrow_mut().take().expect("the script should have begun");
+        let state = &machine.state;
+        let chip8 = emulator.get_chip8_mut();
+        if machine.is_loaded {
+            chip8.restore_state(state)
+        } else {
+            for x in 0..0x10 {
+                if chip8.get_registers()[x] != state.registers[x] { chip8.set_register(x, state.registers[x]) }
+            }
+            if chip8.get_i() != state.i { chip8.set_i(state.i) }
+            if chip8.get_pc() != state.pc { chip8.set_pc(state.pc) }
+            if chip8.get_delay_timer() != state.delay_timer { chip8.set_delay_timer(state.delay_timer) }
+            if chip8.get_sound_timer() != state.sound_timer { chip8.set_sound_timer(state.sound_timer) }
+            for &addr in &machine.pokes {
+                let value = state.memory[addr as usize];
+                if chip8.get_memory()[addr as usize] != value { chip8.write_byte(addr, value) }
+            }
+        }
+
+        for &(key_i, is_down) in &machine.keys { emulator.press_key(key_i, is_down) }
+        if machine.is_exiting { emulator.exit_game() }
+        result.map_err(|e| ScriptError::Runtime(e.to_string()))
+    }
+}
+
+fn register_hooks(engine: &mut Engine, hooks: &Rc<RefCell<Hooks>>) {
+    let frame_hooks = hooks.clone();
+    engine.register_fn("on_frame", move |hook: FnPtr| frame_hooks.borrow_mut().frame.push(hook));
+
+    let instruction_hooks = hooks.clone();
+    engine.register_fn("on_instruction", move |addr: i64, hook: FnPtr| -> ScriptResult<()> {
+        instruction_hooks.borrow_mut().instructions.entry(to_addr(addr)?).or_default().push(hook);
+        Ok(())
+    });
+
+    let write_hooks = hooks.clone();
+    engine.register_fn("on_write", move |addr: i64, hook: FnPtr| -> ScriptResult<()> {
+        write_hooks.borrow_mut().writes.entry(to_addr(addr)?).or_default().push(hook);
+        Ok(())
+    });
+}
+
+// Functions reading and changing the machine, each given its own handle on it
+fn register_machine(engine: &mut Engine, machine: &Rc<RefCell<Option<Machine>>>) {
+    macro_rules! register {
+        ($name:expr, |$machine:ident $(, $arg:ident: $t:ty)*| $body:expr) => {{
+            let shared = machine.clone();
+            engine.register_fn($name, move |$($arg: $t),*| -> ScriptResult<_> {
+                let mut borrowed = shared.borrow_mut();
+                let $machine = borrowed.as_mut().expect("the script should have begun");
+                $body
+            });
+        }}
+    }
+
+    register!("reg", |m, x: i64| Ok(m.state.registers[to_register(x)?] as i64));
+    register!("set_reg", |m, x: i64, value: i64| { m.state.registers[to_register(x)?] = to_byte(value)?; Ok(()) });
+    register!("reg_i", |m| Ok(m.state.i as i64));
+    register!("set_reg_i", |m, value: i64| { m.state.i = to_addr(value)?; Ok(()) });
+    register!("pc", |m| Ok(m.state.pc as i64));
+    register!("set_pc", |m, addr: i64| { m.state.pc = to_addr(addr)?; Ok(()) });
+    register!("sp", |m| Ok(m.state.sp as i64));
+    register!("dt", |m| Ok(m.state.delay_timer as i64));
+    register!("set_dt", |m, value: i64| { m.state.delay_timer = to_byte(value)?; Ok(()) });
+    register!("st", |m| Ok(m.state.sound_timer as i64));
+    register!("set_st", |m, value: i64| { m.state.sound_timer = to_byte(value)?; Ok(()) });
+    register!("peek", |m, addr: i64| Ok(m.state.memory[to_addr(addr)? as usize] as i64));
+    register!("poke", |m, addr: i64, value: i64| {
+        let (addr, value) = (to_addr(addr)?, to_byte(value)?);
+        m.state.memory[addr as usize] = value;
+        m.pokes.push(addr);
+        Ok(())
+    });
+    register!("pixel", |m, x: i64, y: i64| {
+        if x < 0 || x >= W as i64 || y < 0 || y >= H as i64 { return Err(format!("({}, {}) is off the screen", x, y).into()) }
+        Ok(m.state.pixels[y as usize * W + x as usize])
+    });
+    register!("press", |m, key: i64| { m.keys.push((to_key(key)?, true)); Ok(()) });
+    register!("release", |m, key: i64| { m.keys.push((to_key(key)?, false)); Ok(()) });
+    register!("frame", |m| Ok(m.frame as i64));
+    register!("cycle", |m| Ok(m.cycle as i64));
+    register!("screenshot", |m, path: &str| save_screenshot(path, &m.state.pixels).map_err(|e| io_error(path, e)));
+    register!("save_state", |m, path: &str| m.state.save(path).map_err(|e| io_error(path, e)));
+    register!("load_state", |m, path: &str| {
+        m.state = SaveState::load(path).map_err(|e| io_error(path, e))?;
+        m.is_loaded = true;
+        Ok(())
+    });
+    register!("exit", |m| { m.is_exiting = true; Ok(()) });
+}
+
+// Plain PBM, one row of 0s and 1s a line with 1 lit
+pub fn save_screenshot(path: &str, pixels: &[bool]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "P1\n{} {}", W, H)?;
+    for row in pixels.chunks(W) {
+        writeln!(writer, "{}", row.iter().map(|&is_lit| if is_lit { '1' } else { '0' }).collect::<String>())?
+    }
+    writer.flush()
+}
+
+fn to_register(x: i64) -> ScriptResult<usize> {
+    if (0..0x10).contains(&x) { Ok(x as usize) } else { Err(format!("V{} isn't a register", x).into()) }
+}
+
+fn to_byte(value: i64) -> ScriptResult<u8> {
+    if (0..=0xFF).contains(&value) { Ok(value as u8) } else { Err(format!("{} doesn't fit in a byte", value).into()) }
+}
+
+fn to_addr(addr: i64) -> ScriptResult<u16> {
+    if (0..RAM_BYTES as i64).contains(&addr) { Ok(addr as u16) } else { Err(format!("{:#x} is outside memory", addr).into()) }
+}
+
+fn to_key(key: i64) -> ScriptResult<usize> {
+    if (0..KEYS as i64).contains(&key) { Ok(key as usize) } else { Err(format!("{} isn't a key", key).into()) }
+}
+
+fn io_error(path: &str, e: io::Error) -> Box<EvalAltResult> {
+    format!("'{}': {}", path, e).into()
+}
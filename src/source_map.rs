@@ -0,0 +1,76 @@
+/*
+Source map file (plain text, one instruction per line), as written by an
+assembler alongside the ROM:
+<addr> <line> <file>
+addr - address of the instruction in memory, in hex, e.g. 200
+line - line of the instruction in the assembler source, counting from 1
+file - path of the assembler source, which may contain spaces. Relative
+       paths are relative to the source map's directory.
+Blank lines and lines starting with '#' are ignored.
+*/
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub addr: u16,
+    pub line: u32,
+    pub path: PathBuf
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMap {
+    lines: Vec<SourceLine>
+}
+
+impl SourceMap {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        SourceMap::read_from(BufReader::new(File::open(path)?), base_dir)
+    }
+
+    pub fn read_from<R: BufRead>(reader: R, base_dir: &Path) -> io::Result<Self> {
+        let mut lines = Vec::new();
+        for text in reader.lines() {
+            let text = text?;
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') { continue }
+
+            let mut fields = text.splitn(3, ' ');
+            let addr = fields.next().and_then(|addr| u16::from_str_radix(addr, 16).ok());
+            let line = fields.next().and_then(|line| line.parse::<u32>().ok()).filter(|&line| line > 0);
+            let file = fields.next().map(str::trim).filter(|file| !file.is_empty());
+            match (addr, line, file) {
+                (Some(addr), Some(line), Some(file)) => lines.push(SourceLine { addr, line, path: base_dir.join(file) }),
+                _ => return Err(Error::new(ErrorKind::InvalidData,
+                    format!("Invalid source map: bad entry: {}", text)))
+            }
+        }
+        Ok(SourceMap { lines })
+    }
+
+    pub fn lines(&self) -> &[SourceLine] {
+        &self.lines
+    }
+
+    // The source line of the instruction at an address
+    pub fn location(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.iter().find(|source_line| source_line.addr == addr)
+    }
+
+    // The first instruction on the line, or on the next line with one, e.g. for
+    // a breakpoint set on a comment
+    pub fn instruction_at(&self, path: &Path, line: u32) -> Option<&SourceLine> {
+        self.lines.iter()
+            .filter(|source_line| is_same_file(&source_line.path, path) && source_line.line >= line)
+            .min_by_key(|source_line| (source_line.line, source_line.addr))
+    }
+}
+
+// Debugger clients send absolute paths, so paths that differ only in how
+// they're written match once resolved. Files with the same name elsewhere don't.
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
@@ -26,3 +26,2430 @@ mod assembly_test {
         assert_eq!(Opcode(UNDEFINED, NONE), OpcodeDisassembler::disassemble(0x800a));
     }
 }
+
+mod movie_test {
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+    use chip8::Quirks;
+    use command::{Command, DisplayCommand::SendMessage, KeyCommand::{self, KeyDownUp}};
+    use emulator::Chip8Emulator;
+    use io::HeadlessIO;
+    use library::Quirk;
+    use movie::{Movie, MoviePlayer, rom_hash};
+    use rng::SeededRandom;
+
+    fn sample_movie() -> Movie {
+        let mut movie = Movie::new(&rom_hash(&[0x00, 0xe0]), 42);
+        movie.quirks = vec![Quirk::ShiftVy, Quirk::Clip];
+        movie.record(3, KeyDownUp(0x1, true));
+        movie.record(3, KeyDownUp(0xc, true));
+        movie.record(10, KeyDownUp(0x1, false));
+        movie
+    }
+
+    #[test]
+    fn movie_round_trip() {
+        let movie = sample_movie();
+        let mut bytes: Vec<u8> = Vec::new();
+        movie.write_to(&mut bytes).unwrap();
+
+        assert_eq!(movie, Movie::read_from(Cursor::new(bytes)).unwrap());
+    }
+
+    #[test]
+    fn movie_without_quirks_round_trip() {
+        let movie = Movie::new("abc", 7);
+        let mut bytes: Vec<u8> = Vec::new();
+        movie.write_to(&mut bytes).unwrap();
+
+        assert_eq!("CHIP8MOVIE 2\nrom abc\nseed 7\nquirks\n", String::from_utf8(bytes.clone()).unwrap());
+        assert_eq!(movie, Movie::read_from(Cursor::new(bytes)).unwrap());
+    }
+
+    #[test]
+    fn movie_rejects_bad_input() {
+        let header = "CHIP8MOVIE 1\nrom abc\nseed 7\n";
+        assert!(Movie::read_from(Cursor::new("")).is_err());
+        assert!(Movie::read_from(Cursor::new("CHIP8MOVIE 3\nrom abc\nseed 7\nquirks\n")).is_err());
+        assert!(Movie::read_from(Cursor::new("CHIP8MOVIE 1\nseed 7\n")).is_err());
+        assert!(Movie::read_from(Cursor::new("CHIP8MOVIE 2\nrom abc\nseed 7\n5 a up\n")).is_err());
+        assert!(Movie::read_from(Cursor::new("CHIP8MOVIE 2\nrom abc\nseed 7\nquirks fast\n")).is_err());
+        assert!(Movie::read_from(Cursor::new(format!("{}5 10 down\n", header))).is_err());
+        assert!(Movie::read_from(Cursor::new(format!("{}5 a sideways\n", header))).is_err());
+        assert_eq!(1, Movie::read_from(Cursor::new(format!("{}5 a up\n\n", header)))
+            .unwrap().events.len());
+    }
+
+    #[test]
+    fn version_1_movies_have_no_quirks() {
+        let movie = Movie::read_from(Cursor::new("CHIP8MOVIE 1\nrom abc\nseed 7\n5 a up\n")).unwrap();
+        assert!(movie.quirks.is_empty());
+        assert_eq!(1, movie.events.len());
+    }
+
+    #[test]
+    fn replay_warns_about_different_quirks() {
+        let messages = |movie_quirks: Vec<Quirk>, quirks: Quirks| {
+            let mut emulator = Chip8Emulator::new(Box::new(HeadlessIO::new()), Box::new(SeededRandom::new(42)));
+            let shown = Rc::new(RefCell::new(Vec::new()));
+            let log = Rc::clone(&shown);
+            emulator.set_tracer(Box::new(move |_, command| if let Command::Display(SendMessage(text)) = command {
+                log.borrow_mut().push(text.clone())
+            }));
+
+            let mut movie = Movie::new(&rom_hash(&[0x12, 0x00]), 42);
+            movie.quirks = movie_quirks;
+            emulator.replay_movie(movie);
+            emulator.set_quirks(quirks);
+            emulator.load_game(&[0x12, 0x00]).unwrap();
+            shown.take()
+        };
+
+        let shift_vy = Quirks { shift_vy: true, ..Quirks::default() };
+        assert_eq!(vec!["Replaying movie"], messages(vec![Quirk::ShiftVy], shift_vy));
+        assert_eq!(vec!["Replaying movie", "Movie was recorded with different quirks"],
+            messages(Vec::new(), shift_vy));
+        assert_eq!(vec!["Replaying movie", "Movie was recorded with different quirks"],
+            messages(vec![Quirk::ShiftVy], Quirks::default()));
+    }
+
+    #[test]
+    fn movie_player_events_by_frame() {
+        let mut player = MoviePlayer::new(sample_movie());
+        assert_eq!(Vec::<KeyCommand>::new(), player.events_at(2));
+        assert_eq!(vec![KeyDownUp(0x1, true), KeyDownUp(0xc, true)], player.events_at(3));
+        assert!(player.events_at(9).is_empty());
+        assert_eq!(vec![KeyDownUp(0x1, false)], player.events_at(11));
+    }
+
+    #[test]
+    fn rom_hash_is_sha1() {
+        assert_eq!("da39a3ee5e6b4b0d3255bfef95601890afd80709", rom_hash(&[]));
+    }
+}
+
+mod rng_test {
+    use rng::{RandomSource, SeededRandom, ScriptedRandom, OsRandom, from_seed};
+
+    fn take(rng: &mut dyn RandomSource, n: usize) -> Vec<u8> {
+        (0..n).map(|_| rng.rng_byte()).collect()
+    }
+
+    #[test]
+    fn seeded_rng_is_reproducible() {
+        let bytes = take(&mut SeededRandom::new(1234), 64);
+        assert_eq!(bytes, take(&mut SeededRandom::new(1234), 64));
+        assert_eq!(bytes, take(&mut *from_seed(Some(1234)), 64));
+        assert_ne!(bytes, take(&mut SeededRandom::new(1235), 64));
+        assert!(take(&mut SeededRandom::new(0), 64).iter().any(|b| *b != 0));
+    }
+
+    #[test]
+    fn rng_state_restores_sequence() {
+        let mut seeded = SeededRandom::new(99);
+        take(&mut seeded, 10);
+        let mut restored = seeded.state().unwrap().restore();
+        assert_eq!(take(&mut seeded, 32), take(&mut *restored, 32));
+
+        let mut scripted = ScriptedRandom::new(&[1, 2, 3]);
+        scripted.rng_byte();
+        let mut restored = scripted.state().unwrap().restore();
+        assert_eq!(vec![2, 3, 1, 2], take(&mut *restored, 4));
+
+        assert_eq!(None, OsRandom.state());
+    }
+
+    #[test]
+    fn scripted_rng_repeats() {
+        assert_eq!(vec![7, 9, 7, 9, 7], take(&mut ScriptedRandom::new(&[7, 9]), 5));
+    }
+}
+
+mod options_test {
+    use chip8::Engine;
+    use options::Options;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn options_parse() {
+        let options = parse(&["--record", "a.movie"]).unwrap();
+        assert_eq!(Some("a.movie".to_string()), options.record_path);
+        assert_eq!(None, options.replay_path);
+
+        assert_eq!(Some("b.movie".to_string()),
+            parse(&["--replay", "b.movie"]).unwrap().replay_path);
+        assert_eq!(Some(17), parse(&["--seed", "17"]).unwrap().rng_seed);
+        assert!(parse(&["--seed", "x"]).is_err());
+        assert!(parse(&["--replay"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+
+        assert_eq!(Engine::Interpreter, parse(&[]).unwrap().engine);
+        assert_eq!(Engine::Translated, parse(&["--engine", "translated"]).unwrap().engine);
+        assert!(parse(&["--engine", "x"]).is_err());
+
+        let paced = parse(&["--speed", "600", "--fast-forward", "3"]).unwrap();
+        assert_eq!((Some(600), 3), (paced.cycles_per_second, paced.fast_forward_multiplier));
+        assert_eq!(None, parse(&[]).unwrap().cycles_per_second);
+        assert!(parse(&["--speed", "0"]).is_err());
+        assert!(parse(&["--fast-forward", "-1"]).is_err());
+
+        assert_eq!(vec!["./ROMs/".to_string()], parse(&[]).unwrap().rom_dirs);
+        assert_eq!(vec!["a".to_string(), "b".to_string()],
+            parse(&["--rom-dir", "a", "--rom-dir", "b"]).unwrap().rom_dirs);
+        assert!(parse(&["--terminal-menu"]).unwrap().is_terminal_menu);
+        assert_eq!(Some("my.db".to_string()), parse(&["--rom-db", "my.db"]).unwrap().rom_db_path);
+        assert_eq!("h.txt", parse(&["--menu-history", "h.txt"]).unwrap().menu_history_path);
+        assert_eq!(Some("p.txt".to_string()), parse(&["--profile", "p.txt"]).unwrap().profile_path);
+        assert_eq!(Some(1234), parse(&["--gdb", "1234"]).unwrap().gdb_port);
+        assert!(parse(&["--gdb", "70000"]).is_err());
+        assert_eq!(Some("bot.rhai".to_string()), parse(&["--script", "bot.rhai"]).unwrap().script_path);
+        assert_eq!(Some("c.txt".to_string()), parse(&["--cheats", "c.txt"]).unwrap().cheats_path);
+    }
+}
+
+mod bus_test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use bus::EventBus;
+    use command::{Command, CommandEmulator, Topic, AudioCommand::Play, KeyCommand::KeyDownUp};
+    use emulator::Chip8Emulator;
+    use io::HeadlessIO;
+    use rng::SeededRandom;
+    use router::Router;
+
+    fn keys(commands: &[Command]) -> Vec<usize> {
+        commands.iter().filter_map(|c| match c {
+            Command::Key(KeyDownUp(key_i, _)) => Some(*key_i),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn subscribers_get_their_topics_in_order() {
+        let mut bus = EventBus::new();
+        let (a, b, c) = (bus.register("a"), bus.register("b"), bus.register("c"));
+        bus.subscribe(b, Topic::Key);
+        bus.subscribe(b, Topic::Audio);
+        bus.subscribe(c, Topic::Key);
+        // Subscribing twice still delivers each command once
+        bus.subscribe(c, Topic::Key);
+
+        bus.publish(a, Command::Key(KeyDownUp(1, true)));
+        bus.publish(a, Command::Audio(Play));
+        bus.publish(a, Command::Key(KeyDownUp(2, true)));
+        bus.publish(b, Command::Key(KeyDownUp(3, true)));
+
+        let received_by_b = bus.take(b);
+        assert_eq!(3, received_by_b.len());
+        assert_eq!(vec![1, 2], keys(&received_by_b));
+        assert_eq!(vec![1, 2, 3], keys(&bus.take(c)));
+        assert!(bus.take(a).is_empty());
+        assert!(bus.take(c).is_empty());
+    }
+
+    #[test]
+    fn tracer_sees_every_command() {
+        let traced = Rc::new(RefCell::new(Vec::new()));
+        let mut bus = EventBus::new();
+        let a = bus.register("a");
+        let log = Rc::clone(&traced);
+        bus.set_tracer(Box::new(move |name, command| log.borrow_mut().push((name, command.topic()))));
+
+        bus.publish(a, Command::Audio(Play));
+        assert_eq!(vec![("a", Topic::Audio)], *traced.borrow());
+    }
+
+    // Presses a key on its first cycle and keeps the audio commands it sees
+    struct KeyPresser {
+        commands: Router<Command>,
+        pressed: bool,
+        audio: Rc<RefCell<usize>>
+    }
+
+    impl CommandEmulator for KeyPresser {
+        fn get_commands(&mut self) -> &mut Router<Command> {
+            &mut self.commands
+        }
+
+        fn process_inbound_command(&mut self, command: &Command) {
+            if let Command::Audio(_) = command { *self.audio.borrow_mut() += 1 }
+        }
+
+        fn emulate_cycle(&mut self) {
+            if !self.pressed {
+                self.commands.send_outbound(Command::Key(KeyDownUp(0xA, true)));
+                self.pressed = true
+            }
+        }
+    }
+
+    #[test]
+    fn added_components_plug_into_the_bus() {
+        let audio = Rc::new(RefCell::new(0));
+        let mut emulator = Chip8Emulator::new(
+            Box::new(HeadlessIO::new()),
+            Box::new(SeededRandom::new(0)));
+        emulator.add_component("key presser", Box::new(KeyPresser {
+            commands: Router::new(),
+            pressed: false,
+            audio: Rc::clone(&audio)
+        }), &[Topic::Audio]);
+
+        // LD V1, K; LD F, V1; DRW V0, V0, 5; LD V2, 2; LD ST, V2; JP 0x20a
+        emulator.load_game(&[0xF1, 0x0A, 0xF1, 0x29, 0xD0, 0x05, 0x62, 0x02, 0xF2, 0x18, 0x12, 0x0A]).unwrap();
+        (0..10).for_each(|_| emulator.run_frame());
+
+        assert!(emulator.get_pixels().iter().any(|p| *p));
+        // The sound started and stopped
+        assert_eq!(2, *audio.borrow());
+    }
+}
+
+mod remote_io_test {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use command::{Command, DisplayCommand::SendPixels, GameCommand::Exit, KeyCommand::KeyDownUp};
+    use emulator::Chip8Emulator;
+    use io::RemoteIO;
+    use rng::SeededRandom;
+    use router::ChannelRouter;
+
+    #[test]
+    fn channel_router_disconnects() {
+        let (mut a, b) = ChannelRouter::pair();
+        a.send_outbound(1);
+        drop(b);
+        assert!(a.consume_all_inbound().is_empty());
+        assert!(!a.is_connected());
+    }
+
+    #[test]
+    fn emulator_runs_on_its_own_thread() {
+        let (emulator_link, mut front_end) = ChannelRouter::pair();
+        let emulator_thread = thread::spawn(move || {
+            let mut emulator = Chip8Emulator::new(
+                Box::new(RemoteIO::new(emulator_link)),
+                Box::new(SeededRandom::new(0)));
+            // LD V1, K; LD F, V1; DRW V0, V0, 5; JP 0x206
+            emulator.load_game(&[0xF1, 0x0A, 0xF1, 0x29, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+            while emulator.is_running() { emulator.run_frame() }
+        });
+
+        // Presses are repeated since the first may arrive before LD V1, K waits for one
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut is_drawn = false;
+        while !is_drawn && Instant::now() < deadline {
+            front_end.send_outbound(Command::Key(KeyDownUp(0xA, true)));
+            is_drawn = front_end.wait_all_inbound(Duration::from_millis(100)).iter().any(|c| match c {
+                Command::Display(SendPixels(pixels)) => pixels.iter().any(|p| *p),
+                _ => false
+            })
+        }
+        assert!(is_drawn, "Key press should reach the emulator thread and draw");
+
+        front_end.send_outbound(Command::GameState(Exit));
+        emulator_thread.join().unwrap();
+    }
+}
+
+mod pacing_test {
+    use std::time::{Duration, Instant};
+
+    use std::fs;
+
+    use chip8::Engine;
+    use command::{Command, GameCommand::{TogglePause, FrameAdvance}};
+    use emulator::Chip8Emulator;
+    use io::{HeadlessIO, RemoteIO};
+    use pacing::Pacer;
+    use rng::SeededRandom;
+    use router::ChannelRouter;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn cycles_follow_a_fixed_schedule() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(1000);
+        pacer.restart(start);
+
+        assert_eq!(Some(ms(1)), pacer.cycle_delay(start));
+
+        // Cycle 10 is due at 10ms however late earlier cycles finished
+        (2..10).for_each(|_| { pacer.cycle_delay(start + ms(1)); });
+        assert_eq!(Some(ms(4)), pacer.cycle_delay(start + ms(6)));
+
+        // Waits under a millisecond build up instead of sleeping
+        assert_eq!(None, pacer.cycle_delay(start + ms(10) + Duration::from_micros(500)));
+    }
+
+    #[test]
+    fn late_schedule_restarts() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(1000);
+        pacer.restart(start);
+
+        assert_eq!(None, pacer.cycle_delay(start + ms(50)));
+        assert_eq!(None, pacer.cycle_delay(start + ms(500)));
+        assert_eq!(Some(ms(1)), pacer.cycle_delay(start + ms(500)));
+    }
+
+    #[test]
+    fn fast_forward_multiplies_the_rate() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new(1000);
+        pacer.set_multiplier(5, start);
+        (0..4).for_each(|_| { pacer.cycle_delay(start); });
+        assert_eq!(Some(ms(1)), pacer.cycle_delay(start));
+    }
+
+    #[test]
+    fn pause_and_frame_advance() {
+        let (emulator_link, mut front_end) = ChannelRouter::pair();
+        let mut emulator = Chip8Emulator::new(
+            Box::new(RemoteIO::new(emulator_link)),
+            Box::new(SeededRandom::new(0)));
+        emulator.set_pacing(60_000, 2);
+        emulator.load_game(&[0x12, 0x00]).unwrap();
+
+        // A paced frame runs a video frame, 1000 cycles at 60000 cycles per second.
+        // The pause is read during the first one, which still finishes.
+        front_end.send_outbound(Command::GameState(TogglePause));
+        (0..5).for_each(|_| emulator.run_paced_frame());
+        assert!(emulator.is_paused());
+        assert_eq!(1000, emulator.get_frame());
+
+        front_end.send_outbound(Command::GameState(FrameAdvance));
+        (0..5).for_each(|_| emulator.run_paced_frame());
+        assert_eq!(2000, emulator.get_frame());
+
+        front_end.send_outbound(Command::GameState(TogglePause));
+        (0..10).for_each(|_| emulator.run_paced_frame());
+        assert!(!emulator.is_paused());
+        assert_eq!(11000, emulator.get_frame());
+    }
+
+    #[test]
+    fn batched_cycles_match_single_frames() {
+        let rom_bytes = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/ROMs/BRIX")).unwrap();
+        for &engine in &[Engine::Interpreter, Engine::Translated] {
+            let new_emulator = || {
+                let mut emulator = Chip8Emulator::new(Box::new(HeadlessIO::new()), Box::new(SeededRandom::new(7)));
+                emulator.set_engine(engine);
+                emulator.load_game(&rom_bytes).unwrap();
+                emulator
+            };
+            let (mut single, mut batched) = (new_emulator(), new_emulator());
+            (0..3000).for_each(|_| single.run_frame());
+            (0..30).for_each(|_| batched.run_cycles(100));
+
+            assert_eq!(single.get_frame(), batched.get_frame());
+            assert_eq!(single.get_chip8().save_state(), batched.get_chip8().save_state());
+        }
+    }
+}
+
+mod osd_test {
+    use std::time::{Duration, Instant};
+
+    use command::{Command, DisplayCommand::SendStatus, GameCommand::TogglePause};
+    use emulator::Chip8Emulator;
+    use io::RemoteIO;
+    use osd::{Osd, Status, MESSAGE_TIME, text_pixels, text_width};
+    use pacing::RateMeter;
+    use rng::SeededRandom;
+    use router::ChannelRouter;
+
+    #[test]
+    fn text_uses_the_built_in_font() {
+        assert_eq!(vec![(1, 0), (0, 1), (1, 1), (1, 2), (1, 3), (0, 4), (1, 4), (2, 4)], text_pixels("1"));
+        assert_eq!(text_pixels("OK"), text_pixels("ok"));
+        assert!(text_pixels(" 1").iter().all(|&(x, _)| x >= 4));
+        assert_eq!(7, text_width("AB"));
+    }
+
+    #[test]
+    fn messages_fade_and_expire() {
+        let now = Instant::now();
+        let mut osd = Osd::new();
+        assert!(!osd.needs_redraw());
+
+        osd.show_message("Saved", now);
+        assert!(osd.needs_redraw());
+        assert_eq!(vec![("SAVED".to_string(), 0xFF)], osd.lines(now));
+
+        let fading = osd.lines(now + MESSAGE_TIME - Duration::from_millis(500));
+        assert!(fading[0].1 > 0 && fading[0].1 < 0xFF);
+
+        // One last redraw clears the expired message
+        assert!(osd.needs_redraw());
+        assert!(osd.lines(now + MESSAGE_TIME).is_empty());
+        assert!(!osd.needs_redraw());
+    }
+
+    #[test]
+    fn status_line_toggles_but_pause_always_shows() {
+        let now = Instant::now();
+        let mut osd = Osd::new();
+        osd.set_status(Status { cycles_per_second: 1200, is_paused: false });
+        assert!(osd.lines(now).is_empty());
+
+        osd.toggle_status();
+        assert_eq!("FPS 0 CPS 1200", osd.lines(now)[0].0);
+
+        osd.set_status(Status { cycles_per_second: 0, is_paused: true });
+        assert_eq!("FPS 0 CPS 0 PAUSED", osd.lines(now)[0].0);
+
+        osd.toggle_status();
+        assert!(osd.needs_redraw());
+        assert_eq!("PAUSED", osd.lines(now)[0].0);
+    }
+
+    #[test]
+    fn rate_meter_counts_per_second() {
+        let start = Instant::now();
+        let mut meter = RateMeter::new(start);
+        (0..59).for_each(|i| assert_eq!(None, meter.tick(start + Duration::from_millis(i * 16))));
+        assert_eq!(Some(40), meter.tick(start + Duration::from_millis(1500)));
+        assert_eq!(None, meter.tick(start + Duration::from_millis(1600)));
+    }
+
+    #[test]
+    fn pausing_sends_status() {
+        let (emulator_link, mut front_end) = ChannelRouter::pair();
+        let mut emulator = Chip8Emulator::new(
+            Box::new(RemoteIO::new(emulator_link)),
+            Box::new(SeededRandom::new(0)));
+        emulator.load_game(&[0x12, 0x00]).unwrap();
+
+        front_end.send_outbound(Command::GameState(TogglePause));
+        (0..3).for_each(|_| emulator.run_frame());
+        let statuses = front_end.consume_all_inbound().into_iter()
+            .filter_map(|c| match c {
+                Command::Display(SendStatus(status)) => Some(status),
+                _ => None
+            })
+            .collect::<Vec<Status>>();
+        assert_eq!(vec![Status { cycles_per_second: 0, is_paused: true }], statuses);
+    }
+}
+
+mod rom_browser_test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use constants::{RAM_BYTES, ROM_ADDR, BLACK, WHITE};
+    use rom_browser::{RomBrowser, BrowserInput::*, BROWSER_ROWS, run_preview};
+    use rom_files::{RomEntry, RomSource};
+    use library::Library;
+
+    fn numbered_roms(count: usize) -> RomBrowser {
+        RomBrowser::new((0..count)
+            .map(|i| RomEntry { name: format!("ROM{}", i), source: RomSource::File(PathBuf::from(format!("ROM{}", i))) })
+            .collect())
+    }
+
+    #[test]
+    fn selection_stays_in_view() {
+        let mut browser = numbered_roms(BROWSER_ROWS + 10);
+        browser.move_selection(Up);
+        assert_eq!(("ROM0".to_string(), true), browser.visible_rows()[0]);
+
+        (0..BROWSER_ROWS).for_each(|_| browser.move_selection(Down));
+        let rows = browser.visible_rows();
+        assert_eq!(BROWSER_ROWS, rows.len());
+        assert_eq!(("ROM1".to_string(), false), rows[0]);
+        assert_eq!((format!("ROM{}", BROWSER_ROWS), true), rows[BROWSER_ROWS - 1]);
+
+        browser.move_selection(PageDown);
+        assert_eq!(Some(&RomSource::File(PathBuf::from(format!("ROM{}", BROWSER_ROWS + 9)))), browser.selected_rom());
+        browser.move_selection(PageUp);
+        browser.move_selection(First);
+        assert_eq!(("ROM0".to_string(), true), browser.visible_rows()[0]);
+        browser.move_selection(Last);
+        assert_eq!(("ROM33".to_string(), true), browser.visible_rows()[BROWSER_ROWS - 1]);
+    }
+
+    #[test]
+    fn lists_library_directories() {
+        let rom_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("ROMs");
+        let roms = [rom_dir.to_string_lossy().into_owned(), "missing directory".to_string()];
+        let mut browser = RomBrowser::from_roots(&roms, &Library::parse("").unwrap());
+        assert_eq!("15PUZZLE", browser.visible_rows()[0].0);
+
+        // Known ROMs are listed by title
+        let titled = RomBrowser::from_roots(&roms, &Library::bundled());
+        assert!(titled.visible_rows().iter().any(|(name, _)| name == "Space Invaders"));
+
+        // Selecting PONG previews its screen
+        while browser.selected_rom() != Some(&RomSource::File(rom_dir.join("PONG"))) {
+            browser.move_selection(Down)
+        }
+        let (pixels, colours) = browser.preview().unwrap();
+        assert!(pixels.iter().any(|p| *p));
+        assert_eq!((WHITE, BLACK), colours);
+        assert!(RomBrowser::new(Vec::new()).preview().is_none());
+    }
+
+    #[test]
+    fn previews_use_library_settings() {
+        let rom_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("ROMs");
+        let library = Library::bundled();
+        let mut browser = RomBrowser::from_roots(&[rom_dir.to_string_lossy().into_owned()], &library);
+        while browser.selected_rom() != Some(&RomSource::File(rom_dir.join("BLINKY"))) {
+            browser.move_selection(Down)
+        }
+
+        // Blinky runs faster than the default and has its own colours
+        let rom_bytes = fs::read(rom_dir.join("BLINKY")).unwrap();
+        let info = library.lookup(&rom_bytes).unwrap();
+        let (pixels, colours) = browser.preview().unwrap();
+        assert_eq!(info.colours.unwrap(), colours);
+        assert_eq!(run_preview(&rom_bytes, Some(info)).unwrap(), pixels);
+        assert_ne!(run_preview(&rom_bytes, None).unwrap(), pixels);
+    }
+
+    #[test]
+    fn oversized_rom_has_no_preview() {
+        assert!(run_preview(&vec![0; RAM_BYTES - ROM_ADDR], None).is_some());
+        assert!(run_preview(&vec![0; RAM_BYTES - ROM_ADDR + 1], None).is_none());
+    }
+}
+
+mod rom_files_test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    use rom_files::{RomSource, scan_roots};
+
+    // A fresh directory per test under the system temp directory
+    fn temp_library(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("chip8-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("pack/.hidden")).unwrap();
+        root
+    }
+
+    #[test]
+    fn scans_directories_and_archives() {
+        let root = temp_library("scan");
+        fs::write(root.join("PONG"), [0x12, 0x00]).unwrap();
+        fs::write(root.join("notes.txt"), "not a ROM").unwrap();
+        fs::write(root.join("pack/game.CH8"), [0x00, 0xE0]).unwrap();
+        fs::write(root.join("pack/.hidden/secret.ch8"), [0x00]).unwrap();
+        fs::write(root.join("broken.zip"), "not an archive").unwrap();
+
+        let mut zip = ZipWriter::new(File::create(root.join("more.zip")).unwrap());
+        zip.start_file("super/big.sc8", SimpleFileOptions::default()).unwrap();
+        zip.write_all(&[0x00, 0xFF]).unwrap();
+        zip.start_file("readme.txt", SimpleFileOptions::default()).unwrap();
+        zip.finish().unwrap();
+
+        let roots = [root.to_string_lossy().into_owned(), root.join("missing").to_string_lossy().into_owned()];
+        let scan = scan_roots(&roots);
+        let names = scan.roms.iter().map(|rom| rom.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(vec!["PONG", "more.zip/super/big.sc8", "pack/game.CH8"], names);
+        assert_eq!(2, scan.errors.len(), "{:?}", scan.errors);
+
+        assert_eq!(RomSource::Zipped(root.join("more.zip"), "super/big.sc8".to_string()), scan.roms[1].source);
+        assert_eq!(vec![0x00, 0xFF], scan.roms[1].source.read().unwrap());
+        assert_eq!(vec![0x12, 0x00], scan.roms[0].source.read().unwrap());
+        assert!(RomSource::Zipped(root.join("more.zip"), "gone.ch8".to_string()).read().is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
+
+mod rom_search_test {
+    use std::io::Cursor;
+
+    use rom_search::{fuzzy_score, search, suggest};
+    use menu_history::{MenuHistory, MAX_RECENT};
+    use rom_menu::menu_order;
+
+    const NAMES: [&str; 5] = ["BRIX", "INVADERS", "PONG", "PONG2", "games/Space Invaders.ch8"];
+
+    #[test]
+    fn fuzzy_matches_subsequences() {
+        assert!(fuzzy_score("spinv", "Space Invaders").is_some());
+        assert!(fuzzy_score("INV", "space invaders").is_some());
+        assert_eq!(None, fuzzy_score("xyz", "Space Invaders"));
+        assert!(fuzzy_score("inv", "Invaders") > fuzzy_score("inv", "iNtro Vector"));
+
+        assert_eq!(vec![2, 3], search("pong", &NAMES));
+        assert_eq!(vec![1, 4], search("inv", &NAMES));
+        assert_eq!(NAMES.len(), search("", &NAMES).len());
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        assert_eq!(Some(2), suggest("PONH", &NAMES));
+        assert_eq!(Some(0), suggest("brx", &NAMES));
+        assert_eq!(None, suggest("TETRIS", &NAMES));
+    }
+
+    #[test]
+    fn menu_lists_favourites_then_recent() {
+        let mut history = MenuHistory::new();
+        history.played("PONG");
+        history.played("BRIX");
+        history.toggle_favourite("INVADERS");
+        history.toggle_favourite("gone");
+
+        assert_eq!(vec![1, 0, 2, 3, 4], menu_order("", &NAMES, &history));
+        assert_eq!(vec![2, 3], menu_order("pong", &NAMES, &history));
+    }
+
+    #[test]
+    fn history_tracks_recent_and_favourites() {
+        let mut history = MenuHistory::new();
+        (0..MAX_RECENT + 2).for_each(|i| history.played(&format!("ROM {}", i)));
+        history.played("ROM 5");
+        assert_eq!(MAX_RECENT, history.recent.len());
+        assert_eq!("ROM 5", history.recent[0]);
+        assert_eq!(1, history.recent.iter().filter(|name| *name == "ROM 5").count());
+
+        history.toggle_favourite("PONG");
+        assert!(history.is_favourite("PONG"));
+        history.toggle_favourite("PONG");
+        assert!(!history.is_favourite("PONG"));
+    }
+
+    #[test]
+    fn history_round_trip() {
+        let mut history = MenuHistory::new();
+        history.played("games/Space Invaders.ch8");
+        history.toggle_favourite("PONG");
+        let mut bytes: Vec<u8> = Vec::new();
+        history.write_to(&mut bytes).unwrap();
+
+        assert_eq!(history, MenuHistory::read_from(Cursor::new(bytes)).unwrap());
+        assert!(MenuHistory::read_from(Cursor::new("played PONG\n")).is_err());
+    }
+}
+
+mod rom_loader_test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use library::Platform;
+    use memory::Memory;
+    use rom_loader::{RomError, check_rom, unsupported_addrs};
+
+    #[test]
+    fn rejects_empty_and_oversized_roms() {
+        match check_rom(Vec::new(), Platform::Chip8) {
+            Err(RomError::Empty) => {},
+            result => panic!("{:?}", result)
+        }
+        match check_rom(vec![0; 0xE01], Platform::XoChip) {
+            Err(RomError::TooLarge { size: 0xE01, max: 0xE00, .. }) => {},
+            result => panic!("{:?}", result)
+        }
+        assert!(check_rom(vec![0x12, 0x00], Platform::Chip8).unwrap().warnings.is_empty());
+
+        let mut memory = Memory::new();
+        assert!(memory.load_rom(&[0; 0xE00]).is_ok());
+        assert!(memory.load_rom(&[0; 0x1000]).is_err());
+    }
+
+    #[test]
+    fn warns_about_odd_lengths_and_unsupported_instructions() {
+        assert_eq!(1, check_rom(vec![0x12, 0x00, 0xFF], Platform::Chip8).unwrap().warnings.len());
+
+        // SE skips to either an undefined instruction or SCHIP's exit, the sprite data after is never run
+        let rom = [0x30, 0x00, 0x80, 0x0F, 0x00, 0xFD, 0x12, 0x00, 0xFF, 0xFF];
+        assert_eq!(vec![0x202, 0x204], unsupported_addrs(&rom));
+        let warnings = check_rom(rom.to_vec(), Platform::Chip8).unwrap().warnings;
+        assert_eq!(vec!["Instructions not supported in CHIP-8 mode at 202, 204".to_string()], warnings);
+
+        // Calls return to the instruction after them
+        assert_eq!(vec![0x202], unsupported_addrs(&[0x22, 0x04, 0xF0, 0xFF, 0x00, 0xEE]));
+    }
+
+    #[test]
+    fn bundled_roms_load_cleanly() {
+        let rom_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("ROMs");
+        for entry in fs::read_dir(rom_dir).unwrap() {
+            let path = entry.unwrap().path();
+            let rom_bytes = fs::read(&path).unwrap();
+            let odd_length = rom_bytes.len() % 2;
+            let checked = check_rom(rom_bytes, Platform::Chip8).unwrap();
+            assert_eq!(odd_length, checked.warnings.len(), "{}: {:?}", path.display(), checked.warnings);
+        }
+    }
+}
+
+mod analysis_test {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use analysis::{Analysis, trace};
+    use library::{Platform, Quirk, extension};
+
+    const ROM: [u8; 30] = [
+        0x61, 0x05, // 200 LD V1, 5
+        0xE1, 0x9E, // 202 SKP V1
+        0x22, 0x12, // 204 CALL 212
+        0x82, 0x36, // 206 SHR V2, V3
+        0xA2, 0x00, // 208 LD I, 200
+        0xF0, 0x55, // 20A LD [I], V0
+        0xD0, 0x15, // 20C DRW V0, V1, 5
+        0x00, 0xFF, // 20E hires
+        0x12, 0x0E, // 210 JP 20E
+        0xF0, 0x0A, // 212 LD V0, K
+        0x80, 0x01, // 214 OR V0, V0
+        0x3F, 0x00, // 216 SE VF, 0
+        0x00, 0xEE, // 218 RET
+        0xB3, 0x00, // 21A JP V0, 300
+        0xFF, 0xFF  // 21C data
+    ];
+
+    #[test]
+    fn traces_reachable_code() {
+        let flow = trace(&ROM);
+        assert_eq!(14, flow.code.len());
+        assert!(!flow.code.contains_key(&0x21C));
+
+        let mut calls = BTreeMap::new();
+        calls.insert(0x200, vec![0x212].into_iter().collect::<BTreeSet<usize>>());
+        calls.insert(0x212, BTreeSet::new());
+        assert_eq!(calls, flow.calls);
+    }
+
+    #[test]
+    fn reports_rom_features() {
+        let analysis = Analysis::of(&ROM);
+        assert_eq!(Platform::SuperChip, analysis.platform);
+        assert_eq!(vec![(0x20E, Platform::SuperChip, "hires")], analysis.extensions);
+        assert_eq!(vec![(Quirk::ShiftVy, 0x206), (Quirk::LoadStoreI, 0x20A), (Quirk::VfReset, 0x214),
+            (Quirk::JumpVx, 0x21A)], analysis.quirks);
+        assert_eq!(vec![5], analysis.keys.into_iter().collect::<Vec<u16>>());
+        assert_eq!(vec![0x212], analysis.key_waits);
+        assert!(analysis.unknown_key_tests.is_empty());
+        assert_eq!(vec![(0x20A, 0x200)], analysis.self_modifying);
+        assert!(analysis.histogram.contains(&("RET".to_string(), 1)));
+        assert_eq!(14, analysis.histogram.iter().map(|(_, count)| count).sum::<usize>());
+        assert!(Analysis::of(&ROM).to_string().contains("200 -> 212"));
+    }
+
+    #[test]
+    fn names_platform_extensions() {
+        assert_eq!(Some((Platform::SuperChip, "exit")), extension(0x00FD));
+        assert_eq!(Some((Platform::XoChip, "long I")), extension(0xF000));
+        assert_eq!(None, extension(0x6000));
+    }
+}
+
+mod profiler_test {
+    use chip8::{Chip8Builder, Engine};
+    use command::CommandEmulator;
+    use constants::ROM_ADDR;
+    use opcode::Operation::*;
+
+    const ROM: [u8; 10] = [
+        0x60, 0x00, // 200 LD V0, 0
+        0x30, 0x01, // 202 SE V0, 1
+        0x12, 0x02, // 204 JP 202
+        0x00, 0xE0, // 206 CLS, never run
+        0x12, 0x08  // 208 JP 208
+    ];
+
+    #[test]
+    fn counts_hits_on_both_engines() {
+        for &engine in &[Engine::Interpreter, Engine::Translated] {
+            let mut chip8 = Chip8Builder::new().memory(ROM_ADDR, &ROM).build();
+            chip8.set_engine(engine);
+            chip8.start_profiling();
+            (0..7).for_each(|_| chip8.emulate_cycle());
+
+            let profile = chip8.take_profile().unwrap();
+            assert_eq!(7, profile.total());
+            assert_eq!((1, 3, 3, 0), (profile.hits(0x200), profile.hits(0x202), profile.hits(0x204), profile.hits(0x206)));
+            assert_eq!((1, 3, 3), (profile.operation_hits(LD), profile.operation_hits(SE), profile.operation_hits(JP)));
+            assert_eq!(vec![(0x206, 0x208)], profile.never_executed(&ROM));
+        }
+    }
+
+    #[test]
+    fn writes_annotated_report() {
+        let mut chip8 = Chip8Builder::new().memory(ROM_ADDR, &ROM).build();
+        chip8.start_profiling();
+        (0..7).for_each(|_| chip8.emulate_cycle());
+
+        let mut report: Vec<u8> = Vec::new();
+        chip8.take_profile().unwrap().write_report(&mut report, &ROM).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("Instructions run: 7\n"), "{}", report);
+        assert!(report.lines().any(|line| line.starts_with("202: 3001") && line.contains("42.86%")), "{}", report);
+        assert!(report.contains("206-208 (2 instructions)"), "{}", report);
+    }
+}
+
+mod debugger_test {
+    use debugger::{Debugger, StopReason};
+    use emulator::Chip8Emulator;
+    use io::HeadlessIO;
+    use rng::SeededRandom;
+
+    // Steps from a halt, with or without the emulator running a frame
+    fn step(debugger: &mut Debugger, emulator: &mut Chip8Emulator, is_run: bool) -> Option<StopReason> {
+        if debugger.is_halted() { debugger.step() }
+        assert!(debugger.before_cycle(emulator));
+        if is_run { emulator.run_frame() }
+        debugger.after_cycle(emulator);
+        debugger.take_stop()
+    }
+
+    #[test]
+    fn steps_finish_once_an_instruction_runs() {
+        // 200 LD V0, K; 202 JP 202
+        let mut emulator = Chip8Emulator::new(Box::new(HeadlessIO::new()), Box::new(SeededRandom::new(0)));
+        emulator.load_game(&[0xF0, 0x0A, 0x12, 0x02]).unwrap();
+        let mut debugger = Debugger::new();
+
+        // Nothing ran, e.g. the emulator is paused
+        assert_eq!(None, step(&mut debugger, &mut emulator, false));
+        assert_eq!(Some(StopReason::Step), step(&mut debugger, &mut emulator, true));
+        assert_eq!(0x202, emulator.get_chip8().get_pc());
+
+        // Waiting for a key, then the jump to itself runs
+        assert_eq!(None, step(&mut debugger, &mut emulator, true));
+        assert!(!debugger.is_halted());
+        emulator.press_key(0x5, true);
+        assert_eq!(Some(StopReason::Step), step(&mut debugger, &mut emulator, true));
+        assert_eq!(0x202, emulator.get_chip8().get_pc());
+    }
+}
+
+mod gdb_test {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use emulator::Chip8Emulator;
+    use gdb::{GdbStub, decode_packet, encode_packet};
+    use io::HeadlessIO;
+    use rng::SeededRandom;
+
+    const ROM: [u8; 8] = [
+        0x60, 0x01, // 200 LD V0, 1
+        0x70, 0x01, // 202 ADD V0, 1
+        0x61, 0x07, // 204 LD V1, 7
+        0x12, 0x02  // 206 JP 202
+    ];
+
+    // Sends a packet and returns the reply, acknowledging both ways
+    fn request(stream: &mut TcpStream, packet: &str) -> String {
+        stream.write_all(&encode_packet(packet)).unwrap();
+        let mut ack = [0; 1];
+        stream.read_exact(&mut ack).unwrap();
+        assert_eq!(b'+', ack[0]);
+        reply(stream)
+    }
+
+    fn reply(stream: &mut TcpStream) -> String {
+        let mut received = Vec::new();
+        let mut byte = [0; 1];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            received.push(byte[0]);
+            if let Some((packet, _)) = decode_packet(&received) {
+                stream.write_all(b"+").unwrap();
+                return packet.expect("Reply checksum should be valid")
+            }
+        }
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        assert_eq!(b"$OK#9a".to_vec(), encode_packet("OK"));
+        assert_eq!(Some((Some("OK".to_string()), 6)), decode_packet(b"$OK#9a+"));
+        assert_eq!(Some((None, 6)), decode_packet(b"$OK#00"));
+        assert_eq!(None, decode_packet(b"$OK#9"));
+    }
+
+    #[test]
+    fn debugs_a_running_rom() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let emulator_thread = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut emulator = Chip8Emulator::new(Box::new(HeadlessIO::new()), Box::new(SeededRandom::new(0)));
+            emulator.set_pacing(100_000, 1);
+            emulator.attach_debugger(Box::new(GdbStub::new(stream).unwrap()));
+            emulator.run_game(&ROM).unwrap()
+        });
+
+        let mut gdb = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        assert_eq!("PacketSize=1000", request(&mut gdb, "qSupported:swbreak+"));
+        assert_eq!("S05", request(&mut gdb, "?"));
+        let registers = request(&mut gdb, "g");
+        assert_eq!(46, registers.len());
+        assert_eq!("0200", &registers[36..40]);
+
+        // Stops before the breakpoint's instruction, then steps over it. Resuming
+        // packets are answered when the program stops.
+        assert_eq!("OK", request(&mut gdb, "Z0,204,2"));
+        assert_eq!("S05", request(&mut gdb, "c"));
+        assert_eq!(("0204".to_string(), "02".to_string()), (request(&mut gdb, "p11"), request(&mut gdb, "p0")));
+        assert_eq!("S05", request(&mut gdb, "s"));
+        assert_eq!(("0206".to_string(), "07".to_string()), (request(&mut gdb, "p11"), request(&mut gdb, "p1")));
+        assert_eq!("OK", request(&mut gdb, "z0,204,2"));
+
+        assert_eq!("60017001", request(&mut gdb, "m200,4"));
+        assert_eq!("OK", request(&mut gdb, "M300,2:abcd"));
+        assert_eq!("abcd", request(&mut gdb, "m300,2"));
+        assert_eq!("E01", request(&mut gdb, "mfff,2"));
+        assert_eq!("E01", request(&mut gdb, "m300,0"));
+        assert_eq!("E01", request(&mut gdb, "M300,2:a\u{e9}b"));
+        assert_eq!("E01", request(&mut gdb, "M300,1:+f"));
+        assert_eq!("E01", request(&mut gdb, &format!("G0\u{e9}{}", "0".repeat(43))));
+        assert_eq!("abcd", request(&mut gdb, "m300,2"));
+        assert_eq!("OK", request(&mut gdb, "P3=7f"));
+        assert_eq!("7f", request(&mut gdb, "p3"));
+        assert_eq!("OK", request(&mut gdb, "P10=0abc"));
+        assert_eq!("0abc", &request(&mut gdb, "g")[32..36]);
+        assert_eq!("", request(&mut gdb, "vMustReplyEmpty"));
+
+        // Interrupted while running
+        gdb.write_all(&encode_packet("c")).unwrap();
+        let mut ack = [0; 1];
+        gdb.read_exact(&mut ack).unwrap();
+        gdb.write_all(&[0x03]).unwrap();
+        assert_eq!("S02", reply(&mut gdb));
+
+        gdb.write_all(&encode_packet("k")).unwrap();
+        emulator_thread.join().unwrap();
+    }
+}
+
+mod dap_test {
+    use std::io::Cursor;
+    use std::path::Path;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+
+    use serde_json::{Value, json};
+
+    use emulator::Chip8Emulator;
+    use dap::{DapSession, LaunchArgs, read_message, write_message, send_messages, wait_for_launch,
+        base64_encode, base64_decode};
+    use io::HeadlessIO;
+    use rng::SeededRandom;
+    use source_map::SourceMap;
+
+    const ROM: [u8; 12] = [
+        0x60, 0x01, // 200 LD V0, 1
+        0x22, 0x08, // 202 CALL 208
+        0x70, 0x01, // 204 ADD V0, 1
+        0x12, 0x02, // 206 JP 202
+        0x61, 0x07, // 208 LD V1, 7
+        0x00, 0xEE  // 20A RET
+    ];
+
+    // The subroutine's label is on line 5
+    const SOURCE_MAP: &str = "
+        # game.asm
+        200 1 game.asm
+        202 2 game.asm
+        204 3 game.asm
+        206 4 game.asm
+        208 6 game.asm
+        20a 7 game.asm
+        ";
+
+    fn next_message(messages: &Receiver<Value>) -> Value {
+        messages.recv_timeout(Duration::from_secs(5)).expect("The session should reply")
+    }
+
+    fn request(requests: &Sender<Value>, messages: &Receiver<Value>, command: &str, args: Value) -> Value {
+        requests.send(json!({ "seq": 1, "type": "request", "command": command, "arguments": args })).unwrap();
+        let reply = next_message(messages);
+        assert_eq!((json!("response"), json!(command)), (reply["type"].clone(), reply["command"].clone()));
+        assert_eq!(json!(true), reply["success"], "{}", reply);
+        reply["body"].clone()
+    }
+
+    fn stopped(messages: &Receiver<Value>) -> Value {
+        let message = next_message(messages);
+        assert_eq!(json!("stopped"), message["event"], "{}", message);
+        message["body"]["reason"].clone()
+    }
+
+    fn top_frame(requests: &Sender<Value>, messages: &Receiver<Value>) -> Value {
+        request(requests, messages, "stackTrace", json!({ "threadId": 1 }))["stackFrames"][0].clone()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &json!({ "command": "threads" })).unwrap();
+        assert_eq!(b"Content-Length: 21\r\n\r\n{\"command\":\"threads\"}".to_vec(), bytes);
+        let mut reader = Cursor::new(bytes);
+        assert_eq!(Some(json!({ "command": "threads" })), read_message(&mut reader).unwrap());
+        assert_eq!(None, read_message(&mut reader).unwrap());
+        assert!(read_message(&mut Cursor::new(b"Content-Length: 2\r\n\r\n{]".to_vec())).is_err());
+
+        let (sender, receiver) = channel();
+        sender.send(json!({ "event": "a" })).unwrap();
+        sender.send(json!({ "event": "b" })).unwrap();
+        drop(sender);
+        let mut written = Vec::new();
+        send_messages(receiver, &mut written).unwrap();
+        let mut reader = Cursor::new(written);
+        assert_eq!(json!(1), read_message(&mut reader).unwrap().unwrap()["seq"]);
+        assert_eq!(json!(2), read_message(&mut reader).unwrap().unwrap()["seq"]);
+
+        assert_eq!("Q2hpcC04IQ==", base64_encode(b"Chip-8!"));
+        assert_eq!(Some(b"Chip-8!".to_vec()), base64_decode("Q2hpcC04IQ=="));
+        assert_eq!(None, base64_decode("Q2h*"));
+    }
+
+    #[test]
+    fn parses_source_maps() {
+        let map = SourceMap::read_from(Cursor::new(SOURCE_MAP), Path::new("/src")).unwrap();
+        assert_eq!(6, map.lines().len());
+        assert_eq!(Some(6), map.location(0x208).map(|source_line| source_line.line));
+        assert_eq!(None, map.location(0x209));
+        assert_eq!(Some(0x208), map.instruction_at(Path::new("/src/game.asm"), 5).map(|source_line| source_line.addr));
+        assert_eq!(None, map.instruction_at(Path::new("/elsewhere/game.asm"), 1));
+        assert_eq!(None, map.instruction_at(Path::new("/src/game.asm"), 8));
+        assert_eq!(None, map.instruction_at(Path::new("/src/other.asm"), 1));
+        // The same file written another way
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let map = SourceMap::read_from(Cursor::new("200 1 README.md"), Path::new(dir)).unwrap();
+        let other_way = Path::new(dir).join("src").join("..").join("README.md");
+        assert_eq!(Some(0x200), map.instruction_at(&other_way, 1).map(|source_line| source_line.addr));
+        for entry in &["200 game.asm", "20g 1 game.asm", "200 0 game.asm", "200 1"] {
+            assert!(SourceMap::read_from(Cursor::new(*entry), Path::new("")).is_err(), "{}", entry)
+        }
+    }
+
+    #[test]
+    fn answers_until_launch() {
+        let (requests, request_receiver) = channel();
+        let (message_sender, messages) = channel();
+        requests.send(json!({ "seq": 1, "command": "initialize", "arguments": {} })).unwrap();
+        requests.send(json!({ "seq": 2, "command": "threads" })).unwrap();
+        requests.send(json!({ "seq": 3, "command": "launch", "arguments": { "program": "pong.ch8", "stopOnEntry": true } })).unwrap();
+
+        let (launch_request, launch) = wait_for_launch(&request_receiver, &message_sender).unwrap();
+        assert_eq!(json!(3), launch_request["seq"]);
+        assert_eq!(LaunchArgs { program: "pong.ch8".to_string(), source_map: None, stop_on_entry: true }, launch);
+        assert_eq!(json!(true), next_message(&messages)["body"]["supportsInstructionBreakpoints"]);
+        assert_eq!(json!(false), next_message(&messages)["success"]);
+    }
+
+    #[test]
+    fn debugs_a_running_rom() {
+        let (requests, request_receiver) = channel();
+        let (message_sender, messages) = channel();
+        let map = SourceMap::read_from(Cursor::new(SOURCE_MAP), Path::new("/src")).unwrap();
+        let emulator_thread = thread::spawn(move || {
+            let mut emulator = Chip8Emulator::new(Box::new(HeadlessIO::new()), Box::new(SeededRandom::new(0)));
+            emulator.set_pacing(100_000, 1);
+            emulator.attach_debugger(Box::new(DapSession::new(request_receiver, message_sender, Some(map), false)));
+            emulator.load_game(&ROM).unwrap();
+            emulator.play(&ROM)
+        });
+
+        // Moved to the next line with an instruction
+        let breakpoints = request(&requests, &messages, "setBreakpoints",
+            json!({ "source": { "path": "/src/game.asm" }, "breakpoints": [{ "line": 5 }, { "line": 99 }] }))["breakpoints"].clone();
+        assert_eq!((json!(true), json!(6)), (breakpoints[0]["verified"].clone(), breakpoints[0]["line"].clone()));
+        assert_eq!(json!(false), breakpoints[1]["verified"]);
+
+        request(&requests, &messages, "configurationDone", json!({}));
+        assert_eq!(json!("breakpoint"), stopped(&messages));
+        let frames = request(&requests, &messages, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(json!(2), frames["totalFrames"]);
+        let names_and_lines = frames["stackFrames"].as_array().unwrap().iter()
+            .map(|frame| (frame["name"].clone(), frame["line"].clone(), frame["instructionPointerReference"].clone()))
+            .collect::<Vec<(Value, Value, Value)>>();
+        assert_eq!(vec![(json!("sub_208"), json!(6), json!("0x208")), (json!("main"), json!(2), json!("0x202"))], names_and_lines);
+        assert_eq!(json!("/src/game.asm"), frames["stackFrames"][0]["source"]["path"]);
+
+        let registers = request(&requests, &messages, "variables", json!({ "variablesReference": 1 }))["variables"].clone();
+        assert_eq!((json!("V0"), json!("0x01 (1)")), (registers[0]["name"].clone(), registers[0]["value"].clone()));
+        assert_eq!((json!("PC"), json!("0x208")), (registers[17]["name"].clone(), registers[17]["memoryReference"].clone()));
+        assert_eq!(2, request(&requests, &messages, "variables", json!({ "variablesReference": 2 }))["variables"].as_array().unwrap().len());
+        assert_eq!(json!("0x7F (127)"), request(&requests, &messages, "setVariable",
+            json!({ "variablesReference": 1, "name": "V3", "value": "0x7f" }))["value"]);
+
+        // Out of the subroutine, then over the call on the next pass
+        request(&requests, &messages, "stepOut", json!({ "threadId": 1 }));
+        assert_eq!(json!("step"), stopped(&messages));
+        assert_eq!(json!(3), top_frame(&requests, &messages)["line"]);
+        request(&requests, &messages, "setBreakpoints", json!({ "source": { "path": "/src/game.asm" }, "breakpoints": [] }));
+        for line in &[4, 2, 3] {
+            request(&requests, &messages, "next", json!({ "threadId": 1 }));
+            assert_eq!(json!("step"), stopped(&messages));
+            assert_eq!(json!(line), top_frame(&requests, &messages)["line"])
+        }
+
+        assert_eq!(json!("YAEiCA=="), request(&requests, &messages, "readMemory",
+            json!({ "memoryReference": "0x200", "count": 4 }))["data"]);
+        request(&requests, &messages, "writeMemory", json!({ "memoryReference": "0x2FF", "offset": 1, "data": "q80=" }));
+        assert_eq!(json!("q80="), request(&requests, &messages, "readMemory",
+            json!({ "memoryReference": "0x300", "count": 2 }))["data"]);
+        assert_eq!(json!(3), request(&requests, &messages, "readMemory",
+            json!({ "memoryReference": "0xFFF", "count": 4 }))["unreadableBytes"]);
+        assert_eq!(json!(u64::MAX - 1), request(&requests, &messages, "readMemory",
+            json!({ "memoryReference": "0xFFE", "offset": 1, "count": u64::MAX }))["unreadableBytes"]);
+        let instructions = request(&requests, &messages, "disassemble",
+            json!({ "memoryReference": "0x200", "instructionCount": 2 }))["instructions"].clone();
+        assert_eq!((json!("LD V0, 1"), json!(2)), (instructions[0]["instruction"].clone(), instructions[1]["line"].clone()));
+
+        request(&requests, &messages, "setInstructionBreakpoints", json!({ "breakpoints": [{ "instructionReference": "0x206" }] }));
+        request(&requests, &messages, "continue", json!({ "threadId": 1 }));
+        assert_eq!(json!("breakpoint"), stopped(&messages));
+        assert_eq!(json!("0x206"), top_frame(&requests, &messages)["instructionPointerReference"]);
+
+        request(&requests, &messages, "setInstructionBreakpoints", json!({ "breakpoints": [] }));
+        request(&requests, &messages, "continue", json!({ "threadId": 1 }));
+        request(&requests, &messages, "pause", json!({ "threadId": 1 }));
+        assert_eq!(json!("pause"), stopped(&messages));
+
+        request(&requests, &messages, "terminate", json!({}));
+        assert_eq!(json!("terminated"), next_message(&messages)["event"]);
+        assert_eq!(json!("exited"), next_message(&messages)["event"]);
+        emulator_thread.join().unwrap();
+    }
+}
+
+mod script_test {
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    use chip8::Chip8Builder;
+    use emulator::Chip8Emulator;
+    use io::HeadlessIO;
+    use rng::{RngState, SeededRandom, ScriptedRandom};
+    use save_state::SaveState;
+    use script::{Script, ScriptError};
+
+    const ROM: [u8; 10] = [
+        0xA3, 0x00, // 200 LD I, 300
+        0x60, 0x05, // 202 LD V0, 5
+        0x70, 0x01, // 204 ADD V0, 1
+        0xF0, 0x55, // 206 LD [I], V0
+        0x12, 0x04  // 208 JP 204
+    ];
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("chip8-{}-{}", name, ::std::process::id()))
+    }
+
+    fn run_script(source: &str) -> Chip8Emulator {
+        let mut emulator = Chip8Emulator::new(Box::new(HeadlessIO::new()), Box::new(SeededRandom::new(0)));
+        // 100 cycles a video frame
+        emulator.set_pacing(6_000, 1);
+        emulator.attach_script(Script::compile(source).unwrap());
+        emulator.run_game(&ROM).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn save_states_round_trip() {
+        let chip8 = Chip8Builder::new()
+            .rng(Box::new(ScriptedRandom::new(&[1, 2, 3])))
+            .pc(0x2A4).i(0x3F0).register(0xF, 1).stack(&[0x202, 0x240])
+            .delay_timer(60).memory(0x300, &[0xDE, 0xAD]).pixel(63, 31, true)
+            .build();
+        let state = chip8.save_state();
+        let mut bytes = Vec::new();
+        state.write_to(&mut bytes).unwrap();
+        assert_eq!(state, SaveState::read_from(Cursor::new(&bytes)).unwrap());
+        assert_eq!(Some(RngState::Scripted(vec![1, 2, 3], 0)), state.rng);
+
+        let mut restored = Chip8Builder::new().build();
+        restored.restore_state(&state);
+        assert_eq!(state, restored.save_state());
+        assert_eq!(&[0x202, 0x240], restored.get_stack());
+
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(SaveState::read_from(Cursor::new(text.replace("CHIP8STATE 1", "CHIP8STATE 2"))).is_err());
+        assert!(SaveState::read_from(Cursor::new(text.replace("pc 2a4", "pc"))).is_err());
+        assert!(SaveState::read_from(Cursor::new(text.replace("memory 00", "memory 0g"))).is_err());
+    }
+
+    #[test]
+    fn hooks_drive_the_game() {
+        let screenshot = temp_path("screenshot.pbm");
+        let emulator = run_script(&format!(r#"
+            let runs = 0;
+            on_instruction(0x204, || runs += 1);
+            on_write(0x300, |addr, value| poke(addr + 1, value * 2));
+            let frames = 0;
+            on_frame(|| {{
+                frames += 1;
+                if frame() == 2 {{
+                    set_reg(0xE, runs);
+                    set_reg(0xD, frames);
+                    set_reg(0xC, cycle() / 10);
+                    screenshot("{}");
+                    exit()
+                }}
+            }});
+        "#, screenshot.display()));
+
+        let chip8 = emulator.get_chip8();
+        assert!(emulator.script_error().is_none());
+        assert_eq!(200, emulator.get_frame());
+        assert_eq!(2, emulator.get_video_frame());
+        // Two instructions set up the loop, then each pass is three
+        assert_eq!(66, chip8.get_registers()[0xE]);
+        // Frame hooks run once a video frame, not once a cycle
+        assert_eq!(2, chip8.get_registers()[0xD]);
+        assert_eq!(20, chip8.get_registers()[0xC]);
+        assert_eq!(chip8.get_memory()[0x300] * 2, chip8.get_memory()[0x301]);
+        assert!(fs::read_to_string(&screenshot).unwrap().starts_with("P1\n64 32\n"));
+        fs::remove_file(screenshot).unwrap();
+    }
+
+    #[test]
+    fn loads_saved_states() {
+        let state = temp_path("state.txt");
+        let emulator = run_script(&format!(r#"
+            on_frame(|| {{
+                if frame() == 10 {{ save_state("{0}") }}
+                if frame() == 20 {{ load_state("{0}"); set_reg(0xE, reg(0)) }}
+                if frame() == 21 {{ exit() }}
+            }});
+        "#, state.display()));
+
+        let saved = SaveState::load(state.to_str().unwrap()).unwrap();
+        assert_eq!(saved.registers[0], emulator.get_chip8().get_registers()[0xE]);
+        fs::remove_file(state).unwrap();
+    }
+
+    #[test]
+    fn errors_end_the_game() {
+        assert!(matches!(Script::compile("on_frame(|| "), Err(ScriptError::Syntax(_))));
+        assert!(matches!(Script::load("missing.rhai"), Err(ScriptError::Unreadable(_))));
+
+        let emulator = run_script("on_frame(|| if frame() == 5 { peek(0x1000) })");
+        assert!(matches!(emulator.script_error(), Some(ScriptError::Runtime(_))));
+        assert_eq!(500, emulator.get_frame());
+    }
+}
+
+mod memory_viewer_test {
+    use std::time::{Duration, Instant};
+
+    use command::{Command, GameCommand::ShowMemory, MemoryCommand::{SendRAM, SendByte, SendPointers}};
+    use constants::RAM_BYTES;
+    use emulator::Chip8Emulator;
+    use io::RemoteIO;
+    use memory_viewer::{MemoryViewer, ViewerInput::*, ViewerEdit, Highlight, VIEWER_ROWS, ascii};
+    use rng::SeededRandom;
+    use router::ChannelRouter;
+
+    #[test]
+    fn edits_bytes_and_goes_to_addresses() {
+        let now = Instant::now();
+        let mut viewer = MemoryViewer::new();
+        let mut ram = Box::new([0; RAM_BYTES]);
+        ram[0x204] = 0x41;
+        viewer.process(&SendRAM(ram), now);
+
+        assert_eq!(None, viewer.input(HexDigit(0xA), now));
+        assert_eq!("000: A_   I 000   PC 000", viewer.status_line());
+        assert_eq!(Some(ViewerEdit::Write(0x000, 0xA5)), viewer.input(HexDigit(0x5), now));
+        assert_eq!((1, 0xA5), (viewer.cursor(), viewer.ram()[0]));
+
+        // A move drops a half typed byte
+        viewer.input(HexDigit(0x1), now);
+        viewer.input(Right, now);
+        assert_eq!((2, 0x00), (viewer.cursor(), viewer.ram()[1]));
+
+        viewer.input(GoTo, now);
+        [0x2, 0x0, 0x7].iter().for_each(|&digit| { viewer.input(HexDigit(digit), now); });
+        viewer.input(Erase, now);
+        viewer.input(HexDigit(0x4), now);
+        assert_eq!("GO TO: 204_", viewer.status_line());
+        viewer.input(Confirm, now);
+        assert_eq!(0x204, viewer.cursor());
+        assert_eq!("204: 41   I 000   PC 000", viewer.status_line());
+        assert_eq!('A', ascii(viewer.ram()[0x204]));
+        assert_eq!('.', ascii(0x00));
+
+        // The cursor stays on screen and in memory
+        let rows = viewer.rows(now);
+        assert_eq!((VIEWER_ROWS, 0x200 - (VIEWER_ROWS - 1) * 0x10), (rows.len(), rows[0].0));
+        viewer.input(Up, now);
+        assert_eq!(0x1F4, viewer.cursor());
+        (0..20).for_each(|_| { viewer.input(PageDown, now); });
+        assert_eq!(RAM_BYTES - 1, viewer.cursor());
+        assert_eq!(RAM_BYTES - VIEWER_ROWS * 0x10, viewer.rows(now)[0].0);
+    }
+
+    #[test]
+    fn marks_pointers_and_flashes_writes() {
+        let now = Instant::now();
+        let mut viewer = MemoryViewer::new();
+        viewer.process(&SendPointers(0x20F, 0x200), now);
+        viewer.process(&SendByte(0x20A, 0x12), now);
+        viewer.input(GoToPc, now);
+
+        let rows = viewer.rows(now);
+        let row = &rows.iter().find(|(addr, _)| *addr == 0x200).unwrap().1;
+        let highlights = row.iter().map(|cell| cell.highlight).collect::<Vec<Highlight>>();
+        assert_eq!(&[Highlight::Cursor, Highlight::Pc, Highlight::None], &highlights[..3]);
+        assert_eq!(Highlight::I, highlights[0xF]);
+        assert_eq!((0x12, 0xFF), (row[0xA].value, row[0xA].flash));
+        assert_eq!(0, row[0xB].flash);
+
+        // Flashes keep the window redrawing until they fade
+        assert!(viewer.needs_redraw());
+        let later = now + Duration::from_secs(1);
+        assert_eq!(0, viewer.rows(later)[0].1[0xA].flash);
+        assert!(!viewer.needs_redraw());
+    }
+
+    #[test]
+    fn sprite_is_drawn_from_i() {
+        let now = Instant::now();
+        let mut viewer = MemoryViewer::new();
+        viewer.process(&SendByte(0xFFF, 0x81), now);
+        viewer.process(&SendByte(0x000, 0xF0), now);
+        viewer.process(&SendPointers(0xFFF, 0x200), now);
+
+        // Rows wrap around the end of memory like DRW's reads
+        let sprite = viewer.sprite();
+        assert_eq!(15, sprite.len());
+        assert_eq!([true, false, false, false, false, false, false, true], sprite[0]);
+        assert_eq!([true, true, true, true, false, false, false, false], sprite[1]);
+    }
+
+    #[test]
+    fn emulator_sends_memory_while_shown() {
+        let (emulator_link, mut front_end) = ChannelRouter::pair();
+        let mut emulator = Chip8Emulator::new(
+            Box::new(RemoteIO::new(emulator_link)),
+            Box::new(SeededRandom::new(0)));
+        // LD I, 0x300; LD V0, 0x42; LD [I], V0; JP 0x206
+        emulator.load_game(&[0xA3, 0x00, 0x60, 0x42, 0xF0, 0x55, 0x12, 0x06]).unwrap();
+        emulator.run_frame();
+        assert!(!front_end.consume_all_inbound().iter().any(is_memory));
+
+        front_end.send_outbound(Command::GameState(ShowMemory(true)));
+        (0..4).for_each(|_| emulator.run_frame());
+        let mut viewer = MemoryViewer::new();
+        for command in front_end.consume_all_inbound() {
+            if let Command::Memory(c) = command { viewer.process(&c, Instant::now()) }
+        }
+        assert_eq!(&[0xA3, 0x00, 0x60, 0x42], &viewer.ram()[0x200..0x204]);
+        assert_eq!(0x42, viewer.ram()[0x300]);
+        assert_eq!("000: 00   I 300   PC 206", viewer.status_line());
+
+        // Edits are written to the CPU's memory
+        front_end.send_outbound(Command::Memory(SendByte(0x301, 0x99)));
+        emulator.run_frame();
+        emulator.run_frame();
+        assert_eq!(0x99, emulator.get_chip8().get_memory()[0x301]);
+
+        front_end.send_outbound(Command::GameState(ShowMemory(false)));
+        (0..4).for_each(|_| emulator.run_frame());
+        front_end.consume_all_inbound();
+        emulator.run_frame();
+        assert!(!front_end.consume_all_inbound().iter().any(is_memory));
+    }
+
+    fn is_memory(command: &Command) -> bool {
+        matches!(command, Command::Memory(_))
+    }
+}
+
+mod cheats_test {
+    use std::time::Instant;
+
+    use cheats::{Cheat, CheatFile, CheatTarget, RamSearch, SearchFilter};
+    use chip8::Chip8Builder;
+    use command::{Command, CommandEmulator, GameCommand, MemoryCommand::SendByte};
+    use constants::ROM_ADDR;
+    use emulator::Chip8Emulator;
+    use io::{HeadlessIO, RemoteIO};
+    use memory_viewer::{MemoryViewer, ViewerInput::*, ViewerEdit, Highlight};
+    use movie::rom_hash;
+    use rng::SeededRandom;
+    use router::ChannelRouter;
+
+    // LD I, 0x300; LD V0, [I]; ADD V0, 1; LD [I], V0; ADD V5, 1; JP 0x202
+    const COUNTER_ROM: [u8; 12] = [0xA3, 0x00, 0xF0, 0x65, 0x70, 0x01, 0xF0, 0x55, 0x75, 0x01, 0x12, 0x02];
+
+    #[test]
+    fn parses_cheat_files() {
+        let text = format!("# Counter\n[{}]\ncheat Stuck counter\nfreeze 300 07\n\
+            cheat Stuck V5\nfreeze V5 9\nfreeze 301 ff\n\n[{}]\n", rom_hash(&COUNTER_ROM).to_uppercase(), rom_hash(&[0]));
+        let cheat_file = CheatFile::parse(&text).unwrap();
+        assert_eq!(&[
+            Cheat { name: "Stuck counter".to_string(), freezes: vec![(CheatTarget::Memory(0x300), 0x07)] },
+            Cheat { name: "Stuck V5".to_string(),
+                freezes: vec![(CheatTarget::Register(5), 0x09), (CheatTarget::Memory(0x301), 0xFF)] }
+        ], cheat_file.lookup(&COUNTER_ROM));
+        assert!(cheat_file.lookup(&[0]).is_empty());
+        assert!(cheat_file.lookup(&[1]).is_empty());
+        assert_eq!("v5 300", format!("{} {}", CheatTarget::Register(5), CheatTarget::Memory(0x300)));
+
+        for bad in ["cheat Outside", "[00]\nfreeze 300 07", "[00]\ncheat A\nfreeze 1000 07",
+            "[00]\ncheat A\nfreeze v10 07", "[00]\ncheat A\nfreeze 300 100", "[00]\ncheat", "[00]\nlives 3"].iter() {
+            assert!(CheatFile::parse(bad).is_err(), "{}", bad)
+        }
+    }
+
+    #[test]
+    fn ram_search_narrows_candidates() {
+        let mut ram = vec![3, 3, 5, 0];
+        let mut search = RamSearch::new(&ram);
+        search.filter(&ram, SearchFilter::Equal(3));
+        assert_eq!(&[0, 1], search.candidates());
+
+        ram[1] = 2;
+        ram[2] = 6;
+        search.filter(&ram, SearchFilter::Decreased);
+        assert_eq!(&[1], search.candidates());
+        assert!(search.is_candidate(1) && !search.is_candidate(0));
+
+        let mut search = RamSearch::new(&ram);
+        ram[3] = 1;
+        search.filter(&ram, SearchFilter::Changed);
+        assert_eq!(&[3], search.candidates());
+        let mut search = RamSearch::new(&ram);
+        search.filter(&ram, SearchFilter::Unchanged);
+        search.filter(&[2, 2, 7, 1], SearchFilter::Increased);
+        assert_eq!(&[2], search.candidates());
+    }
+
+    #[test]
+    fn viewer_searches_and_freezes() {
+        let now = Instant::now();
+        let mut viewer = MemoryViewer::new();
+        viewer.process(&SendByte(0x300, 3), now);
+        viewer.process(&SendByte(0x400, 3), now);
+
+        viewer.input(SearchEqual, now);
+        viewer.input(HexDigit(3), now);
+        assert_eq!("EQUAL TO: 3_", viewer.status_line());
+        viewer.input(Confirm, now);
+        viewer.process(&SendByte(0x300, 2), now);
+        viewer.input(Search(SearchFilter::Decreased), now);
+        assert_eq!(Some(&[0x300][..]), viewer.candidates());
+        assert!(viewer.status_line().ends_with("SEARCH 1"));
+
+        viewer.input(NextCandidate, now);
+        assert_eq!(0x300, viewer.cursor());
+        assert_eq!(Some(ViewerEdit::Freeze(0x300, Some(2))), viewer.input(Freeze, now));
+        viewer.input(Left, now);
+        let rows = viewer.rows(now);
+        let row = &rows.iter().find(|(addr, _)| *addr == 0x300).unwrap().1;
+        assert_eq!(Highlight::Frozen, row[0].highlight);
+        viewer.input(Right, now);
+        assert_eq!(Some(ViewerEdit::Freeze(0x300, None)), viewer.input(Freeze, now));
+
+        // A new search starts from every address again
+        viewer.input(StartSearch, now);
+        assert_eq!(Some(0x1000), viewer.candidates().map(|candidates| candidates.len()));
+    }
+
+    #[test]
+    fn cheats_hold_their_targets() {
+        let (emulator_link, mut front_end) = ChannelRouter::pair();
+        let mut emulator = Chip8Emulator::new(
+            Box::new(RemoteIO::new(emulator_link)),
+            Box::new(SeededRandom::new(0)));
+        emulator.load_game(&COUNTER_ROM).unwrap();
+        emulator.enable_cheats(&[Cheat { name: "Stuck V5".to_string(), freezes: vec![(CheatTarget::Register(5), 9)] }]);
+        (0..40).for_each(|_| emulator.run_frame());
+        assert_eq!(9, emulator.get_chip8().get_registers()[5]);
+        assert!(emulator.get_chip8().get_memory()[0x300] > 5);
+
+        // Frozen from the memory window, the program's stores never stick
+        front_end.send_outbound(Command::GameState(GameCommand::Freeze(0x300, Some(0x42))));
+        for _ in 0..40 {
+            emulator.run_frame();
+            assert_eq!(0x42, emulator.get_chip8().get_memory()[0x300])
+        }
+
+        front_end.send_outbound(Command::GameState(GameCommand::Freeze(0x300, None)));
+        (0..40).for_each(|_| emulator.run_frame());
+        assert!(emulator.get_chip8().get_memory()[0x300] > 0x42);
+    }
+
+    #[test]
+    fn frozen_writes_never_land() {
+        let mut chip8 = Chip8Builder::new().memory(ROM_ADDR, &COUNTER_ROM).build();
+        chip8.freeze(0x300, Some(0x42));
+        assert_eq!(0x42, chip8.get_memory()[0x300]);
+        chip8.get_commands().consume_all_outbound();
+
+        (0..40).for_each(|_| chip8.emulate_cycle());
+        assert_eq!(0x42, chip8.get_memory()[0x300]);
+        assert!(chip8.get_commands().consume_all_outbound().iter()
+            .all(|command| !matches!(command, Command::Memory(SendByte(..)))));
+
+        // Writes from elsewhere are answered with the frozen byte
+        chip8.get_commands().send_inbound(Command::Memory(SendByte(0x300, 0x10)));
+        chip8.process_inbound_commands();
+        assert_eq!(0x42, chip8.get_memory()[0x300]);
+        assert!(matches!(chip8.get_commands().consume_all_outbound()[..], [Command::Memory(SendByte(0x300, 0x42))]));
+
+        chip8.freeze(0x300, None);
+        (0..40).for_each(|_| chip8.emulate_cycle());
+        assert!(chip8.get_memory()[0x300] > 0x42);
+    }
+
+    #[test]
+    fn memory_cheats_survive_loading() {
+        let mut emulator = Chip8Emulator::new(Box::new(HeadlessIO::new()), Box::new(SeededRandom::new(0)));
+        emulator.enable_cheats(&[Cheat { name: "Stuck counter".to_string(), freezes: vec![(CheatTarget::Memory(0x300), 7)] }]);
+        emulator.load_game(&COUNTER_ROM).unwrap();
+        (0..40).for_each(|_| emulator.run_frame());
+        assert_eq!(7, emulator.get_chip8().get_memory()[0x300]);
+
+        let mut thawed = emulator.get_chip8().save_state();
+        thawed.memory[0x300] = 0;
+        emulator.get_chip8_mut().restore_state(&thawed);
+        assert_eq!(7, emulator.get_chip8().get_memory()[0x300]);
+    }
+}
+
+mod library_test {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use sdl2::pixels::Color;
+
+    use library::{Library, Platform, Quirk, detect_platform};
+    use movie::rom_hash;
+
+    const DATABASE: &str = "
+        # A test ROM
+        [0000000000000000000000000000000000000000]
+        title Test
+        author Someone
+        year 2021
+        platform schip
+        quirks shift_vy clip
+        speed 600
+        colours ff8000 000020
+        key a Fire the laser
+        ";
+
+    #[test]
+    fn parses_metadata() {
+        let library = Library::parse(DATABASE).unwrap();
+        assert_eq!(1, library.len());
+
+        let mut merged = Library::bundled();
+        let bundled_len = merged.len();
+        merged.merge(library);
+        assert_eq!(bundled_len + 1, merged.len());
+
+        let info = Library::parse(&DATABASE.replace("0000000000000000000000000000000000000000",
+            &rom_hash(&[0x12, 0x00]))).unwrap().lookup(&[0x12, 0x00]).cloned().unwrap();
+        assert_eq!("Test by Someone (2021)", info.credits());
+        assert_eq!(Platform::SuperChip, info.platform);
+        assert_eq!(vec![Quirk::ShiftVy, Quirk::Clip], info.quirks);
+        assert_eq!(Some(600), info.speed);
+        assert_eq!(Some((Color::RGB(0xFF, 0x80, 0x00), Color::RGB(0x00, 0x00, 0x20))), info.colours);
+        assert_eq!(vec![(0xA, "Fire the laser".to_string())], info.keys);
+        assert_eq!(1, info.warnings().len());
+    }
+
+    #[test]
+    fn rejects_bad_metadata() {
+        assert!(Library::parse("title Outside a block").is_err());
+        for line in &["year soon", "platform nes", "quirks fast", "speed 0", "colours ffffff", "key g Jump", "colour red"] {
+            assert!(Library::parse(&format!("[00]\n{}", line)).is_err(), "{}", line)
+        }
+    }
+
+    #[test]
+    fn bundled_roms_are_known() {
+        let library = Library::bundled();
+        let rom_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("ROMs");
+        for entry in fs::read_dir(rom_dir).unwrap() {
+            let rom_bytes = fs::read(entry.unwrap().path()).unwrap();
+            let info = library.lookup(&rom_bytes).expect("Bundled ROMs should be in the database");
+            assert!(info.warnings().is_empty());
+        }
+    }
+
+    #[test]
+    fn bundled_roms_have_their_settings() {
+        let library = Library::bundled();
+        let rom_info = |rom_name| {
+            let rom_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("ROMs").join(rom_name);
+            library.lookup(&fs::read(rom_path).unwrap()).cloned().unwrap()
+        };
+
+        let kaleidoscope = rom_info("KALEID");
+        assert_eq!(vec![Quirk::ShiftVy, Quirk::LoadStoreI, Quirk::VfReset, Quirk::Clip], kaleidoscope.quirks);
+        assert_eq!(None, kaleidoscope.speed);
+
+        let blinky = rom_info("BLINKY");
+        assert!(blinky.quirks.is_empty());
+        assert_eq!(Some(2000), blinky.speed);
+        assert!(rom_info("INVADERS").quirks.is_empty());
+    }
+
+    #[test]
+    fn detects_platform_from_opcodes() {
+        assert_eq!(Platform::Chip8, detect_platform(&[0x60, 0x01, 0xD0, 0x15, 0x12, 0x00]));
+        assert_eq!(Platform::SuperChip, detect_platform(&[0x00, 0xFF, 0xD0, 0x10]));
+        assert_eq!(Platform::XoChip, detect_platform(&[0x00, 0xFF, 0xF0, 0x00, 0x12, 0x34]));
+    }
+}
+
+mod opcode_test {
+    use chip8::{Chip8, Chip8Builder, Engine, Quirks};
+    use command::{Command, CommandEmulator, AudioCommand::{Play, Pause}, KeyCommand::KeyDownUp, MemoryCommand};
+    use constants::{ROM_ADDR, W};
+    use rng::ScriptedRandom;
+
+    const PC: u16 = ROM_ADDR as u16;
+
+    // Runs one instruction placed at the program start
+    fn run(builder: Chip8Builder, instruction: u16) -> Chip8 {
+        let mut chip8 = builder
+            .memory(ROM_ADDR, &[(instruction >> 8) as u8, instruction as u8])
+            .build();
+        chip8.emulate_cycle();
+        chip8
+    }
+
+    #[test]
+    fn arithmetic_and_flags() {
+        // (instruction, V1, V2, expected V1, expected VF), VF starts at 0xAA
+        let cases: [(u16, u8, u8, u8, u8); 24] = [
+            (0x61AB, 0x00, 0x00, 0xAB, 0xAA), // LD V1, nn
+            (0x71FF, 0x02, 0x00, 0x01, 0xAA), // ADD V1, nn has no carry flag
+            (0x8120, 0x12, 0x34, 0x34, 0xAA), // LD V1, V2
+            (0x8121, 0xF0, 0x0F, 0xFF, 0xAA), // OR
+            (0x8122, 0xF0, 0x3C, 0x30, 0xAA), // AND
+            (0x8123, 0xFF, 0x0F, 0xF0, 0xAA), // XOR
+            (0x8124, 0x10, 0x20, 0x30, 0x00), // ADD
+            (0x8124, 0xFF, 0x01, 0x00, 0x01), // ADD carry to zero
+            (0x8124, 0xF0, 0x20, 0x10, 0x01), // ADD carry
+            (0x8124, 0xFF, 0xFF, 0xFE, 0x01), // ADD largest carry
+            (0x8125, 0x30, 0x10, 0x20, 0x01), // SUB
+            (0x8125, 0x10, 0x30, 0xE0, 0x00), // SUB borrow
+            (0x8125, 0x20, 0x20, 0x00, 0x01), // SUB equal has no borrow
+            (0x8125, 0x00, 0xFF, 0x01, 0x00), // SUB largest borrow
+            (0x8126, 0x05, 0x00, 0x02, 0x01), // SHR shifts out 1
+            (0x8126, 0x04, 0x00, 0x02, 0x00), // SHR shifts out 0
+            (0x8126, 0x01, 0x00, 0x00, 0x01), // SHR to zero
+            (0x8127, 0x10, 0x30, 0x20, 0x01), // SUBN
+            (0x8127, 0x30, 0x10, 0xE0, 0x00), // SUBN borrow
+            (0x8127, 0x20, 0x20, 0x00, 0x01), // SUBN equal has no borrow
+            (0x812E, 0x81, 0x00, 0x02, 0x01), // SHL shifts out 1
+            (0x812E, 0x41, 0x00, 0x82, 0x00), // SHL shifts out 0
+            (0x812E, 0x80, 0x00, 0x00, 0x01), // SHL to zero
+            (0x8124, 0x00, 0x00, 0x00, 0x00), // ADD zero
+        ];
+
+        for &(instruction, v1, v2, expected_v1, expected_vf) in cases.iter() {
+            let chip8 = run(Chip8Builder::new()
+                .register(1, v1)
+                .register(2, v2)
+                .register(0xF, 0xAA), instruction);
+            assert_eq!((expected_v1, expected_vf),
+                (chip8.get_registers()[1], chip8.get_registers()[0xF]),
+                "{:04X} with V1={:#04x}, V2={:#04x}", instruction, v1, v2);
+            assert_eq!(v2, chip8.get_registers()[2]);
+        }
+    }
+
+    #[test]
+    fn flag_wins_over_vf_result() {
+        // (instruction, VF, V1, expected VF)
+        let cases: [(u16, u8, u8, u8); 6] = [
+            (0x8F14, 0xFF, 0x01, 0x01), // ADD VF, V1 carry
+            (0x8F14, 0x01, 0x01, 0x00), // ADD VF, V1 no carry
+            (0x8F15, 0x01, 0x02, 0x00), // SUB VF, V1 borrow
+            (0x8F17, 0x01, 0x02, 0x01), // SUBN VF, V1
+            (0x8F06, 0x03, 0x00, 0x01), // SHR VF
+            (0x8F0E, 0x40, 0x00, 0x00), // SHL VF
+        ];
+
+        for &(instruction, vf, v1, expected_vf) in cases.iter() {
+            let chip8 = run(Chip8Builder::new()
+                .register(0xF, vf)
+                .register(1, v1), instruction);
+            assert_eq!(expected_vf, chip8.get_registers()[0xF], "{:04X}", instruction);
+        }
+    }
+
+    #[test]
+    fn random() {
+        let chip8 = run(Chip8Builder::new()
+            .rng(Box::new(ScriptedRandom::new(&[0xF5]))), 0xC30F);
+        assert_eq!(0x05, chip8.get_registers()[3]);
+    }
+
+    #[test]
+    fn skips() {
+        // (instruction, V1, V2, key 7 down, skipped)
+        let cases: [(u16, u8, u8, bool, bool); 14] = [
+            (0x3112, 0x12, 0x00, false, true),  // SE V1, nn
+            (0x3112, 0x13, 0x00, false, false),
+            (0x4112, 0x13, 0x00, false, true),  // SNE V1, nn
+            (0x4112, 0x12, 0x00, false, false),
+            (0x5120, 0x44, 0x44, false, true),  // SE V1, V2
+            (0x5120, 0x44, 0x45, false, false),
+            (0x9120, 0x44, 0x45, false, true),  // SNE V1, V2
+            (0x9120, 0x44, 0x44, false, false),
+            (0xE19E, 0x07, 0x00, true, true),   // SKP V1
+            (0xE19E, 0x07, 0x00, false, false),
+            (0xE19E, 0x17, 0x00, true, true),   // SKP uses the low nibble of V1
+            (0xE1A1, 0x07, 0x00, false, true),  // SKNP V1
+            (0xE1A1, 0x07, 0x00, true, false),
+            (0xE1A1, 0x06, 0x00, true, true),
+        ];
+
+        for &(instruction, v1, v2, key_down, skipped) in cases.iter() {
+            let chip8 = run(Chip8Builder::new()
+                .register(1, v1)
+                .register(2, v2)
+                .key(7, key_down), instruction);
+            assert_eq!(if skipped { PC + 4 } else { PC + 2 }, chip8.get_pc(),
+                "{:04X} with V1={:#04x}, V2={:#04x}, key down={}",
+                instruction, v1, v2, key_down);
+        }
+    }
+
+    #[test]
+    fn jumps_and_subroutines() {
+        assert_eq!(0x345, run(Chip8Builder::new(), 0x1345).get_pc());
+        assert_eq!(0x355, run(Chip8Builder::new().register(0, 0x10), 0xB345).get_pc());
+
+        let called = run(Chip8Builder::new().stack(&[0x400]), 0x2456);
+        assert_eq!(0x456, called.get_pc());
+        assert_eq!(2, called.get_sp());
+        assert_eq!(&[0x400, PC + 2], called.get_stack());
+
+        let returned = run(Chip8Builder::new().stack(&[0x400, 0x502]), 0x00EE);
+        assert_eq!(0x502, returned.get_pc());
+        assert_eq!(&[0x400], returned.get_stack());
+
+        // SYS calls to RCA 1802 code are ignored
+        let sys = run(Chip8Builder::new(), 0x0123);
+        assert_eq!(PC + 2, sys.get_pc());
+        assert_eq!(0, sys.get_sp());
+    }
+
+    #[test]
+    fn index_register() {
+        assert_eq!(0xABC, run(Chip8Builder::new(), 0xAABC).get_i());
+        assert_eq!(0x310, run(Chip8Builder::new().i(0x300).register(4, 0x10), 0xF41E).get_i());
+        assert_eq!(0x00F, run(Chip8Builder::new().i(0xFFF).register(4, 0x10), 0xF41E).get_i());
+        assert_eq!(0x50 + 5 * 0xA, run(Chip8Builder::new().register(4, 0xA), 0xF429).get_i());
+    }
+
+    #[test]
+    fn memory_transfers() {
+        let bcd = run(Chip8Builder::new().i(0x300).register(4, 254), 0xF433);
+        assert_eq!(&[2, 5, 4], &bcd.get_memory()[0x300..0x303]);
+
+        let store = run(Chip8Builder::new()
+            .i(0x300)
+            .register(0, 0x11)
+            .register(1, 0x22)
+            .register(2, 0x33)
+            .register(3, 0x44), 0xF255);
+        assert_eq!(&[0x11, 0x22, 0x33, 0x00], &store.get_memory()[0x300..0x304]);
+        assert_eq!(0x300, store.get_i());
+
+        let load = run(Chip8Builder::new()
+            .i(0x300)
+            .register(3, 0xEE)
+            .memory(0x300, &[0x11, 0x22, 0x33, 0x44]), 0xF265);
+        assert_eq!(&[0x11, 0x22, 0x33, 0xEE], &load.get_registers()[0..4]);
+        assert_eq!(0x300, load.get_i());
+    }
+
+    #[test]
+    fn timers() {
+        // Timers count down once at the end of every cycle
+        assert_eq!(0x1F, run(Chip8Builder::new().register(5, 0x20), 0xF515).get_delay_timer());
+        assert_eq!(0x1F, run(Chip8Builder::new().register(5, 0x20), 0xF518).get_sound_timer());
+        assert_eq!(0x20, run(Chip8Builder::new().delay_timer(0x20), 0xF507).get_registers()[5]);
+        assert_eq!(0, run(Chip8Builder::new(), 0xF507).get_delay_timer());
+    }
+
+    #[test]
+    fn sound_is_only_sent_when_it_changes() {
+        for &engine in &[Engine::Interpreter, Engine::Translated] {
+            // LD V0, 1; JP 0x200
+            let mut chip8 = Chip8Builder::new().engine(engine).sound_timer(3)
+                .memory(PC as usize, &[0x60, 0x01, 0x12, 0x00]).build();
+            let mut audio = || {
+                (0..10).for_each(|_| chip8.emulate_cycle());
+                chip8.get_commands().consume_all_outbound().into_iter()
+                    .filter(|command| matches!(command, Command::Audio(_)))
+                    .map(|command| format!("{:?}", command))
+                    .collect::<Vec<String>>()
+            };
+            assert_eq!(vec![format!("{:?}", Command::Audio(Play)), format!("{:?}", Command::Audio(Pause))], audio());
+            assert!(audio().is_empty());
+        }
+    }
+
+    #[test]
+    fn wait_for_key() {
+        let mut chip8 = run(Chip8Builder::new(), 0xF30A);
+        assert!(chip8.is_waiting_for_key());
+
+        chip8.get_commands().send_inbound(Command::Key(KeyDownUp(0xB, false)));
+        chip8.process_inbound_commands();
+        assert!(chip8.is_waiting_for_key());
+
+        chip8.get_commands().send_inbound(Command::Key(KeyDownUp(0xB, true)));
+        chip8.process_inbound_commands();
+        assert!(!chip8.is_waiting_for_key());
+        assert_eq!(0xB, chip8.get_registers()[3]);
+    }
+
+    fn lit_pixels(chip8: &Chip8) -> Vec<(usize, usize)> {
+        chip8.get_pixels().iter().enumerate()
+            .filter(|&(_, p)| *p)
+            .map(|(i, _)| (i % W, i / W))
+            .collect()
+    }
+
+    #[test]
+    fn draw_and_collision() {
+        // 1 row sprite 0b1000_0001 at (V1, V2)
+        let sprite = Chip8Builder::new().i(0x300).memory(0x300, &[0x81]);
+
+        let drawn = run(sprite.register(1, 4).register(2, 3), 0xD121);
+        assert_eq!(vec![(4, 3), (11, 3)], lit_pixels(&drawn));
+        assert_eq!(0, drawn.get_registers()[0xF]);
+
+        let erased = run(Chip8Builder::new()
+            .i(0x300)
+            .memory(0x300, &[0x81])
+            .register(1, 4)
+            .register(2, 3)
+            .pixel(4, 3, true)
+            .pixel(5, 3, true), 0xD121);
+        assert_eq!(vec![(5, 3), (11, 3)], lit_pixels(&erased));
+        assert_eq!(1, erased.get_registers()[0xF]);
+
+        let no_collision = run(Chip8Builder::new()
+            .i(0x300)
+            .memory(0x300, &[0x81])
+            .pixel(1, 0, true), 0xD121);
+        assert_eq!(0, no_collision.get_registers()[0xF]);
+
+        // Sprites wrap around the screen edges
+        let wrapped = run(Chip8Builder::new()
+            .i(0x300)
+            .memory(0x300, &[0x81, 0x81])
+            .register(1, 60)
+            .register(2, 31), 0xD122);
+        assert_eq!(vec![(3, 0), (60, 0), (3, 31), (60, 31)], lit_pixels(&wrapped));
+    }
+
+    #[test]
+    fn clear_screen() {
+        let chip8 = run(Chip8Builder::new().pixel(0, 0, true).pixel(63, 31, true), 0x00E0);
+        assert!(lit_pixels(&chip8).is_empty());
+    }
+
+    #[test]
+    fn quirks() {
+        for &engine in &[Engine::Interpreter, Engine::Translated] {
+            let quirky = |quirks| Chip8Builder::new().engine(engine).quirks(quirks);
+
+            let shifted = run(quirky(Quirks { shift_vy: true, ..Quirks::default() })
+                .register(2, 0x05), 0x8126);
+            assert_eq!(&[0x02, 0x05], &shifted.get_registers()[1..3]);
+            assert_eq!(0x01, shifted.get_registers()[0xF]);
+
+            let reset = run(quirky(Quirks { vf_reset: true, ..Quirks::default() })
+                .register(1, 0xF0)
+                .register(2, 0x0F)
+                .register(0xF, 0xAA), 0x8121);
+            assert_eq!(0xFF, reset.get_registers()[1]);
+            assert_eq!(0x00, reset.get_registers()[0xF]);
+
+            let load_store_i = Quirks { load_store_i: true, ..Quirks::default() };
+            assert_eq!(0x303, run(quirky(load_store_i).i(0x300), 0xF255).get_i());
+            assert_eq!(0x303, run(quirky(load_store_i).i(0x300), 0xF265).get_i());
+
+            let jumped = run(quirky(Quirks { jump_vx: true, ..Quirks::default() })
+                .register(0, 0x20)
+                .register(3, 0x10), 0xB345);
+            assert_eq!(0x355, jumped.get_pc());
+
+            // The sprite starts at (68 % 64, 31) and its second row is cut off
+            let clipped = run(quirky(Quirks { clip: true, ..Quirks::default() })
+                .i(0x300)
+                .memory(0x300, &[0x81, 0x81])
+                .register(1, 60)
+                .register(2, 31), 0xD122);
+            assert_eq!(vec![(60, 31)], lit_pixels(&clipped));
+            let wrapped_start = run(quirky(Quirks { clip: true, ..Quirks::default() })
+                .i(0x300)
+                .memory(0x300, &[0x81])
+                .register(1, 68), 0xD121);
+            assert_eq!(vec![(4, 0), (11, 0)], lit_pixels(&wrapped_start));
+        }
+    }
+
+    #[test]
+    fn undefined_opcode_is_skipped() {
+        let chip8 = run(Chip8Builder::new().register(1, 1).register(2, 2), 0x5121);
+        assert_eq!(PC + 2, chip8.get_pc());
+        assert_eq!(&[0, 1, 2], &chip8.get_registers()[0..3]);
+    }
+
+    #[test]
+    fn mismatched_operands_are_skipped() {
+        use opcode::{Opcode, Operation::ADD, OpcodeType::NNN};
+        let mut chip8 = Chip8Builder::new().register(1, 1).build();
+        chip8.execute_opcode(Opcode(ADD, NNN(0x123)));
+        assert_eq!(PC, chip8.get_pc());
+        assert_eq!(&[0, 1], &chip8.get_registers()[0..2]);
+    }
+
+    #[test]
+    fn addresses_wrap() {
+        assert_eq!(0x010, run(Chip8Builder::new().register(0, 0x20), 0xBFF0).get_pc());
+        assert_eq!(0x000, run(Chip8Builder::new().pc(0xFFE)
+            .memory(0xFFE, &[0x00, 0xE0]), 0x00E0).get_pc());
+
+        let bcd = run(Chip8Builder::new().i(0xFFF).register(0, 123), 0xF033);
+        assert_eq!(&[2, 3], &bcd.get_memory()[0..2]);
+        assert_eq!(1, bcd.get_memory()[0xFFF]);
+
+        let load = run(Chip8Builder::new().i(0xFFF).memory(0, &[0x22]).memory(0xFFF, &[0x11]), 0xF165);
+        assert_eq!(&[0x11, 0x22], &load.get_registers()[0..2]);
+
+        // Font sprites only exist for the low nibble
+        assert_eq!(0x50 + 5 * 0xF, run(Chip8Builder::new().register(0, 0xFF), 0xF029).get_i());
+    }
+
+    #[test]
+    fn stack_wraps() {
+        let underflow = run(Chip8Builder::new(), 0x00EE);
+        assert_eq!(0xF, underflow.get_sp());
+
+        let full: Vec<u16> = (1..16).map(|i| i * 0x10).collect();
+        let overflow = run(Chip8Builder::new().stack(&full), 0x2300);
+        assert_eq!(0, overflow.get_sp());
+        assert_eq!(0x300, overflow.get_pc());
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 15 return addresses")]
+    fn builder_rejects_a_sixteenth_return_address() {
+        Chip8Builder::new().stack(&[0x200; 16]);
+    }
+
+    // Calls the subroutine at 0x300 (LD V2, 0x55), patches it with LD I then a store, then calls it again
+    fn self_modifying(builder: Chip8Builder, patch: [u8; 4]) -> Chip8 {
+        let program = [
+            0x23, 0x00, // CALL 0x300
+            patch[0], patch[1], patch[2], patch[3],
+            0x23, 0x00, // CALL 0x300
+        ];
+        let mut chip8 = builder
+            .memory(ROM_ADDR, &program)
+            .memory(0x300, &[0x62, 0x55, 0x00, 0xEE])
+            .build();
+        (0..7).for_each(|_| chip8.emulate_cycle());
+        chip8
+    }
+
+    #[test]
+    fn written_code_is_decoded_again() {
+        // LD I, 0x300; LD [I], V1 rewrites the whole instruction
+        let store = self_modifying(
+            Chip8Builder::new().register(0, 0x62).register(1, 0x77),
+            [0xA3, 0x00, 0xF1, 0x55]);
+        assert_eq!(0x77, store.get_registers()[2]);
+
+        // LD I, 0x301; LD B, V4 with 255 rewrites the low byte, giving LD V2, 2
+        let bcd = self_modifying(
+            Chip8Builder::new().register(4, 0xFF),
+            [0xA3, 0x01, 0xF4, 0x33]);
+        assert_eq!(0x02, bcd.get_registers()[2]);
+
+        let translated = self_modifying(
+            Chip8Builder::new().engine(Engine::Translated).register(0, 0x62).register(1, 0x77),
+            [0xA3, 0x00, 0xF1, 0x55]);
+        assert_eq!(0x77, translated.get_registers()[2]);
+    }
+
+    #[test]
+    fn inbound_writes_invalidate_decoded_code() {
+        let mut chip8 = Chip8Builder::new()
+            .memory(ROM_ADDR, &[0x62, 0x55, 0x12, 0x00]) // LD V2, 0x55; JP 0x200
+            .build();
+        (0..2).for_each(|_| chip8.emulate_cycle());
+
+        chip8.get_commands().send_inbound(Command::Memory(MemoryCommand::SendByte(PC + 1, 0x66)));
+        chip8.process_inbound_commands();
+        chip8.emulate_cycle();
+        assert_eq!(0x66, chip8.get_registers()[2]);
+    }
+
+    #[test]
+    fn inbound_writes_invalidate_translated_blocks() {
+        // LD V2, 0x55; LD V3, 0x01; JP 0x200, patched mid block
+        let mut chip8 = Chip8Builder::new()
+            .engine(Engine::Translated)
+            .memory(ROM_ADDR, &[0x62, 0x55, 0x63, 0x01, 0x12, 0x00])
+            .build();
+        (0..4).for_each(|_| chip8.emulate_cycle());
+
+        chip8.get_commands().send_inbound(Command::Memory(MemoryCommand::SendByte(PC + 3, 0x02)));
+        chip8.process_inbound_commands();
+        chip8.emulate_cycle();
+        assert_eq!(0x02, chip8.get_registers()[3]);
+    }
+}
+
+/*
+Property tests: arbitrary ROMs and key presses must never panic the core, and
+single instructions must match an independent reference model of the CPU.
+*/
+mod property_test {
+    use proptest::prelude::*;
+    use proptest::collection::vec;
+    use proptest::array::uniform16;
+
+    use chip8::{Chip8, Chip8Builder, Engine};
+    use command::{Command, CommandEmulator, KeyCommand::KeyDownUp, MemoryCommand::SendByte};
+    use constants::{W, H, N, RAM_BYTES, ROM_ADDR, FONT_ADDR};
+    use emulator::Chip8Emulator;
+    use io::HeadlessIO;
+    use movie::Movie;
+    use rng::{SeededRandom, ScriptedRandom};
+
+    const FUZZ_FRAMES: u64 = 2000;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn arbitrary_rom_never_panics(
+            // Empty ROMs are refused by load_game, see rom_loader_test
+            rom in vec(any::<u8>(), 1..=RAM_BYTES - ROM_ADDR),
+            keys in vec((0..FUZZ_FRAMES, 0..0x10usize, any::<bool>()), 0..32),
+            seed in any::<u64>())
+        {
+            let mut movie = Movie::new("", seed);
+            let mut keys = keys;
+            keys.sort();
+            keys.into_iter().for_each(|(frame, key_i, is_down)|
+                movie.record(frame, KeyDownUp(key_i, is_down)));
+
+            let mut emulator = Chip8Emulator::new(
+                Box::new(HeadlessIO::new()),
+                Box::new(SeededRandom::new(seed)));
+            emulator.replay_movie(movie);
+            emulator.load_game(&rom).unwrap();
+            for _ in 0..FUZZ_FRAMES {
+                emulator.run_frame()
+            }
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        // Random pokes between cycles stand in for writes from outside the CPU
+        #[test]
+        fn translated_engine_matches_interpreter(
+            rom in vec(any::<u8>(), 0..=RAM_BYTES - ROM_ADDR),
+            keys in vec((0..FUZZ_FRAMES, 0..0x10usize, any::<bool>()), 0..32),
+            pokes in vec((0..FUZZ_FRAMES, 0..RAM_BYTES as u16, any::<u8>()), 0..32),
+            seed in any::<u64>())
+        {
+            let build = |engine| Chip8Builder::new()
+                .engine(engine)
+                .rng(Box::new(SeededRandom::new(seed)))
+                .memory(ROM_ADDR, &rom)
+                .build();
+            let (mut interpreted, mut translated) = (build(Engine::Interpreter), build(Engine::Translated));
+
+            for cycle in 0..FUZZ_FRAMES {
+                for chip8 in [&mut interpreted, &mut translated].iter_mut() {
+                    keys.iter().filter(|key| key.0 == cycle).for_each(|&(_, key_i, is_down)|
+                        chip8.get_commands().send_inbound(Command::Key(KeyDownUp(key_i, is_down))));
+                    pokes.iter().filter(|poke| poke.0 == cycle).for_each(|&(_, addr, value)|
+                        chip8.get_commands().send_inbound(Command::Memory(SendByte(addr, value))));
+                    chip8.process_inbound_commands();
+                    chip8.emulate_cycle();
+                }
+                prop_assert_eq!(Reference::from_chip8(&interpreted), Reference::from_chip8(&translated),
+                    "cycle {}", cycle);
+            }
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        // Whole translated blocks per dispatch, against the interpreter a cycle at a time
+        #[test]
+        fn bulk_runs_match_single_cycles(
+            rom in vec(any::<u8>(), 0..=RAM_BYTES - ROM_ADDR),
+            runs in vec((1..100u64, 0..0x10usize, any::<bool>()), 1..32),
+            seed in any::<u64>())
+        {
+            let build = |engine| Chip8Builder::new()
+                .engine(engine)
+                .rng(Box::new(SeededRandom::new(seed)))
+                .memory(ROM_ADDR, &rom)
+                .build();
+            let (mut single, mut bulk) = (build(Engine::Interpreter), build(Engine::Translated));
+
+            for (run, &(cycles, key_i, is_down)) in runs.iter().enumerate() {
+                for chip8 in [&mut single, &mut bulk].iter_mut() {
+                    chip8.get_commands().send_inbound(Command::Key(KeyDownUp(key_i, is_down)));
+                    chip8.process_inbound_commands();
+                }
+                (0..cycles).for_each(|_| single.emulate_cycle());
+                bulk.run_cycles(cycles);
+
+                prop_assert_eq!(Reference::from_chip8(&single), Reference::from_chip8(&bulk), "run {}", run);
+                prop_assert_eq!(format!("{:?}", single.get_commands().consume_all_outbound()),
+                    format!("{:?}", bulk.get_commands().consume_all_outbound()), "run {}", run);
+            }
+        }
+    }
+
+    // Straightforward model of one CPU cycle, written from the instruction set spec
+    #[derive(Debug, Clone, PartialEq)]
+    struct Reference {
+        v: [u8; 16],
+        i: u16,
+        pc: u16,
+        stack: Vec<u16>,
+        sp: u8,
+        dt: u8,
+        st: u8,
+        keys: [bool; 16],
+        key_wait: bool,
+        memory: Vec<u8>,
+        pixels: Vec<bool>
+    }
+
+    impl Reference {
+        fn read(&self, addr: u16) -> u8 {
+            self.memory[addr as usize % RAM_BYTES]
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            self.memory[addr as usize % RAM_BYTES] = value
+        }
+
+        fn step(&mut self, rnd: u8) {
+            if self.key_wait { return }
+
+            let op = (self.read(self.pc) as u16) << 8 | self.read(self.pc + 1) as u16;
+            self.pc = (self.pc + 2) % 0x1000;
+
+            let x = ((op >> 8) & 0xF) as usize;
+            let y = ((op >> 4) & 0xF) as usize;
+            let n = op & 0xF;
+            let nn = (op & 0xFF) as u8;
+            let nnn = op & 0xFFF;
+            let (vx, vy) = (self.v[x], self.v[y]);
+            let mut skip = false;
+
+            match (op >> 12, x, y, n) {
+                (0x0, 0x0, 0xE, 0x0) => self.pixels = vec![false; N],
+                (0x0, 0x0, 0xE, 0xE) => {
+                    self.pc = self.stack[self.sp as usize];
+                    self.sp = (self.sp + 15) % 16
+                },
+                (0x0, ..) => {},
+                (0x1, ..) => self.pc = nnn,
+                (0x2, ..) => {
+                    self.sp = (self.sp + 1) % 16;
+                    self.stack[self.sp as usize] = self.pc;
+                    self.pc = nnn
+                },
+                (0x3, ..) => skip = vx == nn,
+                (0x4, ..) => skip = vx != nn,
+                (0x5, _, _, 0x0) => skip = vx == vy,
+                (0x6, ..) => self.v[x] = nn,
+                (0x7, ..) => self.v[x] = vx.wrapping_add(nn),
+                (0x8, _, _, 0x0) => self.v[x] = vy,
+                (0x8, _, _, 0x1) => self.v[x] = vx | vy,
+                (0x8, _, _, 0x2) => self.v[x] = vx & vy,
+                (0x8, _, _, 0x3) => self.v[x] = vx ^ vy,
+                (0x8, _, _, 0x4) => {
+                    self.v[x] = vx.wrapping_add(vy);
+                    self.v[0xF] = if vx as u16 + vy as u16 > 0xFF { 1 } else { 0 }
+                },
+                (0x8, _, _, 0x5) => {
+                    self.v[x] = vx.wrapping_sub(vy);
+                    self.v[0xF] = if vx >= vy { 1 } else { 0 }
+                },
+                (0x8, _, _, 0x6) => {
+                    self.v[x] = vx / 2;
+                    self.v[0xF] = vx % 2
+                },
+                (0x8, _, _, 0x7) => {
+                    self.v[x] = vy.wrapping_sub(vx);
+                    self.v[0xF] = if vy >= vx { 1 } else { 0 }
+                },
+                (0x8, _, _, 0xE) => {
+                    self.v[x] = vx.wrapping_mul(2);
+                    self.v[0xF] = if vx >= 0x80 { 1 } else { 0 }
+                },
+                (0x9, _, _, 0x0) => skip = vx != vy,
+                (0xA, ..) => self.i = nnn,
+                (0xB, ..) => self.pc = (self.v[0] as u16 + nnn) % 0x1000,
+                (0xC, ..) => self.v[x] = rnd & nn,
+                (0xD, ..) => {
+                    self.v[0xF] = 0;
+                    for row in 0..n {
+                        let sprite = self.read(self.i + row);
+                        for col in 0..8 {
+                            if sprite & (0x80 >> col) != 0 {
+                                let px = (vx as usize + col) % W;
+                                let py = (vy as usize + row as usize) % H;
+                                if self.pixels[py * W + px] { self.v[0xF] = 1 }
+                                self.pixels[py * W + px] = !self.pixels[py * W + px]
+                            }
+                        }
+                    }
+                },
+                (0xE, _, 0x9, 0xE) => skip = self.keys[(vx % 16) as usize],
+                (0xE, _, 0xA, 0x1) => skip = !self.keys[(vx % 16) as usize],
+                (0xF, _, 0x0, 0x7) => self.v[x] = self.dt,
+                (0xF, _, 0x0, 0xA) => self.key_wait = true,
+                (0xF, _, 0x1, 0x5) => self.dt = vx,
+                (0xF, _, 0x1, 0x8) => self.st = vx,
+                (0xF, _, 0x1, 0xE) => self.i = (self.i + vx as u16) % 0x1000,
+                (0xF, _, 0x2, 0x9) => self.i = FONT_ADDR as u16 + 5 * (vx % 16) as u16,
+                (0xF, _, 0x3, 0x3) => {
+                    let i = self.i;
+                    self.write(i, vx / 100);
+                    self.write(i + 1, vx / 10 % 10);
+                    self.write(i + 2, vx % 10)
+                },
+                (0xF, _, 0x5, 0x5) => for r in 0..=x {
+                    let (i, value) = (self.i + r as u16, self.v[r]);
+                    self.write(i, value)
+                },
+                (0xF, _, 0x6, 0x5) => for r in 0..=x {
+                    self.v[r] = self.read(self.i + r as u16)
+                },
+                _ => {}
+            }
+
+            if skip { self.pc = (self.pc + 2) % 0x1000 }
+            self.dt = self.dt.saturating_sub(1);
+            self.st = self.st.saturating_sub(1);
+        }
+
+        fn from_chip8(chip8: &Chip8) -> Self {
+            let mut stack = vec![0; 16];
+            stack[1..=chip8.get_sp() as usize].copy_from_slice(chip8.get_stack());
+            let mut v = [0; 16];
+            v.copy_from_slice(chip8.get_registers());
+
+            Reference {
+                v,
+                i: chip8.get_i(),
+                pc: chip8.get_pc(),
+                stack,
+                sp: chip8.get_sp(),
+                dt: chip8.get_delay_timer(),
+                st: chip8.get_sound_timer(),
+                keys: [false; 16],
+                key_wait: chip8.is_waiting_for_key(),
+                memory: chip8.get_memory().to_vec(),
+                pixels: chip8.get_pixels().to_vec()
+            }
+        }
+
+        fn to_chip8(&self, rnd: u8) -> Chip8 {
+            let builder = (0..16).fold(Chip8Builder::new(), |b, r| b
+                .register(r, self.v[r])
+                .key(r, self.keys[r]));
+            builder
+                .i(self.i)
+                .pc(self.pc)
+                .stack(&self.stack[1..=self.sp as usize])
+                .delay_timer(self.dt)
+                .sound_timer(self.st)
+                .memory(0, &self.memory)
+                .pixels(&self.pixels)
+                .rng(Box::new(ScriptedRandom::new(&[rnd])))
+                .build()
+        }
+    }
+
+    fn cpu_state() -> impl Strategy<Value = Reference> {
+        (uniform16(any::<u8>()), 0..0x1000u16, 0..0x1000u16, vec(0..0x1000u16, 0..16),
+            any::<u8>(), any::<u8>(), uniform16(any::<bool>()),
+            vec(any::<u8>(), RAM_BYTES), vec(any::<bool>(), N))
+            .prop_map(|(v, i, pc, stack, dt, st, keys, memory, pixels)| {
+                let sp = stack.len() as u8;
+                let mut full_stack = vec![0; 16];
+                full_stack[1..=stack.len()].copy_from_slice(&stack);
+                Reference {
+                    v, i, pc,
+                    stack: full_stack,
+                    sp, dt, st, keys,
+                    key_wait: false,
+                    memory,
+                    pixels
+                }
+            })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(1000))]
+
+        #[test]
+        fn matches_reference_model(
+            state in cpu_state(),
+            instruction in any::<u16>(),
+            rnd in any::<u8>())
+        {
+            let mut expected = state;
+            let pc = expected.pc;
+            expected.write(pc, (instruction >> 8) as u8);
+            expected.write(pc + 1, instruction as u8);
+
+            let mut chip8 = expected.to_chip8(rnd);
+            expected.step(rnd);
+            chip8.emulate_cycle();
+
+            // Only the active frames of the stack are observable
+            let sp = expected.sp as usize;
+            (0..16).filter(|&k| k == 0 || k > sp).for_each(|k| expected.stack[k] = 0);
+
+            let mut actual = Reference::from_chip8(&chip8);
+            actual.keys = expected.keys;
+            prop_assert_eq!(expected, actual, "instruction {:04X}", instruction);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::rc::Rc;
+
+use opcode::{Opcode, Operation::*, OpcodeType::*, OpcodeDisassembler};
+
+// Longest run of instructions translated into one block
+const MAX_BLOCK_STEPS: usize = 32;
+
+// Translated straight line code starting at an address. Only the last step can
+// jump, skip, wait for a key or write to memory.
+pub struct Block<T> {
+    pub start: usize,
+    pub steps: Vec<T>
+}
+
+impl<T> Block<T> {
+    // Instructions are 2 bytes, so the last step reaches one byte past its start
+    fn covers(&self, addr: usize) -> bool {
+        addr >= self.start && addr < self.start + 2 * self.steps.len()
+    }
+}
+
+pub fn ends_block(opcode: Opcode) -> bool {
+    matches!(opcode,
+        Opcode(JP, _) | Opcode(CALL, _) | Opcode(RET, _)
+        | Opcode(SE, _) | Opcode(SNE, _) | Opcode(SKP, _) | Opcode(SKNP, _)
+        | Opcode(LD, X_K(_)) | Opcode(LD, B_X(_)) | Opcode(LD, RI_X(_)))
+}
+
+// Blocks by start address, translated from memory the first time they run.
+// Writes to memory must invalidate the addresses they touch.
+pub struct BlockCache<T> {
+    blocks: Vec<Option<Rc<Block<T>>>>
+}
+
+impl<T> BlockCache<T> {
+    pub fn new(size: usize) -> Self {
+        BlockCache {
+            blocks: (0..size).map(|_| None).collect()
+        }
+    }
+
+    pub fn get_or_translate<F: Fn(Opcode) -> T>(&mut self, memory: &[u8], start: usize, translate: F)
+        -> Rc<Block<T>>
+    {
+        if let Some(ref block) = self.blocks[start] {
+            return Rc::clone(block)
+        }
+
+        let size = memory.len();
+        let mut steps = Vec::new();
+        let mut addr = start;
+        loop {
+            let instruction = (memory[addr] as u16) << 8 | memory[(addr + 1) % size] as u16;
+            let opcode = OpcodeDisassembler::disassemble(instruction);
+            steps.push(translate(opcode));
+            addr += 2;
+
+            // Blocks stop before wrapping around memory, so they cover one range of bytes
+            if ends_block(opcode) || steps.len() == MAX_BLOCK_STEPS || addr + 1 >= size { break }
+        }
+
+        let block = Rc::new(Block { start, steps });
+        self.blocks[start] = Some(Rc::clone(&block));
+        block
+    }
+
+    pub fn invalidate(&mut self, addr: usize) {
+        let addr = addr % self.blocks.len();
+        self.invalidate_covering(addr);
+
+        // The instruction at the last address reads its second byte from address 0
+        if addr == 0 { self.invalidate_covering(self.blocks.len()) }
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.iter_mut().for_each(|block| *block = None)
+    }
+
+    fn invalidate_covering(&mut self, addr: usize) {
+        let first = addr.saturating_sub(2 * MAX_BLOCK_STEPS);
+        let last = addr.min(self.blocks.len() - 1);
+        for start in first..=last {
+            let is_covered = self.blocks[start].as_ref().is_some_and(|block| block.covers(addr));
+            if is_covered { self.blocks[start] = None }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+/*
+Conformance tests, run headlessly through the library like a front end would.
+A ROM runs for a number of frames, one CPU cycle each, then the framebuffer
+is checked:
+- Test suites check the opcodes, flags, quirks and keypad themselves, then
+  their pass screens are compared with tests/golden/.
+- Spec checks are small programs whose screen follows from the CHIP-8 spec,
+  so their expected pixels are worked out here rather than stored.
+- Bundled games are compared with tests/golden/ too, but those images are
+  what the emulator drew when they were made. They catch changes, not bugs.
+UPDATE_GOLDEN=1 writes the golden images instead of comparing them.
+*/
+
+extern crate chip8_emulator;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use chip8_emulator::chip8::{Engine, Quirks};
+use chip8_emulator::command::KeyCommand::KeyDownUp;
+use chip8_emulator::constants::{W, H, N, FONT_SPRITES, ROM_ADDR};
+use chip8_emulator::emulator::Chip8Emulator;
+use chip8_emulator::io::HeadlessIO;
+use chip8_emulator::library::Quirk;
+use chip8_emulator::movie::Movie;
+use chip8_emulator::rng::SeededRandom;
+
+const RNG_SEED: u64 = 0xC8;
+const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::Translated];
+
+// Where the suites read what they're run with, as there's no one to ask
+const SUITE_MODE_ADDR: u16 = 0x1FF;
+
+struct Run {
+    engine: Engine,
+    quirks: Quirks,
+    // Written to SUITE_MODE_ADDR before the first frame
+    mode: Option<u8>,
+    keys: Option<Movie>
+}
+
+impl Run {
+    fn on(engine: Engine) -> Self {
+        Run { engine, quirks: Quirks::default(), mode: None, keys: None }
+    }
+
+    fn emulator(self, rom_bytes: &[u8]) -> Chip8Emulator {
+        let mut emulator = Chip8Emulator::new(
+            Box::new(HeadlessIO::new()),
+            Box::new(SeededRandom::new(RNG_SEED)));
+        emulator.set_engine(self.engine);
+        emulator.set_quirks(self.quirks);
+        if let Some(movie) = self.keys { emulator.replay_movie(movie) }
+        emulator.load_game(rom_bytes).unwrap();
+        // Frozen bytes outlast the ROM being copied to the CPU on the first frame
+        if let Some(mode) = self.mode { emulator.get_chip8_mut().freeze(SUITE_MODE_ADDR, Some(mode)) }
+        emulator
+    }
+
+    fn pixels(self, rom_bytes: &[u8], frames: u64) -> Vec<bool> {
+        let mut emulator = self.emulator(rom_bytes);
+        (0..frames).for_each(|_| emulator.run_frame());
+        emulator.get_pixels().to_vec()
+    }
+}
+
+fn repo_path(relative: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+fn read_rom(path: PathBuf) -> Vec<u8> {
+    fs::read(&path).unwrap_or_else(|e| panic!("ROM {:?} should be readable: {}", path, e))
+}
+
+fn render(pixels: &[bool]) -> String {
+    assert_eq!(N, pixels.len());
+    pixels.chunks(W)
+        .map(|row| row.iter().map(|p| if *p { '#' } else { '.' }).collect::<String>() + "\n")
+        .collect()
+}
+
+fn assert_golden(golden_name: &str, pixels: &[bool]) {
+    let golden_path = repo_path("tests/golden").join(format!("{}.txt", golden_name));
+    let image = render(pixels);
+
+    if env::var("UPDATE_GOLDEN").is_ok() {
+        fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+        fs::write(&golden_path, image).unwrap();
+    } else {
+        let golden = fs::read_to_string(&golden_path)
+            .expect("Golden image missing, run with UPDATE_GOLDEN=1 to create it");
+        assert!(golden == image,
+            "Framebuffer differs from {:?}\nexpected:\n{}\nactual:\n{}",
+            golden_path, golden, image);
+    }
+}
+
+fn assert_screen(expected: &[bool], actual: &[bool]) {
+    assert!(expected == actual, "expected:\n{}\nactual:\n{}", render(expected), render(actual));
+}
+
+/*
+Self-checking test ROMs, kept in tests/suites/ as hex listings (see the README
+there). Each stores 0xFF at SUITE_RESULT_ADDR once all its checks have passed,
+or the number of the check that failed, then draws a pass or fail screen.
+*/
+struct Suite {
+    name: &'static str,
+    frames: u64,
+    // Quirks to run the suite with, a run each, and written to SUITE_MODE_ADDR
+    // as a bit each in SUITE_QUIRK_BITS order. Without any it runs once with none.
+    quirk_sets: &'static [&'static [Quirk]],
+    keys: &'static [(u64, usize, bool)]
+}
+
+const SUITES: [Suite; 4] = [
+    Suite { name: "opcodes", frames: 2_000, quirk_sets: &[], keys: &[] },
+    Suite { name: "flags", frames: 2_000, quirk_sets: &[], keys: &[] },
+    Suite { name: "quirks", frames: 2_000, quirk_sets: &[
+        &[],
+        &[Quirk::ShiftVy, Quirk::LoadStoreI, Quirk::VfReset, Quirk::Clip],
+        &[Quirk::JumpVx],
+        &[Quirk::ShiftVy, Quirk::LoadStoreI, Quirk::VfReset, Quirk::Clip, Quirk::JumpVx]
+    ], keys: &[] },
+    Suite { name: "keypad", frames: 2_000, quirk_sets: &[], keys: &[(1_000, 0xA, true), (1_050, 0xA, false)] }
+];
+
+const SUITE_QUIRK_BITS: [Quirk; 5] = [Quirk::ShiftVy, Quirk::LoadStoreI, Quirk::VfReset, Quirk::Clip, Quirk::JumpVx];
+const SUITE_RESULT_ADDR: usize = 0x1FE;
+const SUITE_PASSED: u8 = 0xFF;
+
+// Listing lines are "<address> <hex bytes> <assembly>", lines starting with # are comments
+fn read_listing(path: PathBuf) -> Vec<u8> {
+    let listing = fs::read_to_string(&path).unwrap_or_else(|e| panic!("Listing {:?} should be readable: {}", path, e));
+    let mut rom_bytes = Vec::new();
+    for (line_i, line) in listing.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue }
+        let mut fields = line.split_whitespace();
+        let addr = fields.next().and_then(|addr| usize::from_str_radix(addr, 16).ok());
+        assert_eq!(Some(ROM_ADDR + rom_bytes.len()), addr, "{:?} line {}: address out of order", path, line_i + 1);
+        let hex = fields.next().filter(|hex| hex.len() % 2 == 0)
+            .unwrap_or_else(|| panic!("{:?} line {}: bytes should be pairs of hex digits", path, line_i + 1));
+        for i in (0..hex.len()).step_by(2) {
+            let byte = u8::from_str_radix(&hex[i..i + 2], 16)
+                .unwrap_or_else(|e| panic!("{:?} line {}: {}", path, line_i + 1, e));
+            rom_bytes.push(byte)
+        }
+    }
+    rom_bytes
+}
+
+#[test]
+fn test_suites() {
+    for suite in &SUITES {
+        let mut keys = Movie::new("", RNG_SEED);
+        suite.keys.iter().for_each(|&(frame, key_i, is_down)| keys.record(frame, KeyDownUp(key_i, is_down)));
+        let rom_bytes = read_listing(repo_path("tests/suites").join(format!("{}.lst", suite.name)));
+        let runs: Vec<(Quirks, Option<u8>)> = match suite.quirk_sets {
+            [] => vec![(Quirks::default(), None)],
+            quirk_sets => quirk_sets.iter()
+                .map(|quirks| {
+                    let mode = SUITE_QUIRK_BITS.iter().enumerate()
+                        .filter(|(_, quirk)| quirks.contains(quirk))
+                        .map(|(bit, _)| 1 << bit)
+                        .sum();
+                    (Quirks::new(quirks), Some(mode))
+                })
+                .collect()
+        };
+
+        for &engine in &ENGINES {
+            for &(quirks, mode) in &runs {
+                let run = Run { quirks, mode, keys: Some(keys.clone()), ..Run::on(engine) };
+                let mut emulator = run.emulator(&rom_bytes);
+                (0..suite.frames).for_each(|_| emulator.run_frame());
+                match emulator.get_chip8().get_memory()[SUITE_RESULT_ADDR] {
+                    SUITE_PASSED => {},
+                    0 => panic!("{} suite didn't finish on {:?} with {:?}", suite.name, engine, quirks),
+                    check => panic!("{} suite failed check {} on {:?} with {:?}", suite.name, check, engine, quirks)
+                }
+                assert_golden(&format!("suite-{}", suite.name), emulator.get_pixels())
+            }
+        }
+    }
+}
+
+// Lights the sprite rows, a byte each, with the top left corner at (x, y)
+fn draw_expected(pixels: &mut [bool], x: usize, y: usize, rows: &[u8], clip: bool) {
+    for (row_i, row) in rows.iter().enumerate() {
+        for bit in 0..8 {
+            let (px, py) = (x + bit, y + row_i);
+            if clip && (px >= W || py >= H) { continue }
+            if row >> (7 - bit) & 1 == 1 { pixels[(py % H) * W + px % W] ^= true }
+        }
+    }
+}
+
+#[test]
+fn font_sprites() {
+    for digit in 0..0x10u8 {
+        // LD V0, digit; LD F, V0; LD V1, 3; DRW V1, V1, 5
+        let rom = [0x60, digit, 0xF0, 0x29, 0x61, 0x03, 0xD1, 0x15];
+        let mut expected = vec![false; N];
+        let sprite = &FONT_SPRITES[digit as usize * 5..][..5];
+        draw_expected(&mut expected, 3, 3, sprite, false);
+        for &engine in &ENGINES {
+            assert_screen(&expected, &Run::on(engine).pixels(&rom, 4))
+        }
+    }
+}
+
+#[test]
+fn sprites_wrap_or_clip() {
+    // LD I, 0x20a; LD V0, 60; LD V1, 29; DRW V0, V1, 4; JP 0x208; the sprite
+    let rom = [0xA2, 0x0A, 0x60, 60, 0x61, 29, 0xD0, 0x14, 0x12, 0x08, 0xFF, 0x81, 0x81, 0xFF];
+    for &clip in &[false, true] {
+        let mut expected = vec![false; N];
+        draw_expected(&mut expected, 60, 29, &rom[10..], clip);
+        for &engine in &ENGINES {
+            let run = Run { quirks: Quirks { clip, ..Quirks::default() }, ..Run::on(engine) };
+            assert_screen(&expected, &run.pixels(&rom, 10))
+        }
+    }
+
+    // The start position wraps even when clipping, (70, 40) is drawn at (6, 8)
+    let rom = [0xA2, 0x0A, 0x60, 70, 0x61, 40, 0xD0, 0x14, 0x12, 0x08, 0xFF, 0x81, 0x81, 0xFF];
+    let mut expected = vec![false; N];
+    draw_expected(&mut expected, 6, 8, &rom[10..], true);
+    let run = Run { quirks: Quirks { clip: true, ..Quirks::default() }, ..Run::on(Engine::Interpreter) };
+    assert_screen(&expected, &run.pixels(&rom, 10));
+}
+
+#[test]
+fn collisions_erase_and_set_vf() {
+    // LD V0, 0; LD F, V0; DRW V0, V0, 5; DRW V0, V0, 5; JP 0x208
+    let rom = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x08];
+    for &engine in &ENGINES {
+        let mut emulator = Run::on(engine).emulator(&rom);
+        (0..3).for_each(|_| emulator.run_frame());
+        assert_eq!(0, emulator.get_chip8().get_registers()[0xF]);
+        emulator.run_frame();
+        assert_eq!(1, emulator.get_chip8().get_registers()[0xF]);
+        assert!(emulator.get_pixels().iter().all(|p| !*p));
+    }
+}
+
+#[test]
+fn clear_screen() {
+    // LD V0, 0; LD F, V0; DRW V0, V0, 5; CLS; JP 0x208
+    let rom = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x00, 0xE0, 0x12, 0x08];
+    assert!(Run::on(Engine::Interpreter).pixels(&rom, 3).iter().any(|p| *p));
+    assert!(Run::on(Engine::Interpreter).pixels(&rom, 10).iter().all(|p| !*p));
+}
+
+#[test]
+fn keypad_wait() {
+    // LD V1, K; LD F, V1; DRW V0, V0, 5; JP 0x206
+    let rom = [0xF1, 0x0A, 0xF1, 0x29, 0xD0, 0x05, 0x12, 0x06];
+    let mut keys = Movie::new("", RNG_SEED);
+    keys.record(20, KeyDownUp(0xA, true));
+    keys.record(25, KeyDownUp(0xA, false));
+
+    let run = |frames| Run { keys: Some(keys.clone()), ..Run::on(Engine::Interpreter) }.pixels(&rom, frames);
+    assert!(run(20).iter().all(|p| !*p));
+    let mut expected = vec![false; N];
+    draw_expected(&mut expected, 0, 0, &FONT_SPRITES[0xA * 5..][..5], false);
+    assert_screen(&expected, &run(40));
+    assert_golden("keypad_wait", &run(40));
+}
+
+#[test]
+fn seeded_runs_match() {
+    let rom_bytes = read_rom(repo_path("ROMs/BLITZ"));
+    assert_eq!(Run::on(Engine::Interpreter).pixels(&rom_bytes, 2000),
+        Run::on(Engine::Interpreter).pixels(&rom_bytes, 2000));
+}
+
+// Bundled ROMs and the number of frames to run them for before comparing
+const GOLDEN_ROMS: [(&str, u64); 23] = [
+    ("15PUZZLE", 5000), ("BLINKY", 5000), ("BLITZ", 5000), ("BRIX", 5000),
+    ("CONNECT4", 5000), ("GUESS", 5000), ("HIDDEN", 5000), ("INVADERS", 5000),
+    ("KALEID", 5000), ("MAZE", 5000), ("MERLIN", 5000), ("MISSILE", 5000),
+    ("PONG", 5000), ("PONG2", 5000), ("PUZZLE", 5000), ("SYZYGY", 5000),
+    ("TANK", 5000), ("TETRIS", 5000), ("TICTAC", 5000), ("UFO", 5000),
+    ("VBRIX", 5000), ("VERS", 5000), ("WIPEOFF", 5000),
+];
+
+#[test]
+fn bundled_roms() {
+    for &(rom_name, frames) in &GOLDEN_ROMS {
+        let rom_bytes = read_rom(repo_path("ROMs").join(rom_name));
+        for &engine in &ENGINES {
+            assert_golden(rom_name, &Run::on(engine).pixels(&rom_bytes, frames))
+        }
+    }
+}